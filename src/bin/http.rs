@@ -0,0 +1,130 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use anyhow::Context;
+use clap::Parser;
+use dotenv::dotenv;
+use tokio_util::sync::CancellationToken;
+
+#[path = "../tools/mod.rs"]
+mod tools;
+#[path = "../transport/auth.rs"]
+mod auth;
+#[path = "../transport/streamable.rs"]
+mod streamable;
+use tools::cli::{CommonArgs, ListenArgs};
+use tools::config::{self, protocol_tool_from_env};
+use tools::server;
+
+/// Path serving the MCP endpoint.
+const MCP_PATH: &str = "/mcp";
+
+/// Seconds without a request before a session is closed.
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 30 * 60;
+
+/// Binds the Unix socket at `path`, replacing the socket a previous run left behind.
+/// `mode` restricts which local users may connect.
+#[cfg(unix)]
+fn bind_unix(path: &Path, mode: Option<u32>) -> anyhow::Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+
+    if let Ok(metadata) = std::fs::symlink_metadata(path) {
+        if !metadata.file_type().is_socket() {
+            anyhow::bail!("UDS_PATH {} exists and isn't a socket", path.display());
+        }
+        std::fs::remove_file(path).with_context(|| format!("Failed to remove stale socket {}", path.display()))?;
+    }
+
+    let listener = tokio::net::UnixListener::bind(path)
+        .with_context(|| format!("Failed to bind socket {}", path.display()))?;
+    if let Some(mode) = mode {
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(mode))
+            .with_context(|| format!("Failed to set the mode of socket {}", path.display()))?;
+    }
+    Ok(listener)
+}
+
+#[cfg(not(unix))]
+fn bind_unix(_path: &Path, _mode: Option<u32>) -> anyhow::Result<tokio::net::TcpListener> {
+    anyhow::bail!("UDS_PATH is only supported on unix")
+}
+
+/// Serves tx3 protocols as MCP tools over streamable HTTP.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+    #[command(flatten)]
+    listen: ListenArgs,
+}
+
+#[tokio::main]
+async fn main() -> anyhow::Result<()> {
+    dotenv().ok();
+    let cli = Cli::parse();
+    config::set_overrides(cli.common.overrides().into_iter().chain(cli.listen.overrides()));
+    if let Some(path) = cli.common.config() {
+        config::load_file(path)?;
+    }
+    // A Unix socket replaces the TCP listener.
+    let listen: &[&'static str] = match config::var("UDS_PATH") {
+        Some(_) => &[],
+        None => &["ADDRESS", "PORT"],
+    };
+    config::validate(listen)?;
+
+    server::init_logging(cli.common.check())?;
+    config::log_effective();
+
+    let tool = protocol_tool_from_env()?;
+    let uds_path = config::optional::<PathBuf>("UDS_PATH")?;
+    let auth_tokens = auth::tokens_from_env()?;
+    let idle_timeout = match config::optional::<u64>("HTTP_IDLE_TIMEOUT_SECS")?.unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+
+    if cli.common.check() {
+        return server::check(&tool, cli.common.format()).await;
+    }
+
+    server::check_trp(&tool).await?;
+
+    let ct = CancellationToken::new();
+    // Probes skip authentication, the auth layer only wraps the MCP endpoint.
+    let router = auth::require_tokens(streamable::router(tool.clone(), MCP_PATH, idle_timeout, ct.child_token()), auth_tokens)
+        .merge(server::health_router(tool.clone()));
+    let accepting = ct.child_token();
+    let serving = match &uds_path {
+        Some(path) => {
+            let mode = config::optional::<String>("UDS_MODE")?
+                .map(|mode| u32::from_str_radix(&mode, 8).with_context(|| format!("UDS_MODE must be an octal mode, got {}", mode)))
+                .transpose()?;
+            let listener = bind_unix(path, mode)?;
+            tracing::info!("Serving MCP over streamable HTTP on unix:{}{}", path.display(), MCP_PATH);
+            server::serve(listener, router, accepting.clone(), "Streamable HTTP")
+        }
+        None => {
+            let address = config::required("ADDRESS")?;
+            let port = config::required("PORT")?;
+            let bind_address: SocketAddr = format!("{}:{}", address, port).parse()?;
+            let listener = tokio::net::TcpListener::bind(bind_address).await?;
+            tracing::info!("Serving MCP over streamable HTTP on http://{}{}", bind_address, MCP_PATH);
+            server::serve(listener, router, accepting.clone(), "Streamable HTTP")
+        }
+    };
+
+    server::spawn_background_tasks(&tool, &ct).await?;
+
+    server::wait_for_shutdown(&tool, Some(&accepting)).await?;
+    ct.cancel();
+    serving.await?;
+
+    if let Some(path) = &uds_path {
+        if let Err(err) = std::fs::remove_file(path) {
+            tracing::warn!("Failed to remove socket {}: {}", path.display(), err);
+        }
+    }
+    Ok(())
+}
@@ -1,41 +1,379 @@
-use std::env;
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+use anyhow::Context;
+use axum::Router;
+use axum::body::{Body, Bytes};
+use axum::extract::{Request, State};
+use axum::middleware::{self, Next};
+use axum::http::{HeaderName, HeaderValue, Method, header};
+use axum::response::Response;
+use clap::Parser;
 use dotenv::dotenv;
-use rmcp::transport::sse_server::SseServer;
-use tracing_subscriber::{
-    layer::SubscriberExt,
-    util::SubscriberInitExt,
-    {self},
-};
+use futures::{StreamExt, future, stream};
+use rmcp::ServiceExt as _;
+use rmcp::transport::sse_server::{SseServer, SseServerConfig};
+use serde::Deserialize;
+use tokio_util::sync::CancellationToken;
+use tower::ServiceExt;
+use tower_http::cors::{AllowOrigin, CorsLayer};
+use tracing::Instrument;
 
 #[path = "../tools/mod.rs"]
 mod tools;
+#[path = "../transport/auth.rs"]
+mod auth;
+#[path = "../transport/tls.rs"]
+mod tls;
+use tls::ReloadingAcceptor;
+use tools::cli::{CommonArgs, ListenArgs};
+use tools::config::{self, protocol_tool_from_env};
 use tools::protocol::ProtocolTool;
+use tools::secret::{self, Secret};
+use tools::server;
+
+/// Seconds between keep-alive comments on quiet SSE streams.
+const DEFAULT_KEEP_ALIVE_SECS: u64 = 15;
+
+/// Seconds without a request before a session is closed.
+const DEFAULT_IDLE_TIMEOUT_SECS: u64 = 30 * 60;
+
+/// How often sessions are checked for idleness.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A team allowed to use the server, with the TRP credentials its resolves use.
+#[derive(Deserialize)]
+struct ClientConfig {
+    token: String,
+    trp_key: Option<String>,
+    trp_url: Option<String>,
+}
+
+/// Reads `SSE_CLIENTS`, a JSON object mapping a client name to its bearer token and
+/// TRP credentials. Without it the server accepts every connection.
+fn clients_from_env() -> anyhow::Result<HashMap<String, ClientConfig>> {
+    match config::var("SSE_CLIENTS") {
+        Some(value) => serde_json::from_str(&value)
+            .context("SSE_CLIENTS must be a JSON object of {token, trp_key, trp_url} entries"),
+        None => Ok(HashMap::new()),
+    }
+}
+
+/// Reads `CORS_ALLOWED_ORIGINS`, exact origins or `*`. Without it no CORS headers are
+/// sent and browsers can't connect from other origins.
+fn cors_from_env() -> anyhow::Result<Option<CorsLayer>> {
+    cors_layer(&config::list("CORS_ALLOWED_ORIGINS"))
+}
+
+fn cors_layer(origins: &[String]) -> anyhow::Result<Option<CorsLayer>> {
+    if origins.is_empty() {
+        return Ok(None);
+    }
+
+    let allow_origin = if origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::any()
+    } else {
+        let origins = origins.iter()
+            .map(|origin| origin.parse::<HeaderValue>().with_context(|| format!("Invalid origin in CORS_ALLOWED_ORIGINS: {}", origin)))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        AllowOrigin::list(origins)
+    };
+
+    // EventSource reconnects send Last-Event-ID; POSTs to the message endpoint carry
+    // JSON and, with AUTH_TOKENS or SSE_CLIENTS, the bearer token.
+    Ok(Some(CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([header::AUTHORIZATION, header::CONTENT_TYPE, header::ACCEPT, HeaderName::from_static("last-event-id")])
+        .max_age(Duration::from_secs(3600))))
+}
+
+/// Each client gets its own SSE server, so sessions opened with its token only ever
+/// see its TRP credentials. Requests are routed by bearer token.
+struct AuthenticatedClient {
+    token: String,
+    name: String,
+    router: Router,
+}
+
+async fn route_by_token(
+    State(clients): State<Arc<Vec<AuthenticatedClient>>>,
+    request: Request,
+) -> Response {
+    let index = auth::bearer_token(&request)
+        .and_then(|token| auth::find_token(clients.iter().map(|client| client.token.as_str()), token));
+
+    let Some(client) = index.map(|index| &clients[index]) else {
+        return auth::unauthorized();
+    };
+
+    let span = tracing::info_span!("sse", client = %client.name, path = %request.uri().path());
+    match client.router.clone().oneshot(request).instrument(span).await {
+        Ok(response) => response,
+        Err(err) => match err {},
+    }
+}
+
+/// Runs a session per SSE connection. Sessions without a request for `idle_timeout`
+/// are closed, ending their stream; the client reconnects with a fresh session.
+fn serve_sessions(mut sse_server: SseServer, tool: ProtocolTool, idle_timeout: Option<Duration>, ct: CancellationToken) {
+    tokio::spawn(async move {
+        while let Some(transport) = sse_server.next_transport().await {
+            let session = tool.for_session();
+            let session_ct = ct.child_token();
+            tokio::spawn(async move {
+                let service = match session.clone().serve_with_ct(transport, session_ct.clone()).await {
+                    Ok(service) => service,
+                    Err(err) => {
+                        tracing::debug!("SSE session failed to initialize: {}", err);
+                        return;
+                    }
+                };
+                tracing::info!("Session opened, {} active", session.active_sessions());
+
+                let watchdog = idle_timeout.map(|timeout| {
+                    let session = session.clone();
+                    let session_ct = session_ct.clone();
+                    tokio::spawn(async move {
+                        loop {
+                            tokio::time::sleep(IDLE_CHECK_INTERVAL.min(timeout)).await;
+                            if session.idle_for() >= timeout {
+                                tracing::info!("Closing session idle for {:?}", session.idle_for());
+                                session_ct.cancel();
+                                break;
+                            }
+                        }
+                    })
+                });
+
+                if let Err(err) = service.waiting().await {
+                    tracing::debug!("SSE session ended with an error: {}", err);
+                }
+                if let Some(watchdog) = watchdog {
+                    watchdog.abort();
+                }
+                session.close_session();
+            });
+        }
+    });
+}
+
+/// Interleaves SSE comment frames into event streams, so proxies that drop quiet
+/// connections keep them open. Other responses pass through untouched.
+async fn keep_alive(State(interval): State<Duration>, request: Request, next: Next) -> Response {
+    let response = next.run(request).await;
+    let is_stream = response.headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("text/event-stream"));
+    if !is_stream {
+        return response;
+    }
+
+    let (parts, body) = response.into_parts();
+    // Events end with a None, which ends the merged stream too.
+    let events = body.into_data_stream().map(Some).chain(stream::once(future::ready(None)));
+    let pings = stream::unfold(
+        tokio::time::interval_at(tokio::time::Instant::now() + interval, interval),
+        |mut ticker| async move {
+            ticker.tick().await;
+            Some((Some(Ok(Bytes::from_static(b": keep-alive\n\n"))), ticker))
+        },
+    );
+    let merged = stream::select(events, pings)
+        .take_while(|item| future::ready(item.is_some()))
+        .filter_map(future::ready);
+    Response::from_parts(parts, Body::from_stream(merged))
+}
+
+fn serve_clients(
+    tool: &ProtocolTool,
+    clients: HashMap<String, ClientConfig>,
+    bind: SocketAddr,
+    idle_timeout: Option<Duration>,
+    ct: CancellationToken,
+) -> Router {
+    let clients: Vec<AuthenticatedClient> = clients
+        .into_iter()
+        .map(|(name, client)| {
+            let (server, router) = SseServer::new(SseServerConfig {
+                bind,
+                sse_path: "/sse".to_string(),
+                post_path: "/message".to_string(),
+                ct: ct.child_token(),
+            });
+            let client_tool = tool.for_client(&name, client.trp_key.as_deref().map(Secret::new), client.trp_url.as_deref());
+            serve_sessions(server, client_tool, idle_timeout, ct.child_token());
+            tracing::info!("Accepting SSE client {}", name);
+            AuthenticatedClient { token: client.token, name, router }
+        })
+        .collect();
+
+    Router::new().fallback(route_by_token).with_state(Arc::new(clients))
+}
+
+/// Reads `TLS_CERT_PATH` and `TLS_KEY_PATH`. Without them the server speaks plain HTTP.
+fn tls_from_env() -> anyhow::Result<Option<ReloadingAcceptor>> {
+    match (config::optional::<PathBuf>("TLS_CERT_PATH")?, config::optional::<PathBuf>("TLS_KEY_PATH")?) {
+        (Some(cert), Some(key)) => ReloadingAcceptor::new(&cert, &key).map(Some),
+        (None, None) => Ok(None),
+        _ => anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+    }
+}
+
+/// Serves tx3 protocols as MCP tools over SSE.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+    #[command(flatten)]
+    listen: ListenArgs,
+}
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
     dotenv().ok();
+    let cli = Cli::parse();
+    config::set_overrides(cli.common.overrides().into_iter().chain(cli.listen.overrides()));
+    if let Some(path) = cli.common.config() {
+        config::load_file(path)?;
+    }
+    config::validate(&["ADDRESS", "PORT"])?;
+
+    server::init_logging(cli.common.check())?;
+    config::log_effective();
+
+    let address = config::required("ADDRESS")?;
+    let port = config::required("PORT")?;
+    let tool = protocol_tool_from_env()?;
+    let clients = clients_from_env()?;
+    let tls = tls_from_env()?;
+    let cors = cors_from_env()?;
+    let keep_alive_interval = config::optional::<u64>("SSE_KEEP_ALIVE_SECS")?.unwrap_or(DEFAULT_KEEP_ALIVE_SECS);
+    let idle_timeout = match config::optional::<u64>("SSE_IDLE_TIMEOUT_SECS")?.unwrap_or(DEFAULT_IDLE_TIMEOUT_SECS) {
+        0 => None,
+        secs => Some(Duration::from_secs(secs)),
+    };
+    let auth_tokens = auth::tokens_from_env()?;
+    if !auth_tokens.is_empty() && !clients.is_empty() {
+        anyhow::bail!("AUTH_TOKENS can't be combined with SSE_CLIENTS, which already authenticates each client by token");
+    }
+
+    if cli.common.check() {
+        return server::check(&tool, cli.common.format()).await;
+    }
+
+    server::check_trp(&tool).await?;
+
+    let bind_address: SocketAddr = format!("{}:{}", address, port).parse()?;
+
+    let ct = CancellationToken::new();
+    let router = if clients.is_empty() {
+        let (sse_server, router) = SseServer::new(SseServerConfig {
+            bind: bind_address,
+            sse_path: "/sse".to_string(),
+            post_path: "/message".to_string(),
+            ct: ct.child_token(),
+        });
+        serve_sessions(sse_server, tool.clone(), idle_timeout, ct.child_token());
+        auth::require_tokens(router, auth_tokens)
+    } else {
+        serve_clients(&tool, clients, bind_address, idle_timeout, ct.clone())
+    };
+
+    // Probes skip authentication, the auth layer only wraps the SSE routes.
+    let router = router.merge(server::health_router(tool.clone()));
 
-    let address = env::var("ADDRESS").expect("ADDRESS must be set in the environment");
-    let port = env::var("PORT").expect("PORT must be set in the environment");
-    let registry_url = env::var("TX3_REGISTRY_URL").expect("TX3_REGISTRY_URL must be set in the environment");
-    let trp_url = env::var("TRP_URL").expect("TRP_URL must be set in the environment");
-    let trp_key = env::var("TRP_KEY").expect("TRP_KEY must be set in the environment");
+    let router = match keep_alive_interval {
+        0 => router,
+        secs => router.layer(middleware::from_fn_with_state(Duration::from_secs(secs), keep_alive)),
+    };
 
-    let bind_address = format!("{}:{}", address, port);
+    // Outermost, so preflights are answered before authentication.
+    let router = match cors {
+        Some(cors) => router.layer(cors),
+        None => router,
+    };
 
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "debug".to_string().into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
+    let listener = tokio::net::TcpListener::bind(bind_address).await?;
+    let accepting = ct.child_token();
+    match tls {
+        Some(acceptor) => {
+            tracing::info!("Serving SSE over TLS on https://{}/sse", bind_address);
+            acceptor.spawn_reload_task(secret::RELOAD_INTERVAL, ct.child_token());
+            tls::serve(listener, router, acceptor, accepting.clone());
+        }
+        None => {
+            tracing::info!("Serving SSE on http://{}/sse", bind_address);
+            server::serve(listener, router, accepting.clone(), "SSE");
+        }
+    }
 
-    let ct = SseServer::serve(bind_address.parse()?)
-        .await?
-        .with_service(move || ProtocolTool::new(&registry_url, &trp_url, &trp_key));
+    server::spawn_background_tasks(&tool, &ct).await?;
 
-    tokio::signal::ctrl_c().await?;
+    // Open SSE streams stay up while draining, so running calls can still answer.
+    server::wait_for_shutdown(&tool, Some(&accepting)).await?;
     ct.cancel();
     Ok(())
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::routing::post;
+    use super::*;
+
+    fn router(origins: &[&str]) -> Router {
+        let origins: Vec<String> = origins.iter().map(|origin| origin.to_string()).collect();
+        let cors = cors_layer(&origins).unwrap().unwrap();
+        Router::new().route("/message", post(|| async { "ok" })).layer(cors)
+    }
+
+    async fn preflight(router: Router, origin: &str) -> Response {
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/message")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(header::ACCESS_CONTROL_REQUEST_HEADERS, "authorization,content-type")
+            .body(Body::empty())
+            .unwrap();
+        router.oneshot(request).await.unwrap()
+    }
+
+    fn allowed_origin(response: &Response) -> Option<&str> {
+        response.headers().get(header::ACCESS_CONTROL_ALLOW_ORIGIN).and_then(|value| value.to_str().ok())
+    }
+
+    #[test]
+    fn no_origins_means_no_layer() {
+        assert!(cors_layer(&[]).unwrap().is_none());
+    }
+
+    #[test]
+    fn invalid_origin_is_rejected() {
+        assert!(cors_layer(&["https://ok.example\n".to_string()]).is_err());
+    }
+
+    #[tokio::test]
+    async fn allowed_origin_is_echoed() {
+        let response = preflight(router(&["https://app.example"]), "https://app.example").await;
+        assert_eq!(allowed_origin(&response), Some("https://app.example"));
+        let allowed_headers = response.headers()[header::ACCESS_CONTROL_ALLOW_HEADERS].to_str().unwrap();
+        assert!(allowed_headers.contains("authorization"));
+    }
+
+    #[tokio::test]
+    async fn other_origin_gets_no_cors_headers() {
+        let response = preflight(router(&["https://app.example"]), "https://evil.example").await;
+        assert_eq!(allowed_origin(&response), None);
+    }
+
+    #[tokio::test]
+    async fn wildcard_allows_any_origin() {
+        let response = preflight(router(&["*"]), "https://anywhere.example").await;
+        assert_eq!(allowed_origin(&response), Some("*"));
+    }
+}
@@ -1,34 +1,72 @@
-use std::env;
 use dotenv::dotenv;
 use anyhow::Result;
+use clap::Parser;
 use rmcp::{ServiceExt, transport::stdio};
-use tracing_subscriber::{self, EnvFilter};
+use tokio_util::sync::CancellationToken;
 
 #[path = "../tools/mod.rs"]
 mod tools;
-use tools::protocol::ProtocolTool;
+use tools::cli::CommonArgs;
+use tools::config::{self, protocol_tool_from_env};
+use tools::roots::RootScan;
+use tools::server;
+
+/// Serves tx3 protocols as MCP tools over stdin and stdout.
+#[derive(Parser)]
+#[command(version, about)]
+struct Cli {
+    #[command(flatten)]
+    common: CommonArgs,
+}
 
 #[tokio::main]
 async fn main() -> Result<()> {
     dotenv().ok();
+    let cli = Cli::parse();
+    config::set_overrides(cli.common.overrides());
+    if let Some(path) = cli.common.config() {
+        config::load_file(path)?;
+    }
+    config::validate(&[])?;
+
+    server::init_logging(true)?;
+    config::log_effective();
+
+    let mut tool = protocol_tool_from_env()?;
+    if config::optional::<bool>("DISCOVER_ROOTS")?.unwrap_or(true) {
+        let mut scan = RootScan::default();
+        if let Some(max_depth) = config::optional::<usize>("ROOTS_MAX_DEPTH")? {
+            scan.max_depth = max_depth;
+        }
+        let ignore = config::list("ROOTS_IGNORE");
+        if !ignore.is_empty() {
+            scan.ignore = ignore;
+        }
+        tool = tool.with_root_discovery(scan);
+    }
 
-    let registry_url = env::var("TX3_REGISTRY_URL").expect("TX3_REGISTRY_URL must be set in the environment");
-    let trp_url = env::var("TRP_URL").expect("TRP_URL must be set in the environment");
-    let trp_key = env::var("TRP_KEY").expect("TRP_KEY must be set in the environment");
+    if cli.common.check() {
+        return server::check(&tool, cli.common.format()).await;
+    }
 
-    tracing_subscriber::fmt()
-        .with_env_filter(EnvFilter::from_default_env().add_directive(tracing::Level::DEBUG.into()))
-        .with_writer(std::io::stderr)
-        .with_ansi(false)
-        .init();
+    server::check_trp(&tool).await?;
 
     tracing::info!("Starting MCP server");
 
-    let service = ProtocolTool::new(&registry_url, &trp_url, &trp_key).serve(stdio()).await.inspect_err(|e| {
+    let ct = CancellationToken::new();
+    server::spawn_background_tasks(&tool, &ct).await?;
+
+    let service = tool.clone().serve(stdio()).await.inspect_err(|e| {
         tracing::error!("serving error: {:?}", e);
     })?;
 
-    service.waiting().await?;
-    
+    tokio::select! {
+        result = service.waiting() => {
+            result?;
+        }
+        result = server::wait_for_shutdown(&tool, None) => result?,
+    }
+    ct.cancel();
+
     Ok(())
 }
\ No newline at end of file
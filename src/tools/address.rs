@@ -0,0 +1,207 @@
+use std::fmt;
+use std::str::FromStr;
+
+const CHARSET: &str = "qpzry9x8gf2tvdw0s3jn54khce6mua7l";
+const GENERATOR: [u32; 5] = [0x3b6a57b2, 0x26508e6d, 0x1ea119fa, 0x3d4233dd, 0x2a1462b3];
+
+/// Cardano network an address belongs to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Network {
+    Mainnet,
+    Testnet,
+}
+
+impl FromStr for Network {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "mainnet" => Ok(Self::Mainnet),
+            "testnet" | "preprod" | "preview" => Ok(Self::Testnet),
+            _ => Err(format!("unknown network {}, expected mainnet or testnet", value)),
+        }
+    }
+}
+
+impl fmt::Display for Network {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Mainnet => f.write_str("mainnet"),
+            Self::Testnet => f.write_str("testnet"),
+        }
+    }
+}
+
+impl Network {
+    fn from_id(id: u8) -> Self {
+        if id == 1 { Self::Mainnet } else { Self::Testnet }
+    }
+}
+
+/// Parses a bech32 (`addr1...`, `addr_test1...`, `stake1...`, `stake_test1...`) or hex
+/// encoded Cardano address into its raw bytes. When `expected` is set, addresses for
+/// another network are rejected.
+pub fn parse_address(value: &str, expected: Option<Network>) -> Result<Vec<u8>, String> {
+    let value = value.trim();
+
+    let bytes = if value.contains('1') && !value.chars().all(|c| c.is_ascii_hexdigit()) {
+        let (hrp, bytes) = decode_bech32(value)?;
+        let network = match hrp.as_str() {
+            "addr" | "stake" => Network::Mainnet,
+            "addr_test" | "stake_test" => Network::Testnet,
+            _ => return Err(format!("unexpected prefix {}, expected addr, addr_test, stake or stake_test", hrp)),
+        };
+        let header = *bytes.first().ok_or("address has no payload")?;
+        if !is_byron(header) && Network::from_id(header & 0x0f) != network {
+            return Err(format!("prefix {} doesn't match the network in the address header", hrp));
+        }
+        bytes
+    } else {
+        let hex = value.strip_prefix("0x").unwrap_or(value);
+        hex::decode(hex).map_err(|_| "not a bech32 or hex encoded address".to_string())?
+    };
+
+    let header = *bytes.first().ok_or("address is empty")?;
+    if let Some(expected) = expected {
+        let network = Network::from_id(header & 0x0f);
+        if !is_byron(header) && network != expected {
+            return Err(format!("address is for {}, this server expects {}", network, expected));
+        }
+    }
+
+    Ok(bytes)
+}
+
+/// Byron addresses don't carry the network id in the header nibble.
+fn is_byron(header: u8) -> bool {
+    header >> 4 == 0b1000
+}
+
+fn polymod(values: &[u8]) -> u32 {
+    let mut checksum: u32 = 1;
+    for value in values {
+        let top = checksum >> 25;
+        checksum = ((checksum & 0x1ffffff) << 5) ^ *value as u32;
+        for (index, generator) in GENERATOR.iter().enumerate() {
+            if (top >> index) & 1 == 1 {
+                checksum ^= generator;
+            }
+        }
+    }
+    checksum
+}
+
+/// Decodes bech32 without the 90 character limit of BIP-173, which Cardano addresses
+/// exceed.
+fn decode_bech32(value: &str) -> Result<(String, Vec<u8>), String> {
+    if value.chars().any(|c| c.is_ascii_lowercase()) && value.chars().any(|c| c.is_ascii_uppercase()) {
+        return Err("bech32 address mixes upper and lower case".to_string());
+    }
+    let value = value.to_ascii_lowercase();
+
+    let (hrp, data) = value.rsplit_once('1').ok_or("missing bech32 separator")?;
+    if hrp.is_empty() || data.len() < 6 {
+        return Err("bech32 address is too short".to_string());
+    }
+
+    let data: Vec<u8> = data
+        .chars()
+        .map(|c| CHARSET.find(c).map(|index| index as u8))
+        .collect::<Option<_>>()
+        .ok_or("invalid bech32 character")?;
+
+    let mut checked: Vec<u8> = hrp.bytes().map(|c| c >> 5).collect();
+    checked.push(0);
+    checked.extend(hrp.bytes().map(|c| c & 31));
+    checked.extend(&data);
+    if polymod(&checked) != 1 {
+        return Err("bad bech32 checksum".to_string());
+    }
+
+    let payload = &data[..data.len() - 6];
+    let mut bytes = Vec::with_capacity(payload.len() * 5 / 8);
+    let (mut accumulator, mut bits) = (0u32, 0u32);
+    for value in payload {
+        accumulator = ((accumulator << 5) | *value as u32) & 0xfff;
+        bits += 5;
+        if bits >= 8 {
+            bits -= 8;
+            bytes.push((accumulator >> bits) as u8);
+        }
+    }
+    if bits >= 5 || (accumulator << (8 - bits)) & 0xff != 0 {
+        return Err("invalid bech32 padding".to_string());
+    }
+
+    Ok((hrp.to_string(), bytes))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CIP-19 test vectors.
+    const MAINNET: &str = "addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgse35a3x";
+    const MAINNET_HEX: &str = "019493315cd92eb5d8c4304e67b7e16ae36d61d34502694657811a2c8e337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251";
+    const TESTNET: &str = "addr_test1qz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgs68faae";
+    const STAKE: &str = "stake1uyehkck0lajq8gr28t9uxnuvgcqrc6070x3k9r8048z8y5gh6ffgw";
+
+    #[test]
+    fn decodes_bech32_addresses() {
+        assert_eq!(hex::encode(parse_address(MAINNET, None).unwrap()), MAINNET_HEX);
+        assert_eq!(parse_address(TESTNET, None).unwrap()[0], 0x00);
+        assert_eq!(hex::encode(parse_address(STAKE, None).unwrap()), "e1337b62cfff6403a06a3acbc34f8c46003c69fe79a3628cefa9c47251");
+    }
+
+    #[test]
+    fn accepts_upper_case_bech32_and_hex() {
+        assert_eq!(hex::encode(parse_address(&MAINNET.to_ascii_uppercase(), None).unwrap()), MAINNET_HEX);
+        assert_eq!(hex::encode(parse_address(&format!("0x{}", MAINNET_HEX), None).unwrap()), MAINNET_HEX);
+    }
+
+    #[test]
+    fn rejects_addresses_for_another_network() {
+        assert!(parse_address(MAINNET, Some(Network::Mainnet)).is_ok());
+        assert!(parse_address(TESTNET, Some(Network::Testnet)).is_ok());
+
+        let err = parse_address(MAINNET, Some(Network::Testnet)).unwrap_err();
+        assert_eq!(err, "address is for mainnet, this server expects testnet");
+        assert!(parse_address(MAINNET_HEX, Some(Network::Testnet)).is_err());
+    }
+
+    #[test]
+    fn rejects_a_prefix_that_contradicts_the_header() {
+        // A testnet header encoded under the mainnet prefix.
+        let mislabelled = "addr1qz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgsg8a6rm";
+        let err = parse_address(mislabelled, None).unwrap_err();
+        assert!(err.contains("doesn't match the network"), "{}", err);
+    }
+
+    #[test]
+    fn rejects_malformed_bech32() {
+        let mut corrupted = MAINNET.to_string();
+        corrupted.pop();
+        corrupted.push('q');
+        assert_eq!(parse_address(&corrupted, None).unwrap_err(), "bad bech32 checksum");
+
+        let mixed = format!("A{}", &MAINNET[1..]);
+        assert_eq!(parse_address(&mixed, None).unwrap_err(), "bech32 address mixes upper and lower case");
+
+        assert!(parse_address("addr1qqqq", None).is_err());
+        assert!(parse_address("addr1qx2fxv2umyhttkxyxp8x0dlpdt3k6cwngb", None).is_err());
+        assert!(parse_address("", None).is_err());
+    }
+
+    #[test]
+    fn rejects_unknown_prefixes() {
+        let err = parse_address("pool1xdak9nllvsp6q636e0p5lrzxqq7xnlne5d3gemafc3e9zwrurdx", None).unwrap_err();
+        assert!(err.starts_with("unexpected prefix pool"), "{}", err);
+    }
+
+    #[test]
+    fn parses_network_names() {
+        assert_eq!("preprod".parse::<Network>().unwrap(), Network::Testnet);
+        assert_eq!("mainnet".parse::<Network>().unwrap(), Network::Mainnet);
+        assert!("devnet".parse::<Network>().is_err());
+    }
+}
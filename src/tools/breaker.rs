@@ -0,0 +1,143 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use serde_json::Value;
+
+use super::health::UpstreamHealth;
+
+pub const DEFAULT_THRESHOLD: u32 = 5;
+pub const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+/// Stops calling an upstream after `threshold` consecutive failures until `cooldown`
+/// has elapsed, after which a single probe decides whether to close it again.
+pub struct CircuitBreaker {
+    name: String,
+    threshold: u32,
+    cooldown: Duration,
+    health: UpstreamHealth,
+    opened_at: Mutex<Option<Instant>>,
+}
+
+impl CircuitBreaker {
+    pub fn new(name: &str, threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            name: name.to_string(),
+            threshold,
+            cooldown,
+            health: UpstreamHealth::default(),
+            opened_at: Mutex::default(),
+        }
+    }
+
+    pub fn health(&self) -> &UpstreamHealth {
+        &self.health
+    }
+
+    /// Whether a call should go through. Once the cooldown of an open circuit elapses
+    /// calls are let through again as probes.
+    pub fn allow(&self) -> bool {
+        match *self.opened_at.lock().unwrap() {
+            Some(opened_at) => opened_at.elapsed() >= self.cooldown,
+            None => true,
+        }
+    }
+
+    pub fn record_success(&self) {
+        if self.opened_at.lock().unwrap().take().is_some() {
+            tracing::info!("{} circuit closed, upstream is reachable again", self.name);
+        }
+        self.health.record_success();
+    }
+
+    pub fn record_failure(&self, error: &str) {
+        let failures = self.health.record_failure(error);
+        if failures < self.threshold {
+            return;
+        }
+
+        let mut opened_at = self.opened_at.lock().unwrap();
+        if opened_at.is_none() {
+            tracing::warn!(
+                "{} circuit opened after {} consecutive failures, pausing requests for {}s",
+                self.name,
+                failures,
+                self.cooldown.as_secs()
+            );
+        }
+        *opened_at = Some(Instant::now());
+    }
+
+    pub fn status(&self) -> Value {
+        let circuit = match *self.opened_at.lock().unwrap() {
+            Some(opened_at) if opened_at.elapsed() < self.cooldown => "open",
+            Some(_) => "half-open",
+            None => "closed",
+        };
+
+        let mut status = self.health.status();
+        status["circuit"] = Value::String(circuit.to_string());
+        status
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn circuit(breaker: &CircuitBreaker) -> String {
+        breaker.status()["circuit"].as_str().unwrap().to_string()
+    }
+
+    #[test]
+    fn opens_after_consecutive_failures() {
+        let breaker = CircuitBreaker::new("registry", 3, Duration::from_secs(60));
+        breaker.record_failure("timeout");
+        breaker.record_failure("timeout");
+        assert!(breaker.allow());
+        assert_eq!(circuit(&breaker), "closed");
+
+        breaker.record_failure("timeout");
+        assert!(!breaker.allow());
+        assert_eq!(circuit(&breaker), "open");
+        assert_eq!(breaker.status()["consecutive_failures"], 3);
+        assert_eq!(breaker.status()["last_error"], "timeout");
+    }
+
+    #[test]
+    fn a_success_resets_the_count() {
+        let breaker = CircuitBreaker::new("registry", 2, Duration::from_secs(60));
+        breaker.record_failure("timeout");
+        breaker.record_success();
+        breaker.record_failure("timeout");
+        assert!(breaker.allow());
+        assert_eq!(circuit(&breaker), "closed");
+    }
+
+    #[test]
+    fn half_opens_after_the_cooldown() {
+        let breaker = CircuitBreaker::new("registry", 1, Duration::ZERO);
+        breaker.record_failure("timeout");
+        assert!(breaker.allow());
+        assert_eq!(circuit(&breaker), "half-open");
+
+        // A failed probe opens it again, a successful one closes it.
+        breaker.record_failure("timeout");
+        assert_eq!(circuit(&breaker), "half-open");
+        breaker.record_success();
+        assert_eq!(circuit(&breaker), "closed");
+        assert!(breaker.allow());
+    }
+
+    #[test]
+    fn a_failed_probe_restarts_the_cooldown() {
+        let breaker = CircuitBreaker::new("registry", 1, Duration::from_millis(50));
+        breaker.record_failure("timeout");
+        assert!(!breaker.allow());
+        std::thread::sleep(Duration::from_millis(60));
+        assert!(breaker.allow());
+        assert_eq!(circuit(&breaker), "half-open");
+
+        breaker.record_failure("still down");
+        assert!(!breaker.allow());
+        assert_eq!(circuit(&breaker), "open");
+    }
+}
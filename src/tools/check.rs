@@ -0,0 +1,147 @@
+use std::fmt::Write;
+use std::str::FromStr;
+use serde::Serialize;
+
+/// How the `--check` report is printed.
+#[derive(Clone, Copy, Default)]
+pub enum ReportFormat {
+    #[default]
+    Text,
+    /// A single JSON object, for pipelines.
+    Json,
+}
+
+impl FromStr for ReportFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown report format {}, expected text or json", value)),
+        }
+    }
+}
+
+#[derive(Serialize)]
+pub struct RegistryCheck {
+    pub url: String,
+    pub protocols: usize,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct ProtocolCheck {
+    pub name: String,
+    pub source: String,
+    pub transactions: Vec<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Serialize)]
+pub struct TrpCheck {
+    pub endpoint: String,
+    pub url: String,
+    pub latency_ms: Option<u128>,
+    pub error: Option<String>,
+}
+
+/// What a deployment would serve, found through the same fetch, compile and tool
+/// listing code the server runs.
+#[derive(Serialize)]
+pub struct CheckReport {
+    pub passed: bool,
+    pub registries: Vec<RegistryCheck>,
+    pub protocols: Vec<ProtocolCheck>,
+    /// Protocols left out by the allowlist, denylist or size limit.
+    pub excluded: Vec<String>,
+    pub tools: Vec<String>,
+    pub tools_error: Option<String>,
+    pub trp: Vec<TrpCheck>,
+}
+
+impl CheckReport {
+    /// Passes when every registry answered, every protocol compiled, the tools could be
+    /// listed and every TRP endpoint was reachable.
+    pub fn new(
+        registries: Vec<RegistryCheck>,
+        protocols: Vec<ProtocolCheck>,
+        excluded: Vec<String>,
+        tools: Result<Vec<String>, String>,
+        trp: Vec<TrpCheck>,
+    ) -> Self {
+        let (tools, tools_error) = match tools {
+            Ok(tools) => (tools, None),
+            Err(err) => (Vec::new(), Some(err)),
+        };
+        let passed = registries.iter().all(|check| check.error.is_none())
+            && protocols.iter().all(|check| check.error.is_none())
+            && tools_error.is_none()
+            && trp.iter().all(|check| check.error.is_none());
+
+        Self { passed, registries, protocols, excluded, tools, tools_error, trp }
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Json => serde_json::to_string_pretty(self).unwrap_or_default(),
+            ReportFormat::Text => self.render_text(),
+        }
+    }
+
+    fn render_text(&self) -> String {
+        let mut out = String::new();
+
+        let _ = writeln!(out, "Registries");
+        for check in self.registries.iter() {
+            match &check.error {
+                Some(err) => { let _ = writeln!(out, "  FAIL  {}: {}", check.url, err); }
+                None => { let _ = writeln!(out, "  ok    {} ({} protocols)", check.url, check.protocols); }
+            }
+        }
+
+        let _ = writeln!(out, "Protocols");
+        for check in self.protocols.iter() {
+            match &check.error {
+                Some(err) => { let _ = writeln!(out, "  FAIL  {} from {}: {}", check.name, check.source, err); }
+                None => {
+                    let _ = writeln!(
+                        out,
+                        "  ok    {} from {} ({} transactions: {})",
+                        check.name,
+                        check.source,
+                        check.transactions.len(),
+                        check.transactions.join(", "),
+                    );
+                }
+            }
+        }
+        for name in self.excluded.iter() {
+            let _ = writeln!(out, "  skip  {} (excluded by allowlist, denylist or size limit)", name);
+        }
+
+        let _ = writeln!(out, "Tools");
+        match &self.tools_error {
+            Some(err) => { let _ = writeln!(out, "  FAIL  {}", err); }
+            None => {
+                for tool in self.tools.iter() {
+                    let _ = writeln!(out, "  {}", tool);
+                }
+            }
+        }
+
+        let _ = writeln!(out, "TRP");
+        for check in self.trp.iter() {
+            match &check.error {
+                Some(err) => { let _ = writeln!(out, "  FAIL  {} at {}: {}", check.endpoint, check.url, err); }
+                None => {
+                    let latency = check.latency_ms.unwrap_or_default();
+                    let _ = writeln!(out, "  ok    {} at {} ({}ms)", check.endpoint, check.url, latency);
+                }
+            }
+        }
+
+        let _ = write!(out, "Check {}", if self.passed { "passed" } else { "failed" });
+        out
+    }
+}
@@ -0,0 +1,105 @@
+use std::path::{Path, PathBuf};
+use clap::Args;
+
+use super::check::ReportFormat;
+
+/// Flags and the environment variable each one overrides.
+const FLAGS: &[(&str, &str)] = &[
+    ("TX3_REGISTRY_URL", "--registry-url"),
+    ("TRP_URL", "--trp-url"),
+    ("TRP_KEY", "--trp-key"),
+    ("TX3_PROTOCOL_FILE", "--protocol-file"),
+    ("ADDRESS", "--address"),
+    ("PORT", "--port"),
+];
+
+/// The flag setting the environment variable `name`, if there is one.
+pub fn flag_for(name: &str) -> Option<&'static str> {
+    FLAGS.iter().find(|(var, _)| *var == name).map(|(_, flag)| *flag)
+}
+
+/// Settings every binary takes. Flags override the environment, which overrides `.env`
+/// and then the config file.
+#[derive(Args)]
+pub struct CommonArgs {
+    /// TOML config file, overridden by the environment and these flags
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
+
+    /// Comma-separated tx3 registry endpoints [env: TX3_REGISTRY_URL]
+    #[arg(long, value_name = "URL")]
+    registry_url: Option<String>,
+
+    /// TRP endpoint used to resolve transactions [env: TRP_URL]
+    #[arg(long, value_name = "URL")]
+    trp_url: Option<String>,
+
+    /// TRP API key [env: TRP_KEY]
+    #[arg(long, value_name = "KEY")]
+    trp_key: Option<String>,
+
+    /// Local .tx3 file exposed alongside the registry protocols [env: TX3_PROTOCOL_FILE]
+    #[arg(long, value_name = "PATH")]
+    protocol_file: Option<PathBuf>,
+
+    /// Check the configuration, registries, protocols and TRP, print a report and exit,
+    /// non-zero when something failed
+    #[arg(long)]
+    check: bool,
+
+    /// Format of the --check report: text or json
+    #[arg(long, value_name = "FORMAT", default_value = "text", requires = "check")]
+    format: ReportFormat,
+}
+
+impl CommonArgs {
+    pub fn config(&self) -> Option<&Path> {
+        self.config.as_deref()
+    }
+
+    pub fn check(&self) -> bool {
+        self.check
+    }
+
+    pub fn format(&self) -> ReportFormat {
+        self.format
+    }
+
+    pub fn overrides(&self) -> Vec<(&'static str, String)> {
+        [
+            ("TX3_REGISTRY_URL", self.registry_url.clone()),
+            ("TRP_URL", self.trp_url.clone()),
+            ("TRP_KEY", self.trp_key.clone()),
+            ("TX3_PROTOCOL_FILE", self.protocol_file.as_ref().map(|path| path.display().to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect()
+    }
+}
+
+/// Where the network binaries listen.
+#[derive(Args)]
+#[allow(dead_code)]
+pub struct ListenArgs {
+    /// Address to bind [env: ADDRESS]
+    #[arg(long)]
+    address: Option<String>,
+
+    /// Port to bind [env: PORT]
+    #[arg(long)]
+    port: Option<u16>,
+}
+
+#[allow(dead_code)]
+impl ListenArgs {
+    pub fn overrides(&self) -> Vec<(&'static str, String)> {
+        [
+            ("ADDRESS", self.address.clone()),
+            ("PORT", self.port.map(|port| port.to_string())),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.map(|value| (name, value)))
+        .collect()
+    }
+}
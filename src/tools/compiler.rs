@@ -0,0 +1,196 @@
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tx3_lang::ir::Type;
+
+/// tx3-lang release protocols are compiled with, kept in step with Cargo.toml.
+pub const TX3_LANG_VERSION: &str = "0.6.0";
+
+/// Compiled protocols kept in memory by default.
+pub const DEFAULT_CAPACITY: usize = 256;
+
+/// Protocols compiled in parallel by default: one per available core.
+pub fn default_concurrency() -> usize {
+    std::thread::available_parallelism().map_or(4, |cores| cores.get())
+}
+
+/// A transaction of a compiled protocol: its parameters and the TIR sent to TRP.
+pub struct CompiledTx {
+    pub name: String,
+    pub params: BTreeMap<String, Type>,
+    /// Comment lines written right above the `tx` definition.
+    pub docs: Option<String>,
+    pub tir: Vec<u8>,
+}
+
+/// Everything the server needs from a tx3 protocol, extracted once at compile time so
+/// it can be shared across tasks.
+pub struct CompiledProtocol {
+    pub txs: Vec<CompiledTx>,
+    /// Fields of the protocol's `env` section, which TRP fills from the env args.
+    pub env: Vec<String>,
+}
+
+#[derive(Debug)]
+pub struct CompileError {
+    pub message: String,
+    pub diagnostics: String,
+}
+
+impl CompileError {
+    /// Line and column of the error, when the diagnostics carry a pest style
+    /// `--> line:column` marker.
+    pub fn location(&self) -> Option<(usize, usize)> {
+        let marker = self.diagnostics.split("--> ").nth(1)?;
+        let (line, rest) = marker.split_once(':')?;
+        let column: String = rest.chars().take_while(char::is_ascii_digit).collect();
+        Some((line.trim().parse().ok()?, column.parse().ok()?))
+    }
+}
+
+impl fmt::Display for CompileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl CompiledProtocol {
+    pub fn compile(source: &str) -> Result<Self, CompileError> {
+        let protocol = tx3_lang::Protocol::from_string(source.to_string())
+            .load()
+            .map_err(|err| CompileError {
+                message: err.to_string(),
+                diagnostics: format!("{:?}", err),
+            })?;
+
+        let mut txs = Vec::new();
+        for tx in protocol.txs() {
+            let prototx = match protocol.new_tx(tx.name.as_str()) {
+                Ok(prototx) => prototx,
+                Err(err) => {
+                    tracing::warn!("Skipping transaction {}: {:?}", tx.name, err);
+                    continue;
+                }
+            };
+
+            txs.push(CompiledTx {
+                name: tx.name.to_string(),
+                params: prototx
+                    .find_params()
+                    .iter()
+                    .map(|(name, ty)| (name.clone(), ty.clone()))
+                    .collect(),
+                docs: doc_comment(source, &tx.name),
+                tir: prototx.ir_bytes(),
+            });
+        }
+
+        let env = protocol.ast().env.as_ref()
+            .map(|env| env.fields.iter().map(|field| field.name.clone()).collect())
+            .unwrap_or_default();
+
+        Ok(Self { txs, env })
+    }
+
+    pub fn tx(&self, name: &str) -> Option<&CompiledTx> {
+        self.txs.iter().find(|tx| tx.name == name)
+    }
+}
+
+struct CacheEntry {
+    protocol: Arc<CompiledProtocol>,
+    last_used: u64,
+}
+
+#[derive(Default)]
+struct CacheState {
+    entries: HashMap<u64, CacheEntry>,
+    tick: u64,
+}
+
+/// Least-recently-used cache of compiled protocols keyed by source content hash.
+pub struct CompileCache {
+    capacity: usize,
+    state: Mutex<CacheState>,
+    hits: AtomicU64,
+    misses: AtomicU64,
+}
+
+impl CompileCache {
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            state: Mutex::default(),
+            hits: AtomicU64::new(0),
+            misses: AtomicU64::new(0),
+        }
+    }
+
+    /// Cache hits and misses since startup.
+    pub fn stats(&self) -> (u64, u64) {
+        (self.hits.load(Ordering::Relaxed), self.misses.load(Ordering::Relaxed))
+    }
+
+    pub fn get_or_compile(&self, name: &str, hash: u64, source: &str) -> Result<Arc<CompiledProtocol>, CompileError> {
+        {
+            let mut state = self.state.lock().unwrap();
+            state.tick += 1;
+            let tick = state.tick;
+            if let Some(entry) = state.entries.get_mut(&hash) {
+                entry.last_used = tick;
+                let hits = self.hits.fetch_add(1, Ordering::Relaxed) + 1;
+                tracing::trace!("Compile cache hit for {} (hits={}, misses={})", name, hits, self.misses.load(Ordering::Relaxed));
+                return Ok(entry.protocol.clone());
+            }
+        }
+
+        let misses = self.misses.fetch_add(1, Ordering::Relaxed) + 1;
+        tracing::debug!("Compile cache miss for {} (hits={}, misses={})", name, self.hits.load(Ordering::Relaxed), misses);
+
+        let started = Instant::now();
+        let compiled = CompiledProtocol::compile(source);
+        tracing::debug!("Compiled protocol {} in {:?}", name, started.elapsed());
+        let protocol = Arc::new(compiled?);
+
+        let mut state = self.state.lock().unwrap();
+        state.tick += 1;
+        let tick = state.tick;
+        state.entries.insert(hash, CacheEntry { protocol: protocol.clone(), last_used: tick });
+
+        while state.entries.len() > self.capacity {
+            let oldest = state.entries.iter().min_by_key(|(_, entry)| entry.last_used).map(|(hash, _)| *hash);
+            match oldest {
+                Some(oldest) => state.entries.remove(&oldest),
+                None => break,
+            };
+        }
+
+        Ok(protocol)
+    }
+}
+
+/// Collects the `//` comment block directly above `tx <name>(`. The tx3 parser drops
+/// comments, so they are read from the source text.
+fn doc_comment(source: &str, tx_name: &str) -> Option<String> {
+    let lines: Vec<&str> = source.lines().collect();
+    let position = lines.iter().position(|line| {
+        line.trim_start()
+            .strip_prefix("tx ")
+            .map(|rest| rest.trim_start().strip_prefix(tx_name).is_some_and(|rest| rest.trim_start().starts_with('(')))
+            .unwrap_or(false)
+    })?;
+
+    let mut docs: Vec<&str> = lines[..position]
+        .iter()
+        .rev()
+        .map(|line| line.trim())
+        .take_while(|line| line.starts_with("//"))
+        .map(|line| line.trim_start_matches('/').trim())
+        .collect();
+    docs.reverse();
+
+    let docs = docs.join("\n").trim().to_string();
+    (!docs.is_empty()).then_some(docs)
+}
@@ -0,0 +1,502 @@
+use std::collections::{BTreeMap, HashMap};
+use std::env;
+use std::path::{Path, PathBuf};
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
+use anyhow::{Context, Result, anyhow};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use super::address::{self, Network};
+use super::breaker;
+use super::cli;
+use super::config_file::{self, Config};
+use super::history;
+use super::protocol::{ProtocolTool, ToolMode, Warmup};
+use super::recent;
+use super::registry::{self, ProtocolSource, Registry};
+use super::resolver::MockResolver;
+use super::retry::RetryPolicy;
+use super::secret::{self, Secret};
+use super::snapshot::DiskSnapshot;
+use super::trp::TrpEndpoint;
+
+/// Values given on the command line, which take precedence over the environment.
+static OVERRIDES: OnceLock<HashMap<&'static str, String>> = OnceLock::new();
+
+/// Installs the command line values, keyed by the variable they override. Only the first
+/// call has an effect.
+pub fn set_overrides(overrides: impl IntoIterator<Item = (&'static str, String)>) {
+    let _ = OVERRIDES.set(overrides.into_iter().collect());
+}
+
+fn overridden(name: &str) -> Option<String> {
+    OVERRIDES.get().and_then(|overrides| overrides.get(name).cloned())
+}
+
+/// Settings from the config file, the layer under the environment.
+struct FileSettings {
+    values: HashMap<String, String>,
+    names: Vec<String>,
+    problems: Vec<String>,
+}
+
+static FILE: OnceLock<FileSettings> = OnceLock::new();
+
+/// Reads the TOML config file at `path`. Failing to read or parse it is an error right
+/// away; other problems are reported by `validate` along with missing settings.
+pub fn load_file(path: &Path) -> Result<()> {
+    let content = std::fs::read_to_string(path)
+        .with_context(|| format!("Failed to read config file {}", path.display()))?;
+    let config: Config = toml::from_str(&content)
+        .with_context(|| format!("Invalid config file {}", path.display()))?;
+
+    let vars = config.vars();
+    let settings = FileSettings {
+        names: vars.iter().map(|(name, _)| name.clone()).collect(),
+        values: vars.into_iter().filter_map(|(name, value)| value.map(|value| (name, value))).collect(),
+        problems: config.problems(),
+    };
+    let _ = FILE.set(settings);
+    Ok(())
+}
+
+fn from_file(name: &str) -> Option<String> {
+    FILE.get().and_then(|file| file.values.get(name).cloned())
+}
+
+/// Reads a setting from the command line, or else the environment, or else the config
+/// file.
+pub fn var(name: &str) -> Option<String> {
+    overridden(name)
+        .or_else(|| env::var(name).ok())
+        .or_else(|| from_file(name))
+}
+
+/// Logs the settings the config file can hold, as they are in effect once the
+/// environment and command line are applied. Credentials show as `***`.
+pub fn log_effective() {
+    let Some(file) = FILE.get() else {
+        return;
+    };
+    let effective: Vec<(String, String)> = file.names
+        .iter()
+        .filter_map(|name| var(name).map(|value| (name.clone(), value)))
+        .map(|(name, value)| match config_file::OPAQUE.contains(&name.as_str()) {
+            true => (name, secret::REDACTED.to_string()),
+            false => (name, value),
+        })
+        .collect();
+    let effective = secret::redact(effective.iter().map(|(name, value)| (name, value)));
+    tracing::info!("Effective configuration: {:?}", effective);
+}
+
+pub fn required(name: &str) -> Result<String> {
+    var(name).ok_or_else(|| anyhow!("{} must be set in the environment", name))
+}
+
+/// Fails listing every problem at once: those found in the config file, then missing
+/// settings, which are the registry unless a local protocol is given, TRP unless
+/// `NETWORKS` is set, and the binary's own `extra` settings.
+pub fn validate(extra: &[&'static str]) -> Result<()> {
+    let mut problems: Vec<String> = FILE.get()
+        .map(|file| file.problems.iter().map(|problem| format!("  {}", problem)).collect())
+        .unwrap_or_default();
+    let mut missing = Vec::new();
+    if var("TX3_REGISTRY_URL").is_none() && var("TX3_PROTOCOL_FILE").is_none() && var("TX3_PROTOCOL_INLINE").is_none() {
+        missing.push("TX3_REGISTRY_URL");
+    }
+    if var("TRP_URL").is_none() && list("NETWORKS").is_empty() {
+        missing.push("TRP_URL");
+    }
+    missing.extend(extra.iter().filter(|name| var(name).is_none()));
+
+    problems.extend(missing.iter().map(|name| match cli::flag_for(name) {
+        Some(flag) => format!("  {} is missing (set it or pass {})", name, flag),
+        None => format!("  {} is missing", name),
+    }));
+
+    if problems.is_empty() {
+        return Ok(());
+    }
+    Err(anyhow!("Invalid configuration:\n{}", problems.join("\n")))
+}
+
+/// Splits a comma-separated value, skipping empty entries.
+pub fn split_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// Reads an optional comma-separated list.
+pub fn list(name: &str) -> Vec<String> {
+    var(name).map(|value| split_list(&value)).unwrap_or_default()
+}
+
+/// Reads an optional comma-separated list of `key=value` pairs.
+pub fn map(name: &str) -> Result<HashMap<String, String>> {
+    list(name)
+        .into_iter()
+        .map(|entry| match entry.split_once('=') {
+            Some((key, value)) => Ok((key.trim().to_string(), value.trim().to_string())),
+            None => Err(anyhow!("Invalid entry in {}, expected key=value: {}", name, entry)),
+        })
+        .collect()
+}
+
+pub fn optional<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    match var(name) {
+        Some(value) => value
+            .parse::<T>()
+            .map(Some)
+            .map_err(|err| anyhow!("Invalid value for {}: {}", name, err)),
+        None => Ok(None),
+    }
+}
+
+/// Reads an optional JSON object.
+fn json_object(name: &str) -> Result<Map<String, Value>> {
+    match var(name) {
+        Some(value) => serde_json::from_str(&value).with_context(|| format!("{} must be a JSON object", name)),
+        None => Ok(Map::new()),
+    }
+}
+
+/// Reads extra TRP headers, either as a JSON object of strings or as `key=value` pairs.
+fn headers(name: &str) -> Result<HashMap<String, String>> {
+    match var(name) {
+        Some(value) if value.trim_start().starts_with('{') => {
+            serde_json::from_str(&value).with_context(|| format!("{} must be a JSON object of strings", name))
+        }
+        _ => map(name),
+    }
+}
+
+/// Reads a key from the file named by `<NAME>_FILE`, or else from `<NAME>` itself.
+/// File backed keys can be reloaded while the server runs. A key given on the command
+/// line wins over both, and the config file only counts when the environment has neither.
+pub fn secret(name: &str) -> Result<Option<Secret>> {
+    let file_name = format!("{}_FILE", name);
+    if let Some(value) = overridden(name) {
+        return Ok(Some(Secret::new(&value)));
+    }
+    if let Ok(path) = env::var(&file_name) {
+        return Secret::from_file(Path::new(&path)).map(Some);
+    }
+    if let Ok(value) = env::var(name) {
+        return Ok(Some(Secret::new(&value)));
+    }
+    if let Some(path) = from_file(&file_name) {
+        return Secret::from_file(Path::new(&path)).map(Some);
+    }
+    Ok(from_file(name).map(|value| Secret::new(&value)))
+}
+
+/// Reads the named TRP networks listed in `NETWORKS`. Each one takes its endpoint from
+/// `TRP_URL_<NAME>` and its key from `TRP_KEY_<NAME>`, falling back to the shared key.
+/// `TRP_ENV_ARGS_<NAME>` entries are merged over the shared `TRP_ENV_ARGS`, and
+/// `TRP_HEADERS` is sent to every network.
+fn networks_from_env(
+    env_args: &Map<String, Value>,
+    headers: &HashMap<String, String>,
+    key: Option<&Secret>,
+) -> Result<BTreeMap<String, TrpEndpoint>> {
+    list("NETWORKS")
+        .into_iter()
+        .map(|name| {
+            let suffix = name.to_uppercase().replace('-', "_");
+            let url = required(&format!("TRP_URL_{}", suffix))?;
+            let key = secret(&format!("TRP_KEY_{}", suffix))?.or_else(|| key.cloned());
+            let mut network_env_args = env_args.clone();
+            network_env_args.extend(json_object(&format!("TRP_ENV_ARGS_{}", suffix))?);
+            let endpoint = TrpEndpoint::new(&url)
+                .with_key(key)
+                .with_headers(headers.clone())
+                .with_env_args(network_env_args);
+            Ok((name, endpoint))
+        })
+        .collect()
+}
+
+#[derive(Deserialize)]
+struct EndpointConfig {
+    url: String,
+    key: Option<String>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+}
+
+/// Reads the dedicated TRP endpoints of `TRP_ENDPOINTS`, a JSON object mapping a name to
+/// `{url, key, headers}`. They share `TRP_ENV_ARGS` with the other endpoints.
+fn endpoints_from_env(env_args: &Map<String, Value>) -> Result<BTreeMap<String, TrpEndpoint>> {
+    let Some(value) = var("TRP_ENDPOINTS") else {
+        return Ok(BTreeMap::new());
+    };
+
+    let endpoints: BTreeMap<String, EndpointConfig> = serde_json::from_str(&value)
+        .context("TRP_ENDPOINTS must be a JSON object of {url, key, headers} entries")?;
+    Ok(endpoints
+        .into_iter()
+        .map(|(name, config)| {
+            let endpoint = TrpEndpoint::new(&config.url)
+                .with_key(config.key.as_deref().map(Secret::new))
+                .with_headers(config.headers)
+                .with_env_args(env_args.clone());
+            (name, endpoint)
+        })
+        .collect())
+}
+
+/// Builds a `ProtocolTool` from the command line and process environment, shared by
+/// every binary.
+pub fn protocol_tool_from_env() -> Result<ProtocolTool> {
+    let local_protocol = local_protocol_from_env()?;
+
+    let registry_urls = match local_protocol {
+        Some(_) => list("TX3_REGISTRY_URL"),
+        None => split_list(&required("TX3_REGISTRY_URL")?),
+    };
+    if registry_urls.is_empty() && local_protocol.is_none() {
+        return Err(anyhow!("TX3_REGISTRY_URL must list at least one registry"));
+    }
+    let env_args = json_object("TRP_ENV_ARGS")?;
+    let trp_headers = headers("TRP_HEADERS")?;
+    let trp_key = secret("TRP_KEY")?;
+    let networks = networks_from_env(&env_args, &trp_headers, trp_key.as_ref())?;
+    let trp_url = if networks.is_empty() {
+        required("TRP_URL")?
+    } else {
+        var("TRP_URL").unwrap_or_default()
+    };
+    let trp = TrpEndpoint::new(&trp_url)
+        .with_key(trp_key)
+        .with_headers(trp_headers)
+        .with_env_args(env_args);
+
+    if networks.is_empty() {
+        tracing::info!("TRP endpoint {} with headers {:?}", trp.redacted_url(), trp.redacted_headers());
+    }
+    for (name, endpoint) in networks.iter() {
+        tracing::info!("TRP network {} at {} with headers {:?}", name, endpoint.redacted_url(), endpoint.redacted_headers());
+    }
+
+    let trp_endpoints = endpoints_from_env(&trp.env_args)?;
+    for endpoint in std::iter::once(&trp).chain(networks.values()).chain(trp_endpoints.values()) {
+        if endpoint.is_mock() {
+            MockResolver::from_url(&endpoint.url)?;
+            tracing::warn!("TRP endpoint {} is a mock, resolved transactions are fake", endpoint.url);
+        }
+    }
+    let trp_routes = map("TRP_ROUTES")?;
+    for (name, endpoint) in trp_endpoints.iter() {
+        tracing::info!("TRP endpoint {} at {} with headers {:?}", name, endpoint.redacted_url(), endpoint.redacted_headers());
+    }
+    for (protocol, endpoint) in trp_routes.iter() {
+        if !trp_endpoints.contains_key(endpoint) {
+            tracing::warn!("TRP_ROUTES sends {} to endpoint {}, which is missing from TRP_ENDPOINTS", protocol, endpoint);
+        }
+    }
+
+    let page_size = optional::<i32>("REGISTRY_PAGE_SIZE")?;
+    let max_pages = optional::<usize>("REGISTRY_MAX_PAGES")?;
+    let scopes = list("TX3_SCOPES");
+    let registry_key = secret("TX3_REGISTRY_KEY")?;
+    let registry_header_name = optional::<String>("TX3_REGISTRY_HEADER_NAME")?;
+    let registry_timeout = optional::<u64>("REGISTRY_TIMEOUT_MS")?
+        .map(Duration::from_millis)
+        .unwrap_or(registry::DEFAULT_TIMEOUT);
+    let registry_retry = RetryPolicy::new(
+        optional::<u32>("REGISTRY_RETRY_ATTEMPTS")?.unwrap_or(registry::DEFAULT_RETRY.attempts),
+        optional::<u64>("REGISTRY_RETRY_BASE_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(registry::DEFAULT_RETRY.base_delay),
+    );
+
+    let http = reqwest::Client::new();
+    let registries = registry_urls.iter()
+        .map(|url| {
+            let mut registry = Registry::new(url)
+                .with_client(http.clone())
+                .with_page_size(page_size)
+                .with_scopes(scopes.clone())
+                .with_retry(registry_retry)
+                .with_timeout(registry_timeout);
+            if let Some(key) = &registry_key {
+                registry = registry.with_key(key.clone(), registry_header_name.as_deref());
+            }
+            if let Some(max_pages) = max_pages {
+                registry = registry.with_max_pages(max_pages);
+            }
+            registry.validate()?;
+            Ok(registry)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut tool = ProtocolTool::new(registry_urls.first().map(String::as_str).unwrap_or_default(), trp)
+        .with_registries(registries)
+        .with_http_client(http)
+        .with_networks(networks, var("DEFAULT_NETWORK"))
+        .with_trp_routes(trp_endpoints, trp_routes)
+        .with_protocol_filter(list("PROTOCOL_ALLOWLIST"), list("PROTOCOL_DENYLIST"))
+        .with_version_pins(map("PROTOCOL_VERSION_PINS")?);
+
+    if let Some(secs) = optional::<u64>("REGISTRY_CACHE_TTL_SECS")? {
+        tool = tool.with_cache_ttl(Duration::from_secs(secs));
+    }
+
+    let breaker_threshold = optional::<u32>("REGISTRY_BREAKER_THRESHOLD")?;
+    let breaker_cooldown = optional::<u64>("REGISTRY_BREAKER_COOLDOWN_SECS")?;
+    if breaker_threshold.is_some() || breaker_cooldown.is_some() {
+        tool = tool.with_circuit_breaker(
+            breaker_threshold.unwrap_or(breaker::DEFAULT_THRESHOLD),
+            breaker_cooldown.map(Duration::from_secs).unwrap_or(breaker::DEFAULT_COOLDOWN),
+        );
+    }
+
+    if let Some(secs) = optional::<u64>("PING_UNHEALTHY_AFTER_SECS")? {
+        tool = tool.with_unhealthy_after(Duration::from_secs(secs));
+    }
+
+    if !optional::<bool>("TX3_DISABLE_DISK_CACHE")?.unwrap_or(false) {
+        match DiskSnapshot::default_path() {
+            Some(path) => tool = tool.with_disk_snapshot(DiskSnapshot::new(path)),
+            None => tracing::warn!("No cache directory available, registry snapshots won't be persisted"),
+        }
+    }
+
+    tool = tool.with_tools_page_size(optional::<usize>("TOOLS_PAGE_SIZE")?);
+
+    if let Some(max_size) = optional::<usize>("MAX_PROTOCOL_SIZE_BYTES")? {
+        tool = tool.with_max_protocol_size(max_size);
+    }
+
+    if let Some(capacity) = optional::<usize>("COMPILE_CACHE_SIZE")? {
+        tool = tool.with_compile_cache_size(capacity);
+    }
+
+    if let Some(tool_mode) = optional::<ToolMode>("TOOL_MODE")? {
+        tool = tool.with_tool_mode(tool_mode);
+    }
+
+    if let Some(warmup) = optional::<Warmup>("WARMUP")? {
+        tool = tool.with_warmup(warmup);
+    }
+
+    let history_enabled = optional::<bool>("HISTORY_ENABLED")?.unwrap_or(true);
+    let history_size = optional::<usize>("HISTORY_SIZE")?.unwrap_or(history::DEFAULT_CAPACITY);
+    let audit_log = optional::<PathBuf>("AUDIT_LOG_PATH")?;
+    if let Some(path) = &audit_log {
+        tracing::info!("Appending resolutions to audit log {}", path.display());
+    }
+    tool = tool.with_history(if history_enabled { history_size } else { 0 }, audit_log);
+
+    if let Some(per_minute) = optional::<u32>("RATE_LIMIT_PER_MINUTE")? {
+        let burst = optional::<u32>("RATE_LIMIT_BURST")?.unwrap_or(per_minute);
+        tool = tool.with_rate_limit(per_minute, burst);
+    }
+
+    let recent_capacity = optional::<usize>("RESOLVED_TX_CAPACITY")?;
+    let recent_ttl = optional::<u64>("RESOLVED_TX_TTL_SECS")?;
+    if recent_capacity.is_some() || recent_ttl.is_some() {
+        tool = tool.with_recent_txs(
+            recent_capacity.unwrap_or(recent::DEFAULT_CAPACITY),
+            recent_ttl.map(Duration::from_secs).unwrap_or(recent::DEFAULT_TTL),
+        );
+    }
+
+    if let Some(extra) = optional::<String>("INSTRUCTIONS_EXTRA")? {
+        tool = tool.with_instructions_extra(extra);
+    }
+
+    if let Some(max_protocols) = optional::<usize>("INSTRUCTIONS_MAX_PROTOCOLS")? {
+        tool = tool.with_instructions_max_protocols(max_protocols);
+    }
+
+    if let Some(expose) = optional::<bool>("EXPOSE_DESCRIBE_TOOLS")? {
+        tool = tool.with_describe_tools(expose);
+    }
+
+    if let Some(ms) = optional::<u64>("TRP_TIMEOUT_MS")? {
+        tool = tool.with_trp_timeout(Duration::from_millis(ms));
+    }
+
+    if let Some(max_concurrency) = optional::<usize>("TRP_MAX_CONCURRENCY")? {
+        tool = tool.with_trp_max_concurrency(max_concurrency);
+    }
+
+    if let Some(attempts) = optional::<u32>("TRP_RETRY_ATTEMPTS")? {
+        let base_delay = optional::<u64>("TRP_RETRY_BASE_MS")?
+            .map(Duration::from_millis)
+            .unwrap_or(registry::DEFAULT_RETRY.base_delay);
+        tool = tool.with_trp_retry(RetryPolicy::new(attempts, base_delay));
+    }
+
+    if let Some(ignore) = optional::<bool>("IGNORE_UNKNOWN_ARGS")? {
+        tool = tool.with_ignore_unknown_args(ignore);
+    }
+
+    let known_addresses: BTreeMap<String, String> = map("KNOWN_ADDRESSES")?.into_iter().collect();
+    for (label, address) in known_addresses.iter() {
+        address::parse_address(address, None)
+            .map_err(|err| anyhow!("Invalid address {} in KNOWN_ADDRESSES: {}", label, err))?;
+    }
+    tool = tool.with_known_addresses(known_addresses);
+
+    if let Some(network) = optional::<Network>("CARDANO_NETWORK")? {
+        tool = tool.with_network(network);
+    }
+
+    if let Some(concurrency) = optional::<usize>("COMPILE_CONCURRENCY")? {
+        tool = tool.with_compile_concurrency(concurrency);
+    }
+
+    if let Some(max_len) = optional::<usize>("TOOL_DESCRIPTION_MAX_LEN")? {
+        tool = tool.with_description_max_len(max_len);
+    }
+
+    if let Some(fail) = optional::<bool>("LIST_TOOLS_FAIL_ON_REGISTRY_ERROR")? {
+        tool = tool.with_fail_on_registry_error(fail);
+    }
+
+    if let Some(protocol) = local_protocol {
+        tracing::info!("Serving local protocol {} from {}", protocol.name, protocol.registry_url);
+        tool = tool.with_local_protocol(protocol);
+    }
+
+    Ok(tool)
+}
+
+/// Loads the protocol given through `TX3_PROTOCOL_FILE` or `TX3_PROTOCOL_INLINE`, named
+/// after `TX3_PROTOCOL_NAME` or the file stem.
+fn local_protocol_from_env() -> Result<Option<ProtocolSource>> {
+    let name = optional::<String>("TX3_PROTOCOL_NAME")?;
+
+    if let Some(path) = optional::<String>("TX3_PROTOCOL_FILE")? {
+        let content = std::fs::read_to_string(&path)
+            .with_context(|| format!("Failed to read TX3_PROTOCOL_FILE {}", path))?;
+        let name = name.unwrap_or_else(|| {
+            Path::new(&path)
+                .file_stem()
+                .map(|stem| stem.to_string_lossy().to_string())
+                .unwrap_or_else(|| "local".to_string())
+        });
+        return ProtocolSource::local(&name, content, &format!("file://{}", path)).map(Some);
+    }
+
+    if let Some(content) = optional::<String>("TX3_PROTOCOL_INLINE")? {
+        let name = name.unwrap_or_else(|| "local".to_string());
+        return ProtocolSource::local(&name, content, "inline").map(Some);
+    }
+
+    Ok(None)
+}
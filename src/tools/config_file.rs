@@ -0,0 +1,348 @@
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::net::SocketAddr;
+use std::str::FromStr;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value, json};
+
+use super::address::Network;
+use super::protocol::{ToolMode, Warmup};
+use super::server::LogFormat;
+
+/// Settings read from the `--config` TOML file. Each field stands for the environment
+/// variable named in its comment, so the environment and the command line override it;
+/// a list or table set in the environment replaces the file's one as a whole.
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    pub registry: RegistryConfig,
+    pub protocol: LocalProtocolConfig,
+    pub trp: TrpConfig,
+    /// NETWORKS, with TRP_URL_<NAME>, TRP_KEY_<NAME> and TRP_ENV_ARGS_<NAME>
+    pub networks: BTreeMap<String, NetworkConfig>,
+    /// DEFAULT_NETWORK
+    pub default_network: Option<String>,
+    pub tools: ToolsConfig,
+    pub cache: CacheConfig,
+    pub server: ServerConfig,
+    pub logging: LoggingConfig,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct RegistryConfig {
+    /// TX3_REGISTRY_URL
+    pub urls: Vec<String>,
+    /// TX3_REGISTRY_KEY
+    pub key: Option<String>,
+    /// TX3_REGISTRY_KEY_FILE
+    pub key_file: Option<String>,
+    /// TX3_REGISTRY_HEADER_NAME
+    pub header_name: Option<String>,
+    /// TX3_SCOPES
+    pub scopes: Vec<String>,
+    /// PROTOCOL_ALLOWLIST
+    pub allowlist: Vec<String>,
+    /// PROTOCOL_DENYLIST
+    pub denylist: Vec<String>,
+    /// PROTOCOL_VERSION_PINS
+    pub version_pins: BTreeMap<String, String>,
+    /// REGISTRY_PAGE_SIZE
+    pub page_size: Option<i32>,
+    /// REGISTRY_MAX_PAGES
+    pub max_pages: Option<usize>,
+    /// REGISTRY_TIMEOUT_MS
+    pub timeout_ms: Option<u64>,
+    /// REGISTRY_RETRY_ATTEMPTS
+    pub retry_attempts: Option<u32>,
+    /// REGISTRY_RETRY_BASE_MS
+    pub retry_base_ms: Option<u64>,
+    /// REGISTRY_REFRESH_INTERVAL_SECS
+    pub refresh_interval_secs: Option<u64>,
+    /// REGISTRY_BREAKER_THRESHOLD
+    pub breaker_threshold: Option<u32>,
+    /// REGISTRY_BREAKER_COOLDOWN_SECS
+    pub breaker_cooldown_secs: Option<u64>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LocalProtocolConfig {
+    /// TX3_PROTOCOL_FILE
+    pub file: Option<String>,
+    /// TX3_PROTOCOL_NAME
+    pub name: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct TrpConfig {
+    /// TRP_URL
+    pub url: Option<String>,
+    /// TRP_KEY
+    pub key: Option<String>,
+    /// TRP_KEY_FILE
+    pub key_file: Option<String>,
+    /// TRP_HEADERS
+    pub headers: BTreeMap<String, String>,
+    /// TRP_ENV_ARGS
+    pub env_args: Map<String, Value>,
+    /// TRP_TIMEOUT_MS
+    pub timeout_ms: Option<u64>,
+    /// TRP_MAX_CONCURRENCY
+    pub max_concurrency: Option<usize>,
+    /// TRP_RETRY_ATTEMPTS
+    pub retry_attempts: Option<u32>,
+    /// TRP_RETRY_BASE_MS
+    pub retry_base_ms: Option<u64>,
+    /// TRP_REQUIRE_HEALTHY
+    pub require_healthy: Option<bool>,
+    /// TRP_ENDPOINTS
+    pub endpoints: BTreeMap<String, EndpointConfig>,
+    /// TRP_ROUTES
+    pub routes: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct EndpointConfig {
+    pub url: String,
+    pub key: Option<String>,
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub url: String,
+    pub key: Option<String>,
+    pub key_file: Option<String>,
+    #[serde(default)]
+    pub env_args: Map<String, Value>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ToolsConfig {
+    /// TOOL_MODE
+    pub mode: Option<String>,
+    /// WARMUP
+    pub warmup: Option<String>,
+    /// TOOLS_PAGE_SIZE
+    pub page_size: Option<usize>,
+    /// EXPOSE_DESCRIBE_TOOLS
+    pub expose_describe: Option<bool>,
+    /// TOOL_DESCRIPTION_MAX_LEN
+    pub description_max_len: Option<usize>,
+    /// IGNORE_UNKNOWN_ARGS
+    pub ignore_unknown_args: Option<bool>,
+    /// KNOWN_ADDRESSES
+    pub known_addresses: BTreeMap<String, String>,
+    /// CARDANO_NETWORK
+    pub cardano_network: Option<String>,
+    /// INSTRUCTIONS_EXTRA
+    pub instructions_extra: Option<String>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct CacheConfig {
+    /// REGISTRY_CACHE_TTL_SECS
+    pub registry_ttl_secs: Option<u64>,
+    /// TX3_DISABLE_DISK_CACHE
+    pub disable_disk_cache: Option<bool>,
+    /// COMPILE_CACHE_SIZE
+    pub compile_size: Option<usize>,
+    /// RESOLVED_TX_CAPACITY
+    pub resolved_tx_capacity: Option<usize>,
+    /// RESOLVED_TX_TTL_SECS
+    pub resolved_tx_ttl_secs: Option<u64>,
+    /// HISTORY_SIZE
+    pub history_size: Option<usize>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    /// ADDRESS
+    pub address: Option<String>,
+    /// PORT
+    pub port: Option<u16>,
+    /// AUTH_TOKENS
+    pub auth_tokens: Vec<String>,
+    /// DRAIN_TIMEOUT_SECS
+    pub drain_timeout_secs: Option<u64>,
+    /// METRICS_ADDRESS
+    pub metrics_address: Option<String>,
+    /// RATE_LIMIT_PER_MINUTE
+    pub rate_limit_per_minute: Option<u32>,
+    /// RATE_LIMIT_BURST
+    pub rate_limit_burst: Option<u32>,
+}
+
+#[derive(Deserialize, Default)]
+#[serde(default, deny_unknown_fields)]
+pub struct LoggingConfig {
+    /// LOG_FORMAT
+    pub format: Option<String>,
+    /// RUST_LOG
+    pub level: Option<String>,
+    /// AUDIT_LOG_PATH
+    pub audit_log_path: Option<String>,
+}
+
+/// Values whose content may hold credentials under names that don't say so.
+pub const OPAQUE: &[&str] = &["TRP_HEADERS", "TRP_ENDPOINTS"];
+
+fn join(items: &[String]) -> Option<String> {
+    (!items.is_empty()).then(|| items.join(","))
+}
+
+fn pairs(entries: &BTreeMap<String, String>) -> Option<String> {
+    (!entries.is_empty()).then(|| {
+        entries.iter().map(|(key, value)| format!("{}={}", key, value)).collect::<Vec<_>>().join(",")
+    })
+}
+
+fn json_of(value: impl Serialize, empty: bool) -> Option<String> {
+    (!empty).then(|| json!(value).to_string())
+}
+
+fn text(value: Option<impl Display>) -> Option<String> {
+    value.map(|value| value.to_string())
+}
+
+fn check<T>(problems: &mut Vec<String>, field: &str, value: Option<&String>)
+where
+    T: FromStr,
+    T::Err: Display,
+{
+    if let Some(Err(err)) = value.map(|value| value.parse::<T>()) {
+        problems.push(format!("{}: {}", field, err));
+    }
+}
+
+impl Config {
+    /// Every setting the file can hold, by environment variable, with its value if set.
+    pub fn vars(&self) -> Vec<(String, Option<String>)> {
+        let registry = &self.registry;
+        let trp = &self.trp;
+        let tools = &self.tools;
+        let cache = &self.cache;
+        let server = &self.server;
+        let logging = &self.logging;
+
+        let mut vars = vec![
+            ("TX3_REGISTRY_URL", join(&registry.urls)),
+            ("TX3_REGISTRY_KEY", registry.key.clone()),
+            ("TX3_REGISTRY_KEY_FILE", registry.key_file.clone()),
+            ("TX3_REGISTRY_HEADER_NAME", registry.header_name.clone()),
+            ("TX3_SCOPES", join(&registry.scopes)),
+            ("PROTOCOL_ALLOWLIST", join(&registry.allowlist)),
+            ("PROTOCOL_DENYLIST", join(&registry.denylist)),
+            ("PROTOCOL_VERSION_PINS", pairs(&registry.version_pins)),
+            ("REGISTRY_PAGE_SIZE", text(registry.page_size)),
+            ("REGISTRY_MAX_PAGES", text(registry.max_pages)),
+            ("REGISTRY_TIMEOUT_MS", text(registry.timeout_ms)),
+            ("REGISTRY_RETRY_ATTEMPTS", text(registry.retry_attempts)),
+            ("REGISTRY_RETRY_BASE_MS", text(registry.retry_base_ms)),
+            ("REGISTRY_REFRESH_INTERVAL_SECS", text(registry.refresh_interval_secs)),
+            ("REGISTRY_BREAKER_THRESHOLD", text(registry.breaker_threshold)),
+            ("REGISTRY_BREAKER_COOLDOWN_SECS", text(registry.breaker_cooldown_secs)),
+            ("TX3_PROTOCOL_FILE", self.protocol.file.clone()),
+            ("TX3_PROTOCOL_NAME", self.protocol.name.clone()),
+            ("TRP_URL", trp.url.clone()),
+            ("TRP_KEY", trp.key.clone()),
+            ("TRP_KEY_FILE", trp.key_file.clone()),
+            ("TRP_HEADERS", json_of(&trp.headers, trp.headers.is_empty())),
+            ("TRP_ENV_ARGS", json_of(&trp.env_args, trp.env_args.is_empty())),
+            ("TRP_TIMEOUT_MS", text(trp.timeout_ms)),
+            ("TRP_MAX_CONCURRENCY", text(trp.max_concurrency)),
+            ("TRP_RETRY_ATTEMPTS", text(trp.retry_attempts)),
+            ("TRP_RETRY_BASE_MS", text(trp.retry_base_ms)),
+            ("TRP_REQUIRE_HEALTHY", text(trp.require_healthy)),
+            ("TRP_ENDPOINTS", json_of(&trp.endpoints, trp.endpoints.is_empty())),
+            ("TRP_ROUTES", pairs(&trp.routes)),
+            ("NETWORKS", join(&self.networks.keys().cloned().collect::<Vec<_>>())),
+            ("DEFAULT_NETWORK", self.default_network.clone()),
+            ("TOOL_MODE", tools.mode.clone()),
+            ("WARMUP", tools.warmup.clone()),
+            ("TOOLS_PAGE_SIZE", text(tools.page_size)),
+            ("EXPOSE_DESCRIBE_TOOLS", text(tools.expose_describe)),
+            ("TOOL_DESCRIPTION_MAX_LEN", text(tools.description_max_len)),
+            ("IGNORE_UNKNOWN_ARGS", text(tools.ignore_unknown_args)),
+            ("KNOWN_ADDRESSES", pairs(&tools.known_addresses)),
+            ("CARDANO_NETWORK", tools.cardano_network.clone()),
+            ("INSTRUCTIONS_EXTRA", tools.instructions_extra.clone()),
+            ("REGISTRY_CACHE_TTL_SECS", text(cache.registry_ttl_secs)),
+            ("TX3_DISABLE_DISK_CACHE", text(cache.disable_disk_cache)),
+            ("COMPILE_CACHE_SIZE", text(cache.compile_size)),
+            ("RESOLVED_TX_CAPACITY", text(cache.resolved_tx_capacity)),
+            ("RESOLVED_TX_TTL_SECS", text(cache.resolved_tx_ttl_secs)),
+            ("HISTORY_SIZE", text(cache.history_size)),
+            ("ADDRESS", server.address.clone()),
+            ("PORT", text(server.port)),
+            ("AUTH_TOKENS", join(&server.auth_tokens)),
+            ("DRAIN_TIMEOUT_SECS", text(server.drain_timeout_secs)),
+            ("METRICS_ADDRESS", server.metrics_address.clone()),
+            ("RATE_LIMIT_PER_MINUTE", text(server.rate_limit_per_minute)),
+            ("RATE_LIMIT_BURST", text(server.rate_limit_burst)),
+            ("LOG_FORMAT", logging.format.clone()),
+            ("RUST_LOG", logging.level.clone()),
+            ("AUDIT_LOG_PATH", logging.audit_log_path.clone()),
+        ]
+        .into_iter()
+        .map(|(name, value)| (name.to_string(), value))
+        .collect::<Vec<_>>();
+
+        for (name, network) in self.networks.iter() {
+            let suffix = name.to_uppercase().replace('-', "_");
+            vars.push((format!("TRP_URL_{}", suffix), Some(network.url.clone())));
+            vars.push((format!("TRP_KEY_{}", suffix), network.key.clone()));
+            vars.push((format!("TRP_KEY_{}_FILE", suffix), network.key_file.clone()));
+            vars.push((format!("TRP_ENV_ARGS_{}", suffix), json_of(&network.env_args, network.env_args.is_empty())));
+        }
+        vars
+    }
+
+    /// Everything wrong with the file, so all of it can be fixed in one go.
+    pub fn problems(&self) -> Vec<String> {
+        let mut problems = Vec::new();
+
+        check::<ToolMode>(&mut problems, "tools.mode", self.tools.mode.as_ref());
+        check::<Warmup>(&mut problems, "tools.warmup", self.tools.warmup.as_ref());
+        check::<Network>(&mut problems, "tools.cardano_network", self.tools.cardano_network.as_ref());
+        check::<LogFormat>(&mut problems, "logging.format", self.logging.format.as_ref());
+        check::<SocketAddr>(&mut problems, "server.metrics_address", self.server.metrics_address.as_ref());
+
+        if self.registry.key.is_some() && self.registry.key_file.is_some() {
+            problems.push("registry.key and registry.key_file can't both be set".to_string());
+        }
+        if self.trp.key.is_some() && self.trp.key_file.is_some() {
+            problems.push("trp.key and trp.key_file can't both be set".to_string());
+        }
+        if self.registry.urls.iter().any(|url| url.trim().is_empty()) {
+            problems.push("registry.urls holds an empty URL".to_string());
+        }
+        for (name, network) in self.networks.iter() {
+            if network.url.trim().is_empty() {
+                problems.push(format!("networks.{}.url is empty", name));
+            }
+            if network.key.is_some() && network.key_file.is_some() {
+                problems.push(format!("networks.{}.key and networks.{}.key_file can't both be set", name, name));
+            }
+        }
+        if let Some(default) = &self.default_network {
+            if !self.networks.contains_key(default) {
+                problems.push(format!("default_network {} isn't one of networks", default));
+            }
+        }
+        for (protocol, endpoint) in self.trp.routes.iter() {
+            if !self.trp.endpoints.contains_key(endpoint) {
+                problems.push(format!("trp.routes sends {} to endpoint {}, which isn't in trp.endpoints", protocol, endpoint));
+            }
+        }
+        problems
+    }
+}
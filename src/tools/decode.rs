@@ -0,0 +1,106 @@
+use serde_json::{Value, json};
+use pallas_traverse::{MultiEraAsset, MultiEraPolicyAssets, MultiEraTx};
+
+/// Computes the id of a CBOR encoded transaction, the blake2b-256 hash of its body.
+pub fn tx_hash(cbor_hex: &str) -> Result<String, String> {
+    let bytes = hex::decode(cbor_hex).map_err(|err| format!("transaction is not hex: {}", err))?;
+    let tx = MultiEraTx::decode(&bytes).map_err(|err| format!("transaction is not valid CBOR: {}", err))?;
+    Ok(tx.hash().to_string())
+}
+
+/// Human readable summary of a CBOR encoded transaction, for reviewing it before signing.
+pub fn summarize(cbor_hex: &str) -> Result<Value, String> {
+    let bytes = hex::decode(cbor_hex).map_err(|err| format!("transaction is not hex: {}", err))?;
+    let tx = MultiEraTx::decode(&bytes).map_err(|err| format!("transaction is not valid CBOR: {}", err))?;
+
+    let inputs: Vec<Value> = tx.inputs().iter()
+        .map(|input| json!(format!("{}#{}", input.hash(), input.index())))
+        .collect();
+
+    let outputs: Vec<Value> = tx.outputs().iter()
+        .map(|output| {
+            let address = output.address()
+                .ok()
+                .and_then(|address| address.to_bech32().ok());
+            let value = output.value();
+            json!({
+                "address": address,
+                "lovelace": value.coin(),
+                "assets": assets(&value.assets(), |asset| asset.output_coin().map(i128::from)),
+                "has_datum": output.datum().is_some(),
+            })
+        })
+        .collect();
+
+    let metadata_labels: Vec<u64> = tx.metadata()
+        .collect::<Vec<_>>()
+        .into_iter()
+        .map(|(label, _)| *label)
+        .collect();
+
+    Ok(json!({
+        "hash": tx.hash().to_string(),
+        "era": tx.era().to_string(),
+        "inputs": inputs,
+        "reference_inputs": tx.reference_inputs().iter()
+            .map(|input| format!("{}#{}", input.hash(), input.index()))
+            .collect::<Vec<_>>(),
+        "outputs": outputs,
+        "fee": tx.fee(),
+        "mint": assets(&tx.mints(), |asset| asset.mint_coin().map(i128::from)),
+        "metadata_labels": metadata_labels,
+        "validity": {
+            "start": tx.validity_start(),
+            "ttl": tx.ttl(),
+        },
+    }))
+}
+
+fn assets(
+    policies: &[MultiEraPolicyAssets],
+    amount: impl Fn(&MultiEraAsset) -> Option<i128>,
+) -> Vec<Value> {
+    policies.iter()
+        .flat_map(|policy| {
+            policy.assets().into_iter().map(|asset| {
+                json!({
+                    "policy": policy.policy().to_string(),
+                    "name": hex::encode(asset.name()),
+                    "amount": amount(&asset).map(|amount| amount.to_string()),
+                })
+            }).collect::<Vec<_>>()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// `[{0: [], 1: [], 2: 0}, {}, true, null]`: no inputs, no outputs and no fee.
+    const EMPTY_TX: &str = "84a3008001800200a0f5f6";
+    /// blake2b-256 of the body, `a3008001800200`.
+    const EMPTY_TX_HASH: &str = "36fdff68dfe3660f1ceea60f018a0fd7a83da13def229108794c397a879b0436";
+
+    #[test]
+    fn summary_lists_the_transaction_contents() {
+        let summary = summarize(EMPTY_TX).unwrap();
+        assert_eq!(summary["hash"], EMPTY_TX_HASH);
+        assert_eq!(summary["inputs"], json!([]));
+        assert_eq!(summary["outputs"], json!([]));
+        assert_eq!(summary["fee"], 0);
+        assert_eq!(summary["mint"], json!([]));
+    }
+
+    #[test]
+    fn summary_rejects_what_isnt_a_transaction() {
+        assert!(summarize("not hex").unwrap_err().starts_with("transaction is not hex"));
+        assert!(summarize("8201").unwrap_err().starts_with("transaction is not valid CBOR"));
+    }
+
+    #[test]
+    fn hash_is_the_body_hash() {
+        assert_eq!(tx_hash(EMPTY_TX).unwrap(), EMPTY_TX_HASH);
+        assert!(tx_hash("zz").is_err());
+    }
+}
@@ -0,0 +1,188 @@
+use std::borrow::Cow;
+use std::sync::Arc;
+use serde_json::{Map, Value, json};
+use rmcp::Error as McpError;
+use rmcp::model::{ErrorCode, Tool, ToolAnnotations};
+
+pub const LIST_PROTOCOLS: &str = "list-protocols";
+pub const LIST_TRANSACTIONS: &str = "list-transactions";
+pub const DESCRIBE_TRANSACTION: &str = "describe-transaction";
+pub const RESOLVE_TRANSACTION: &str = "resolve-transaction";
+pub const GET_TIR: &str = "get-tir";
+pub const VALIDATE_TX3: &str = "validate-tx3";
+
+fn schema(value: Value) -> Arc<Map<String, Value>> {
+    match value {
+        Value::Object(schema) => Arc::new(schema),
+        _ => Arc::default(),
+    }
+}
+
+fn annotations(title: &str, idempotent: bool) -> Option<ToolAnnotations> {
+    Some(ToolAnnotations {
+        title: Some(title.to_string()),
+        read_only_hint: Some(true),
+        destructive_hint: Some(false),
+        idempotent_hint: Some(idempotent),
+        open_world_hint: Some(true),
+    })
+}
+
+/// The fixed tool set exposed with `TOOL_MODE=generic`, independent of how many
+/// protocols the registries publish.
+pub fn tools() -> Vec<Tool> {
+    vec![
+        Tool {
+            name: Cow::Borrowed(LIST_PROTOCOLS),
+            description: Some(Cow::Borrowed(
+                "Lists the available tx3 protocols with their scope, version, description, transactions \
+                 and source registry. Start here, then call list-transactions with one of the returned \
+                 protocol names. Results are paginated: pass next_offset back as offset to continue.",
+            )),
+            annotations: annotations("List protocols", true),
+            input_schema: schema(json!({
+                "type": "object",
+                "properties": {
+                    "filter": { "type": "string", "description": "Only protocols whose name contains this text" },
+                    "offset": { "type": "integer", "minimum": 0, "description": "Index of the first protocol to return" },
+                },
+            })),
+        },
+        Tool {
+            name: Cow::Borrowed(LIST_TRANSACTIONS),
+            description: Some(Cow::Borrowed(
+                "Lists the transactions of a tx3 protocol with the JSON schema of each parameter \
+                 and which ones are required.",
+            )),
+            annotations: annotations("List transactions", true),
+            input_schema: schema(json!({
+                "type": "object",
+                "properties": {
+                    "protocol": { "type": "string", "description": "Protocol name as returned by list-protocols" },
+                },
+                "required": ["protocol"],
+            })),
+        },
+        Tool {
+            name: Cow::Borrowed(DESCRIBE_TRANSACTION),
+            description: Some(Cow::Borrowed(
+                "Describes a transaction of a tx3 protocol and its parameters. \
+                 Use the result to build the args object for resolve-transaction.",
+            )),
+            annotations: annotations("Describe transaction", true),
+            input_schema: schema(json!({
+                "type": "object",
+                "properties": {
+                    "protocol": { "type": "string", "description": "Protocol name as returned by list-protocols" },
+                    "transaction": { "type": "string", "description": "Transaction name as returned by list-transactions" },
+                },
+                "required": ["protocol", "transaction"],
+            })),
+        },
+        Tool {
+            name: Cow::Borrowed(RESOLVE_TRANSACTION),
+            description: Some(Cow::Borrowed(
+                "Resolves a transaction of a tx3 protocol into a CBOR transaction ready to be signed. \
+                 Pass the parameters listed by describe-transaction in args.",
+            )),
+            annotations: annotations("Resolve transaction", false),
+            input_schema: schema(json!({
+                "type": "object",
+                "properties": {
+                    "protocol": { "type": "string", "description": "Protocol name as returned by list-protocols" },
+                    "transaction": { "type": "string", "description": "Transaction name as returned by list-transactions" },
+                    "args": { "type": "object", "description": "Transaction parameters keyed by name" },
+                },
+                "required": ["protocol", "transaction"],
+            })),
+        },
+    ]
+}
+
+/// Returns the TIR of a transaction without contacting TRP, listed in every tool mode.
+pub fn get_tir_tool() -> Tool {
+    Tool {
+        name: Cow::Borrowed(GET_TIR),
+        description: Some(Cow::Borrowed(
+            "Returns the compiled TIR of a transaction, exactly as the server sends it to TRP. \
+             Useful for debugging or for calling TRP directly.",
+        )),
+        annotations: annotations("Get TIR", true),
+        input_schema: schema(json!({
+            "type": "object",
+            "properties": {
+                "protocol": { "type": "string", "description": "Protocol name" },
+                "transaction": { "type": "string", "description": "Transaction name" },
+            },
+            "required": ["protocol", "transaction"],
+        })),
+    }
+}
+
+/// Compiles tx3 source supplied by the client, listed in every tool mode.
+pub fn validate_tx3_tool() -> Tool {
+    Tool {
+        name: Cow::Borrowed(VALIDATE_TX3),
+        description: Some(Cow::Borrowed(
+            "Compiles tx3 source without publishing it and returns either its transactions with \
+             their parameter schemas, or the compiler diagnostics.",
+        )),
+        annotations: Some(ToolAnnotations {
+            title: Some("Validate tx3".to_string()),
+            read_only_hint: Some(true),
+            destructive_hint: Some(false),
+            idempotent_hint: Some(true),
+            open_world_hint: Some(false),
+        }),
+        input_schema: schema(json!({
+            "type": "object",
+            "properties": {
+                "source": { "type": "string", "description": "Content of a .tx3 file" },
+            },
+            "required": ["source"],
+        })),
+    }
+}
+
+/// Reads a required string argument of a generic tool.
+pub fn string_arg<'a>(arguments: &'a Map<String, Value>, name: &str) -> Result<&'a str, McpError> {
+    arguments.get(name).and_then(Value::as_str).ok_or_else(|| {
+        McpError::new(
+            ErrorCode::INVALID_PARAMS,
+            format!("Missing string argument {}", name),
+            None,
+        )
+    })
+}
+
+/// Reads an optional string argument.
+pub fn optional_string_arg<'a>(arguments: &'a Map<String, Value>, name: &str) -> Option<&'a str> {
+    arguments.get(name).and_then(Value::as_str)
+}
+
+/// Reads an optional non-negative integer argument.
+pub fn optional_usize_arg(arguments: &Map<String, Value>, name: &str) -> Result<Option<usize>, McpError> {
+    match arguments.get(name) {
+        None | Some(Value::Null) => Ok(None),
+        Some(value) => value.as_u64().map(|value| Some(value as usize)).ok_or_else(|| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Argument {} must be a non-negative integer", name),
+                None,
+            )
+        }),
+    }
+}
+
+/// Reads the optional `args` object of `resolve-transaction`.
+pub fn args_arg(arguments: &Map<String, Value>) -> Result<Map<String, Value>, McpError> {
+    match arguments.get("args") {
+        None | Some(Value::Null) => Ok(Map::new()),
+        Some(Value::Object(args)) => Ok(args.clone()),
+        Some(_) => Err(McpError::new(
+            ErrorCode::INVALID_PARAMS,
+            "Argument args must be an object".to_string(),
+            None,
+        )),
+    }
+}
@@ -0,0 +1,63 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+use serde_json::{Value, json};
+
+#[derive(Default)]
+struct HealthState {
+    consecutive_failures: u32,
+    failing_since: Option<Instant>,
+    last_success: Option<Instant>,
+    last_success_at: Option<SystemTime>,
+    last_error: Option<String>,
+    last_latency: Option<Duration>,
+}
+
+/// Outcome of the latest calls to an upstream service, updated as calls complete so
+/// health checks never have to hit the network.
+#[derive(Default)]
+pub struct UpstreamHealth {
+    state: Mutex<HealthState>,
+}
+
+impl UpstreamHealth {
+    pub fn record_success(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures = 0;
+        state.failing_since = None;
+        state.last_success = Some(Instant::now());
+        state.last_success_at = Some(SystemTime::now());
+    }
+
+    /// Records how long the latest completed call took, successful or not.
+    pub fn record_latency(&self, latency: Duration) {
+        self.state.lock().unwrap().last_latency = Some(latency);
+    }
+
+    /// Records a failure and returns the number of consecutive failures so far.
+    pub fn record_failure(&self, error: &str) -> u32 {
+        let mut state = self.state.lock().unwrap();
+        state.consecutive_failures += 1;
+        state.failing_since.get_or_insert_with(Instant::now);
+        state.last_error = Some(error.to_string());
+        state.consecutive_failures
+    }
+
+    /// How long the upstream has been failing without a success in between.
+    pub fn failing_for(&self) -> Option<Duration> {
+        self.state.lock().unwrap().failing_since.map(|since| since.elapsed())
+    }
+
+    pub fn status(&self) -> Value {
+        let state = self.state.lock().unwrap();
+        json!({
+            "consecutive_failures": state.consecutive_failures,
+            "failing_for_secs": state.failing_since.map(|since| since.elapsed().as_secs()),
+            "last_success_secs_ago": state.last_success.map(|at| at.elapsed().as_secs()),
+            "last_success_at": state.last_success_at
+                .and_then(|at| at.duration_since(SystemTime::UNIX_EPOCH).ok())
+                .map(|since_epoch| since_epoch.as_secs()),
+            "last_error": state.last_error,
+            "last_latency_ms": state.last_latency.map(|latency| latency.as_millis() as u64),
+        })
+    }
+}
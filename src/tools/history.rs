@@ -0,0 +1,158 @@
+use std::collections::VecDeque;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+use serde::Serialize;
+
+/// Resolutions kept in memory for the history resource.
+pub const DEFAULT_CAPACITY: usize = 100;
+
+/// One TRP resolve, successful or not. Parameter values are left out, only their names
+/// are recorded.
+#[derive(Clone, Serialize)]
+pub struct Resolution {
+    /// Unix seconds.
+    pub timestamp: u64,
+    pub protocol: String,
+    pub transaction: String,
+    pub parameters: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tx_hash: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl Resolution {
+    pub fn new(protocol: &str, transaction: &str, mut parameters: Vec<String>, client: Option<&str>) -> Self {
+        parameters.sort();
+        Self {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|since| since.as_secs()).unwrap_or_default(),
+            protocol: protocol.to_string(),
+            transaction: transaction.to_string(),
+            parameters,
+            client: client.map(str::to_string),
+            tx_hash: None,
+            error: None,
+        }
+    }
+}
+
+/// The most recent resolutions across every session, optionally appended to an audit
+/// log as JSON lines. Each entry is kept with its owner, the client or session that made
+/// it, and only read back by that owner. A history with no capacity and no log records
+/// nothing.
+pub struct History {
+    capacity: usize,
+    entries: Mutex<VecDeque<(String, Resolution)>>,
+    audit_log: Option<Mutex<PathBuf>>,
+}
+
+impl Default for History {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, None)
+    }
+}
+
+impl History {
+    pub fn new(capacity: usize, audit_log: Option<PathBuf>) -> Self {
+        Self {
+            capacity,
+            entries: Mutex::new(VecDeque::new()),
+            audit_log: audit_log.map(Mutex::new),
+        }
+    }
+
+    /// Records `resolution` for `owner`. The audit log gets every resolution, whoever
+    /// made it.
+    pub fn record(&self, owner: &str, resolution: Resolution) {
+        if let Some(path) = &self.audit_log {
+            // Held while appending so concurrent records don't interleave.
+            let path = path.lock().unwrap();
+            let appended = serde_json::to_string(&resolution)
+                .map_err(std::io::Error::from)
+                .and_then(|line| {
+                    let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&*path)?;
+                    writeln!(file, "{}", line)
+                });
+            if let Err(err) = appended {
+                tracing::warn!("Failed to append to audit log {}: {}", path.display(), err);
+            }
+        }
+
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((owner.to_string(), resolution));
+    }
+
+    /// Resolutions recorded for `owner`, newest first.
+    pub fn entries(&self, owner: &str) -> Vec<Resolution> {
+        self.entries.lock().unwrap().iter().rev()
+            .filter(|(recorded_by, _)| recorded_by == owner)
+            .map(|(_, resolution)| resolution.clone())
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resolution(transaction: &str) -> Resolution {
+        Resolution::new("acme", transaction, vec!["quantity".to_string()], None)
+    }
+
+    fn transactions(entries: Vec<Resolution>) -> Vec<String> {
+        entries.into_iter().map(|entry| entry.transaction).collect()
+    }
+
+    #[test]
+    fn entries_only_return_the_owners_resolutions() {
+        let history = History::new(10, None);
+        history.record("client:alice", resolution("swap"));
+        history.record("client:bob", resolution("mint"));
+        history.record("client:alice", resolution("burn"));
+
+        assert_eq!(transactions(history.entries("client:alice")), ["burn", "swap"]);
+        assert_eq!(transactions(history.entries("client:bob")), ["mint"]);
+        assert!(history.entries("session:7").is_empty());
+    }
+
+    #[test]
+    fn oldest_entries_are_dropped_at_capacity() {
+        let history = History::new(2, None);
+        history.record("session:1", resolution("swap"));
+        history.record("session:1", resolution("mint"));
+        history.record("session:1", resolution("burn"));
+
+        assert_eq!(transactions(history.entries("session:1")), ["burn", "mint"]);
+    }
+
+    #[test]
+    fn no_capacity_records_nothing() {
+        let history = History::new(0, None);
+        history.record("session:1", resolution("swap"));
+
+        assert!(history.entries("session:1").is_empty());
+    }
+
+    #[test]
+    fn audit_log_gets_every_owner() {
+        let path = std::env::temp_dir().join(format!("tx3-mcp-audit-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let history = History::new(0, Some(path.clone()));
+        history.record("client:alice", resolution("swap"));
+        history.record("client:bob", resolution("mint"));
+
+        let log = std::fs::read_to_string(&path).unwrap();
+        let _ = std::fs::remove_file(&path);
+        assert_eq!(log.lines().count(), 2);
+    }
+}
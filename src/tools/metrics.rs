@@ -0,0 +1,180 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds, in seconds, of the duration histogram buckets.
+const BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+#[derive(Clone, Default)]
+struct Histogram {
+    counts: [u64; BUCKETS.len()],
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, duration: Duration) {
+        let secs = duration.as_secs_f64();
+        for (count, bound) in self.counts.iter_mut().zip(BUCKETS) {
+            if secs <= bound {
+                *count += 1;
+            }
+        }
+        self.count += 1;
+        self.sum += secs;
+    }
+
+    fn render(&self, out: &mut String, name: &str, labels: &str) {
+        let separator = if labels.is_empty() { "" } else { "," };
+        for (count, bound) in self.counts.iter().zip(BUCKETS) {
+            let _ = writeln!(out, "{}_bucket{{{}{}le=\"{}\"}} {}", name, labels, separator, bound, count);
+        }
+        let _ = writeln!(out, "{}_bucket{{{}{}le=\"+Inf\"}} {}", name, labels, separator, self.count);
+        let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, self.sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, self.count);
+    }
+}
+
+#[derive(Default)]
+struct MetricsState {
+    resolves: BTreeMap<(String, String), Histogram>,
+    trp_errors: BTreeMap<(String, String, String), u64>,
+    registry_fetches: Histogram,
+    registry_errors: u64,
+}
+
+/// Counters and histograms of the TRP and registry calls, rendered in the Prometheus
+/// text format. Labels carry protocol and transaction names, never argument values.
+#[derive(Default)]
+pub struct Metrics {
+    state: Mutex<MetricsState>,
+}
+
+impl Metrics {
+    pub fn record_resolve(&self, protocol: &str, transaction: &str, duration: Duration) {
+        let mut state = self.state.lock().unwrap();
+        state.resolves
+            .entry((protocol.to_string(), transaction.to_string()))
+            .or_default()
+            .observe(duration);
+    }
+
+    /// Counts a failed resolve under an error category such as `timeout` or `rejected`.
+    pub fn record_trp_error(&self, protocol: &str, transaction: &str, kind: &str) {
+        let mut state = self.state.lock().unwrap();
+        *state.trp_errors
+            .entry((protocol.to_string(), transaction.to_string(), kind.to_string()))
+            .or_default() += 1;
+    }
+
+    pub fn record_registry_fetch(&self, duration: Duration, success: bool) {
+        let mut state = self.state.lock().unwrap();
+        state.registry_fetches.observe(duration);
+        if !success {
+            state.registry_errors += 1;
+        }
+    }
+
+    pub fn render(&self, compile_cache_hits: u64, compile_cache_misses: u64, active_sessions: usize) -> String {
+        let state = self.state.lock().unwrap();
+        let mut out = String::new();
+
+        out.push_str("# HELP tx3_mcp_resolve_duration_seconds Duration of TRP resolves, including retries.\n");
+        out.push_str("# TYPE tx3_mcp_resolve_duration_seconds histogram\n");
+        for ((protocol, transaction), histogram) in state.resolves.iter() {
+            let labels = format!("protocol=\"{}\",transaction=\"{}\"", escape(protocol), escape(transaction));
+            histogram.render(&mut out, "tx3_mcp_resolve_duration_seconds", &labels);
+        }
+
+        out.push_str("# HELP tx3_mcp_trp_errors_total Failed TRP resolves by error category.\n");
+        out.push_str("# TYPE tx3_mcp_trp_errors_total counter\n");
+        for ((protocol, transaction, kind), count) in state.trp_errors.iter() {
+            let _ = writeln!(
+                out,
+                "tx3_mcp_trp_errors_total{{protocol=\"{}\",transaction=\"{}\",kind=\"{}\"}} {}",
+                escape(protocol),
+                escape(transaction),
+                escape(kind),
+                count,
+            );
+        }
+
+        out.push_str("# HELP tx3_mcp_registry_fetch_duration_seconds Duration of registry refreshes.\n");
+        out.push_str("# TYPE tx3_mcp_registry_fetch_duration_seconds histogram\n");
+        state.registry_fetches.render(&mut out, "tx3_mcp_registry_fetch_duration_seconds", "");
+
+        out.push_str("# HELP tx3_mcp_registry_errors_total Failed registry refreshes.\n");
+        out.push_str("# TYPE tx3_mcp_registry_errors_total counter\n");
+        let _ = writeln!(out, "tx3_mcp_registry_errors_total {}", state.registry_errors);
+
+        out.push_str("# HELP tx3_mcp_compile_cache_hits_total Compiled protocols served from the cache.\n");
+        out.push_str("# TYPE tx3_mcp_compile_cache_hits_total counter\n");
+        let _ = writeln!(out, "tx3_mcp_compile_cache_hits_total {}", compile_cache_hits);
+        out.push_str("# HELP tx3_mcp_compile_cache_misses_total Protocols compiled on a cache miss.\n");
+        out.push_str("# TYPE tx3_mcp_compile_cache_misses_total counter\n");
+        let _ = writeln!(out, "tx3_mcp_compile_cache_misses_total {}", compile_cache_misses);
+
+        out.push_str("# HELP tx3_mcp_active_sessions Connected MCP sessions.\n");
+        out.push_str("# TYPE tx3_mcp_active_sessions gauge\n");
+        let _ = writeln!(out, "tx3_mcp_active_sessions {}", active_sessions);
+
+        out
+    }
+}
+
+fn escape(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolves_are_bucketed_by_duration() {
+        let metrics = Metrics::default();
+        metrics.record_resolve("acme", "swap", Duration::from_millis(200));
+        metrics.record_resolve("acme", "swap", Duration::from_secs(2));
+        let out = metrics.render(0, 0, 0);
+
+        let labels = "protocol=\"acme\",transaction=\"swap\"";
+        assert!(out.contains(&format!("tx3_mcp_resolve_duration_seconds_bucket{{{},le=\"0.1\"}} 0", labels)), "{}", out);
+        assert!(out.contains(&format!("tx3_mcp_resolve_duration_seconds_bucket{{{},le=\"0.25\"}} 1", labels)), "{}", out);
+        assert!(out.contains(&format!("tx3_mcp_resolve_duration_seconds_bucket{{{},le=\"2.5\"}} 2", labels)), "{}", out);
+        assert!(out.contains(&format!("tx3_mcp_resolve_duration_seconds_bucket{{{},le=\"+Inf\"}} 2", labels)), "{}", out);
+        assert!(out.contains(&format!("tx3_mcp_resolve_duration_seconds_count{{{}}} 2", labels)), "{}", out);
+    }
+
+    #[test]
+    fn errors_are_counted_by_kind() {
+        let metrics = Metrics::default();
+        metrics.record_trp_error("acme", "swap", "timeout");
+        metrics.record_trp_error("acme", "swap", "timeout");
+        metrics.record_trp_error("acme", "swap", "rejected");
+        let out = metrics.render(0, 0, 0);
+
+        assert!(out.contains("tx3_mcp_trp_errors_total{protocol=\"acme\",transaction=\"swap\",kind=\"timeout\"} 2"), "{}", out);
+        assert!(out.contains("tx3_mcp_trp_errors_total{protocol=\"acme\",transaction=\"swap\",kind=\"rejected\"} 1"), "{}", out);
+    }
+
+    #[test]
+    fn registry_and_gauges_are_rendered() {
+        let metrics = Metrics::default();
+        metrics.record_registry_fetch(Duration::from_millis(30), true);
+        metrics.record_registry_fetch(Duration::from_millis(30), false);
+        let out = metrics.render(7, 3, 2);
+
+        assert!(out.contains("tx3_mcp_registry_fetch_duration_seconds_count 2\n"), "{}", out);
+        assert!(out.contains("tx3_mcp_registry_errors_total 1\n"), "{}", out);
+        assert!(out.contains("tx3_mcp_compile_cache_hits_total 7\n"), "{}", out);
+        assert!(out.contains("tx3_mcp_compile_cache_misses_total 3\n"), "{}", out);
+        assert!(out.contains("tx3_mcp_active_sessions 2\n"), "{}", out);
+    }
+
+    #[test]
+    fn label_values_are_escaped() {
+        assert_eq!(escape("a\"b\\c\nd"), "a\\\"b\\\\c\\nd");
+    }
+}
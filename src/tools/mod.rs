@@ -1 +1,27 @@
-pub mod protocol;
\ No newline at end of file
+pub mod address;
+pub mod breaker;
+pub mod check;
+pub mod cli;
+pub mod compiler;
+pub mod config;
+pub mod config_file;
+pub mod decode;
+pub mod generic;
+pub mod health;
+pub mod history;
+pub mod metrics;
+pub mod params;
+pub mod prompts;
+pub mod ratelimit;
+pub mod recent;
+pub mod protocol;
+pub mod registry;
+pub mod resolver;
+pub mod resources;
+pub mod retry;
+pub mod roots;
+pub mod secret;
+pub mod server;
+pub mod snapshot;
+pub mod submit;
+pub mod trp;
@@ -0,0 +1,383 @@
+use base64::prelude::*;
+use std::num::IntErrorKind;
+use serde_json::{Value, json};
+use rmcp::Error as McpError;
+use rmcp::model::ErrorCode;
+use tx3_lang::{ArgValue, UtxoRef};
+use tx3_lang::ir::Type;
+
+use super::address::{self, Network};
+
+/// Hex with an even number of digits and an optional `0x` prefix, mirrored by `decode_bytes`.
+const HEX_PATTERN: &str = "^(0x)?([0-9a-fA-F]{2})+$";
+
+/// Decodes a Bytes argument as hex, falling back to standard base64 for strings that
+/// aren't valid hex.
+fn decode_bytes(value: &str) -> Option<Vec<u8>> {
+    let value = value.trim();
+    let hex = value.strip_prefix("0x").unwrap_or(value);
+    if !hex.is_empty() {
+        if let Ok(bytes) = hex::decode(hex) {
+            return Some(bytes);
+        }
+    }
+
+    BASE64_STANDARD.decode(value).ok().filter(|bytes| !bytes.is_empty())
+}
+
+/// JSON schema advertised for a transaction parameter of the given tx3 type. The same
+/// mapping drives `coerce_arg`, so anything valid against the schema is accepted.
+pub fn param_schema(ty: &Type) -> Value {
+    match ty {
+        Type::Int => json!({
+            "type": ["integer", "string"],
+            "pattern": "^-?[0-9]+$",
+            "description": "Integer; pass values beyond 2^53 as a decimal string to keep their precision",
+        }),
+        Type::Bool => json!({
+            "type": "boolean",
+        }),
+        Type::Bytes => json!({
+            "type": "string",
+            "pattern": HEX_PATTERN,
+            "contentEncoding": "base16",
+            "description": "Hex encoded bytes, two digits per byte with an optional 0x prefix. Base64 is accepted as a fallback",
+        }),
+        Type::Address => json!({
+            "type": "string",
+            "pattern": "^((addr|addr_test|stake|stake_test)1[02-9ac-hj-np-z]+|(0x)?([0-9a-fA-F]{2})+)$",
+            "description": "Bech32 encoded Cardano address, or its raw bytes as hex",
+        }),
+        Type::UtxoRef => json!({
+            "oneOf": [
+                {
+                    "type": "string",
+                    "pattern": UTXO_REF_PATTERN,
+                    "description": "UTxO reference as {tx_hash}#{index}",
+                },
+                {
+                    "type": "object",
+                    "properties": {
+                        "hash": { "type": "string", "pattern": "^[0-9a-fA-F]{64}$" },
+                        "index": { "type": "integer", "minimum": 0 },
+                    },
+                    "required": ["hash", "index"],
+                },
+            ],
+        }),
+        _ => json!({
+            "type": "string",
+        }),
+    }
+}
+
+const UTXO_REF_PATTERN: &str = "^[0-9a-fA-F]{64}#[0-9]+$";
+
+/// Parses a UTxO reference given as `"{tx_hash}#{index}"` or `{"hash", "index"}`.
+fn parse_utxo_ref(value: &Value) -> Result<UtxoRef, String> {
+    let (hash, index) = match value {
+        Value::String(string) => {
+            let (hash, index) = string.trim().split_once('#').ok_or("expected {tx_hash}#{index}")?;
+            let index = index.parse::<u64>().map_err(|_| format!("index {} is not a number", index))?;
+            (hash, index)
+        }
+        Value::Object(object) => {
+            let hash = object.get("hash").and_then(Value::as_str).ok_or("missing string field hash")?;
+            let index = object.get("index").and_then(Value::as_u64).ok_or("missing non-negative integer field index")?;
+            (hash, index)
+        }
+        _ => return Err("expected a string or an object".to_string()),
+    };
+
+    let txid = hex::decode(hash).map_err(|_| format!("transaction hash {} is not hex", hash))?;
+    if txid.len() != 32 {
+        return Err(format!("transaction hash must be 32 bytes, got {}", txid.len()));
+    }
+    let index = u32::try_from(index).map_err(|_| format!("index {} is out of range", index))?;
+
+    Ok(UtxoRef { txid, index })
+}
+
+/// Integer value of a JSON number. Whole floats such as `5e6` are accepted since JSON
+/// schema treats them as integers, but only within the range floats represent exactly.
+fn number_to_int(number: &serde_json::Number) -> Result<i128, &'static str> {
+    if let Some(int) = number.as_i64() {
+        return Ok(int as i128);
+    }
+    if let Some(int) = number.as_u64() {
+        return Ok(int as i128);
+    }
+
+    let float = number.as_f64().ok_or("not a number")?;
+    if float.fract() != 0.0 {
+        return Err("has a fractional part");
+    }
+    if float.abs() >= 2f64.powi(53) {
+        return Err("is too large to be exact as a JSON number, pass it as a string");
+    }
+    Ok(float as i128)
+}
+
+fn parse_int(value: &str) -> Result<i128, &'static str> {
+    value.trim().parse::<i128>().map_err(|err| match err.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => "is out of the 128-bit integer range",
+        _ => "is not an integer",
+    })
+}
+
+/// Characters of an offending argument value echoed back in error messages.
+const MAX_SHOWN_VALUE_LEN: usize = 64;
+
+/// Converts a JSON argument into the tx3 value expected by the parameter type.
+pub fn coerce_arg(name: &str, ty: &Type, value: &Value, network: Option<Network>) -> Result<ArgValue, McpError> {
+    let mut shown = value.to_string();
+    if shown.chars().count() > MAX_SHOWN_VALUE_LEN {
+        shown = shown.chars().take(MAX_SHOWN_VALUE_LEN).collect::<String>() + "...";
+    }
+
+    let invalid = || {
+        McpError::new(
+            ErrorCode::INVALID_PARAMS,
+            format!("Invalid value for parameter {}: expected {:?}, got {}", name, ty, shown),
+            None,
+        )
+    };
+
+    let invalid_int = |reason: &str| {
+        McpError::new(
+            ErrorCode::INVALID_PARAMS,
+            format!("Invalid value for parameter {}: {} {}", name, shown, reason),
+            None,
+        )
+    };
+
+    match (ty, value) {
+        (Type::Int, Value::Number(number)) => number_to_int(number).map(ArgValue::Int).map_err(invalid_int),
+        (Type::Int, Value::String(string)) => parse_int(string).map(ArgValue::Int).map_err(invalid_int),
+        (Type::Bool, Value::Bool(bool)) => Ok(ArgValue::Bool(*bool)),
+        (Type::Bool, Value::String(string)) => string.trim().parse::<bool>().map(ArgValue::Bool).map_err(|_| invalid()),
+        (Type::Bytes, value) => value.as_str().and_then(decode_bytes).map(ArgValue::Bytes).ok_or_else(|| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Parameter {} must be hex encoded bytes matching {}, or base64", name, HEX_PATTERN),
+                None,
+            )
+        }),
+        (Type::Address, Value::String(string)) => address::parse_address(string, network).map(ArgValue::Address).map_err(|reason| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Invalid address for parameter {}: {}", name, reason),
+                None,
+            )
+        }),
+        (Type::UtxoRef, value) => parse_utxo_ref(value).map(ArgValue::UtxoRef).map_err(|reason| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Invalid UTxO reference for parameter {}: {}", name, reason),
+                None,
+            )
+        }),
+        _ => Err(invalid()),
+    }
+}
+
+/// Byte values longer than this are echoed as a prefix plus their length.
+const MAX_ECHOED_BYTES: usize = 64;
+
+/// JSON rendering of a coerced argument, echoed back in resolve results so callers can
+/// audit what was sent to TRP.
+pub fn echo_arg(ty: &Type, value: &ArgValue) -> Value {
+    let value = match value {
+        ArgValue::Int(int) => json!(int.to_string()),
+        ArgValue::Bool(bool) => json!(bool),
+        ArgValue::String(string) => json!(string),
+        ArgValue::Bytes(bytes) | ArgValue::Address(bytes) if bytes.len() > MAX_ECHOED_BYTES => {
+            json!(format!("{}... ({} bytes)", hex::encode(&bytes[..MAX_ECHOED_BYTES]), bytes.len()))
+        }
+        ArgValue::Bytes(bytes) | ArgValue::Address(bytes) => json!(hex::encode(bytes)),
+        ArgValue::UtxoRef(utxo_ref) => json!(format!("{}#{}", hex::encode(&utxo_ref.txid), utxo_ref.index)),
+        other => json!(format!("{:?}", other)),
+    };
+
+    json!({
+        "type": format!("{:?}", ty),
+        "value": value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_types_follow_the_tx3_type() {
+        assert_eq!(param_schema(&Type::Int)["type"], json!(["integer", "string"]));
+        assert_eq!(param_schema(&Type::Bool)["type"], "boolean");
+        assert_eq!(param_schema(&Type::Bytes)["type"], "string");
+        assert_eq!(param_schema(&Type::Address)["type"], "string");
+        assert_eq!(param_schema(&Type::UtxoRef)["oneOf"].as_array().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn schema_patterns_are_anchored() {
+        for ty in [Type::Int, Type::Bytes, Type::Address] {
+            let pattern = param_schema(&ty)["pattern"].as_str().unwrap().to_string();
+            assert!(pattern.starts_with('^') && pattern.ends_with('$'), "{:?} pattern {}", ty, pattern);
+        }
+    }
+
+    fn coerce(ty: Type, value: Value) -> Result<ArgValue, McpError> {
+        coerce_arg("param", &ty, &value, None)
+    }
+
+    fn int(value: Value) -> i128 {
+        match coerce(Type::Int, value) {
+            Ok(ArgValue::Int(int)) => int,
+            Ok(_) => panic!("Int should coerce to ArgValue::Int"),
+            Err(err) => panic!("{}", err.message),
+        }
+    }
+
+    #[test]
+    fn int_accepts_json_numbers() {
+        assert_eq!(int(json!(42)), 42);
+        assert_eq!(int(json!(-7)), -7);
+        assert_eq!(int(json!(u64::MAX)), u64::MAX as i128);
+        assert_eq!(int(json!(5e6)), 5_000_000);
+    }
+
+    #[test]
+    fn int_rejects_fractions() {
+        assert!(coerce(Type::Int, json!(1.5)).is_err());
+    }
+
+    #[test]
+    fn int_accepts_padded_strings() {
+        assert_eq!(int(json!(" 42 ")), 42);
+    }
+
+    #[test]
+    fn bool_accepts_booleans_and_strings() {
+        assert!(matches!(coerce(Type::Bool, json!(true)), Ok(ArgValue::Bool(true))));
+        assert!(matches!(coerce(Type::Bool, json!("false")), Ok(ArgValue::Bool(false))));
+        assert!(coerce(Type::Bool, json!("yes")).is_err());
+        assert!(coerce(Type::Bool, json!(1)).is_err());
+    }
+
+    fn bytes(value: Value) -> Vec<u8> {
+        match coerce(Type::Bytes, value) {
+            Ok(ArgValue::Bytes(bytes)) => bytes,
+            Ok(_) => panic!("Bytes should coerce to ArgValue::Bytes"),
+            Err(err) => panic!("{}", err.message),
+        }
+    }
+
+    #[test]
+    fn bytes_accept_hex_with_or_without_prefix() {
+        assert_eq!(bytes(json!("cafe")), [0xca, 0xfe]);
+        assert_eq!(bytes(json!("0xCAFE")), [0xca, 0xfe]);
+    }
+
+    #[test]
+    fn bytes_fall_back_to_base64() {
+        assert_eq!(bytes(json!("aGVsbG8=")), b"hello");
+    }
+
+    #[test]
+    fn bytes_reject_empty_and_garbage() {
+        assert!(coerce(Type::Bytes, json!("")).is_err());
+        assert!(coerce(Type::Bytes, json!("0x")).is_err());
+        assert!(coerce(Type::Bytes, json!("not bytes!")).is_err());
+        assert!(coerce(Type::Bytes, json!(12)).is_err());
+    }
+
+    fn assert_invalid_params(result: Result<ArgValue, McpError>) -> String {
+        let err = result.err().expect("coercion should fail");
+        assert_eq!(err.code.0, ErrorCode::INVALID_PARAMS.0);
+        err.message.to_string()
+    }
+
+    #[test]
+    fn bad_values_are_invalid_params() {
+        let message = assert_invalid_params(coerce(Type::Int, json!("twelve")));
+        assert!(message.contains("param"), "{}", message);
+        assert_invalid_params(coerce(Type::Int, json!(true)));
+        assert_invalid_params(coerce(Type::Bool, json!(null)));
+    }
+
+    #[test]
+    fn long_values_are_cut_in_errors() {
+        let message = assert_invalid_params(coerce(Type::Int, json!("x".repeat(500))));
+        assert!(message.len() < 200, "{}", message);
+        assert!(message.contains("..."), "{}", message);
+    }
+
+    #[test]
+    fn int_strings_cover_the_i128_range() {
+        assert_eq!(int(json!(i128::MAX.to_string())), i128::MAX);
+        assert_eq!(int(json!(i128::MIN.to_string())), i128::MIN);
+        assert_eq!(int(json!("18446744073709551616")), 1i128 << 64);
+    }
+
+    #[test]
+    fn int_overflow_is_reported() {
+        let message = assert_invalid_params(coerce(Type::Int, json!("170141183460469231731687303715884105728")));
+        assert!(message.contains("out of the 128-bit integer range"), "{}", message);
+    }
+
+    #[test]
+    fn inexact_json_numbers_must_be_strings() {
+        let message = assert_invalid_params(coerce(Type::Int, json!(1e20)));
+        assert!(message.contains("pass it as a string"), "{}", message);
+    }
+
+    const HASH: &str = "8f1a2b3c4d5e6f708192a3b4c5d6e7f8091a2b3c4d5e6f708192a3b4c5d6e7f8";
+
+    fn utxo_ref(value: Value) -> UtxoRef {
+        match coerce(Type::UtxoRef, value) {
+            Ok(ArgValue::UtxoRef(utxo_ref)) => utxo_ref,
+            Ok(_) => panic!("UtxoRef should coerce to ArgValue::UtxoRef"),
+            Err(err) => panic!("{}", err.message),
+        }
+    }
+
+    #[test]
+    fn utxo_ref_accepts_a_string() {
+        let parsed = utxo_ref(json!(format!("{}#3", HASH)));
+        assert_eq!(hex::encode(parsed.txid), HASH);
+        assert_eq!(parsed.index, 3);
+    }
+
+    #[test]
+    fn utxo_ref_accepts_an_object() {
+        let parsed = utxo_ref(json!({ "hash": HASH, "index": 0 }));
+        assert_eq!(hex::encode(parsed.txid), HASH);
+        assert_eq!(parsed.index, 0);
+    }
+
+    #[test]
+    fn utxo_ref_rejects_malformed_references() {
+        assert_invalid_params(coerce(Type::UtxoRef, json!(HASH)));
+        assert_invalid_params(coerce(Type::UtxoRef, json!("abcd#0")));
+        assert_invalid_params(coerce(Type::UtxoRef, json!(format!("{}#x", HASH))));
+        assert_invalid_params(coerce(Type::UtxoRef, json!(format!("{}#4294967296", HASH))));
+        assert_invalid_params(coerce(Type::UtxoRef, json!({ "hash": HASH, "index": -1 })));
+        assert_invalid_params(coerce(Type::UtxoRef, json!({ "index": 0 })));
+    }
+
+    #[test]
+    fn echo_renders_values_the_way_they_are_accepted() {
+        assert_eq!(echo_arg(&Type::Int, &ArgValue::Int(i128::MAX))["value"], i128::MAX.to_string());
+        assert_eq!(echo_arg(&Type::Bool, &ArgValue::Bool(true))["value"], true);
+        assert_eq!(echo_arg(&Type::Bytes, &ArgValue::Bytes(vec![0xca, 0xfe]))["value"], "cafe");
+        let utxo_ref = ArgValue::UtxoRef(UtxoRef { txid: hex::decode(HASH).unwrap(), index: 1 });
+        assert_eq!(echo_arg(&Type::UtxoRef, &utxo_ref)["value"], format!("{}#1", HASH));
+        assert_eq!(echo_arg(&Type::Int, &ArgValue::Int(1))["type"], "Int");
+    }
+
+    #[test]
+    fn echo_cuts_long_bytes() {
+        let echoed = echo_arg(&Type::Bytes, &ArgValue::Bytes(vec![0; 100]));
+        assert_eq!(echoed["value"], format!("{}... (100 bytes)", "00".repeat(MAX_ECHOED_BYTES)));
+    }
+}
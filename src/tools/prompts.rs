@@ -0,0 +1,141 @@
+use serde_json::{Map, Value};
+use rmcp::model::{GetPromptResult, Prompt, PromptArgument, PromptMessage, PromptMessageRole};
+use tx3_lang::ir::Type;
+
+use super::compiler::CompiledTx;
+use super::params;
+
+/// How the model should gather a value of each tx3 type, with an example.
+fn guidance(ty: &Type) -> (&'static str, &'static str) {
+    match ty {
+        Type::Int => ("an integer, as a JSON number or a decimal string for values beyond 2^53", "1000000"),
+        Type::Bool => ("true or false", "true"),
+        Type::Bytes => ("hex encoded bytes", "\"0x48656c6c6f\""),
+        Type::Address => ("a bech32 Cardano address, ask the user for it rather than guessing", "\"addr_test1...\""),
+        Type::UtxoRef => ("a UTxO reference as {tx_hash}#{index}", "\"<64 hex digits>#0\""),
+        _ => ("a string", "\"...\""),
+    }
+}
+
+/// The prompt listing a transaction's parameters as prompt arguments.
+pub fn prompt(name: &str, protocol: &str, tx: &CompiledTx) -> Prompt {
+    let arguments = tx.params.iter()
+        .map(|(param, ty)| PromptArgument {
+            name: param.clone(),
+            description: Some(format!("{}, {}", type_name(ty), guidance(ty).0)),
+            required: Some(false),
+        })
+        .collect();
+
+    let mut description = format!("Guides collecting the parameters of transaction '{}' of protocol '{}' and resolving it", tx.name, protocol);
+    if let Some(docs) = tx.docs.as_deref() {
+        description.push_str(": ");
+        description.push_str(docs);
+    }
+
+    Prompt {
+        name: name.to_string(),
+        description: Some(description),
+        arguments: Some(arguments),
+    }
+}
+
+/// Renders the instructions for building a transaction. `call` says how to invoke the
+/// resolve tool; values the client already passed as prompt arguments are embedded.
+pub fn render(protocol: &str, tx: &CompiledTx, call: &str, provided: &Map<String, Value>) -> GetPromptResult {
+    let mut text = format!("Help me build the transaction '{}' of the tx3 protocol '{}'.", tx.name, protocol);
+    if let Some(docs) = tx.docs.as_deref() {
+        text.push_str("\n\nWhat it does: ");
+        text.push_str(docs);
+    }
+
+    if tx.params.is_empty() {
+        text.push_str("\n\nIt takes no parameters.");
+    } else {
+        text.push_str("\n\nCollect each parameter, asking me for anything you can't determine with certainty:\n");
+        for (param, ty) in tx.params.iter() {
+            let (how, example) = guidance(ty);
+            text.push_str(&format!("\n- `{}` ({}, required): {}. Example: {}", param, type_name(ty), how, example));
+            if let Some(value) = provided.get(param) {
+                text.push_str(&format!(". Already provided: {}", value));
+            }
+            if let Some(pattern) = params::param_schema(ty).get("pattern").and_then(Value::as_str) {
+                text.push_str(&format!(". Must match `{}`", pattern));
+            }
+        }
+    }
+
+    text.push_str(&format!("\n\nOnce every required parameter is known, {}. Show me the resulting transaction hash before anything is signed.", call));
+
+    GetPromptResult {
+        description: Some(format!("Build {} {}", protocol, tx.name)),
+        messages: vec![PromptMessage::new_text(PromptMessageRole::User, text)],
+    }
+}
+
+fn type_name(ty: &Type) -> &'static str {
+    match ty {
+        Type::Int => "Int",
+        Type::Bool => "Bool",
+        Type::Bytes => "Bytes",
+        Type::Address => "Address",
+        Type::UtxoRef => "UtxoRef",
+        _ => "value",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+    use rmcp::model::PromptMessageContent;
+    use super::*;
+
+    fn tx(docs: Option<&str>) -> CompiledTx {
+        CompiledTx {
+            name: "swap".to_string(),
+            params: [("buyer".to_string(), Type::Address), ("quantity".to_string(), Type::Int)].into(),
+            docs: docs.map(str::to_string),
+            tir: Vec::new(),
+        }
+    }
+
+    fn text(result: &GetPromptResult) -> &str {
+        match &result.messages[0].content {
+            PromptMessageContent::Text { text } => text,
+            _ => panic!("prompt should be a text message"),
+        }
+    }
+
+    #[test]
+    fn prompt_has_an_argument_per_parameter() {
+        let prompt = prompt("build-acme_swap-swap", "acme_swap", &tx(Some("Swaps ADA for tokens")));
+        let arguments = prompt.arguments.unwrap();
+        let names: Vec<&str> = arguments.iter().map(|argument| argument.name.as_str()).collect();
+        assert_eq!(names, ["buyer", "quantity"]);
+        assert!(arguments.iter().all(|argument| argument.required == Some(false)));
+        assert!(arguments[0].description.as_deref().unwrap().starts_with("Address, "));
+        assert!(prompt.description.unwrap().ends_with(": Swaps ADA for tokens"));
+    }
+
+    #[test]
+    fn render_walks_through_every_parameter() {
+        let provided = json!({ "quantity": 5 }).as_object().unwrap().clone();
+        let result = render("acme_swap", &tx(None), "call resolve-acme_swap-swap", &provided);
+        let text = text(&result);
+
+        assert!(text.contains("- `buyer` (Address, required)"), "{}", text);
+        assert!(text.contains("- `quantity` (Int, required)"), "{}", text);
+        assert!(text.contains("Already provided: 5"), "{}", text);
+        assert!(text.contains("Must match `^-?[0-9]+$`"), "{}", text);
+        assert!(text.contains("call resolve-acme_swap-swap"), "{}", text);
+        assert_eq!(result.description.as_deref(), Some("Build acme_swap swap"));
+    }
+
+    #[test]
+    fn render_without_parameters_says_so() {
+        let mut tx = tx(None);
+        tx.params.clear();
+        let result = render("acme_swap", &tx, "call resolve-acme_swap-swap", &Map::new());
+        assert!(text(&result).contains("It takes no parameters."));
+    }
+}
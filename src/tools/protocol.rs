@@ -1,354 +1,2816 @@
-use std::sync::Arc;
-use std::collections::HashMap;
-use serde_json::Map;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+use tokio::sync::{RwLock, Semaphore};
+use futures::future::{BoxFuture, FutureExt, Shared, join_all};
+use futures::stream::{self, StreamExt};
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing::Instrument;
+use serde_json::{Map, json};
 use rmcp::{Error as McpError, ServerHandler, RoleServer, tool};
-use rmcp::service::RequestContext;
+use rmcp::service::{Peer, RequestContext};
 use rmcp::model::*;
-use cynic;
-use cynic::QueryBuilder;
-use cynic::http::SurfExt;
 use tx3_sdk::trp::{Client as TrpClient, ClientOptions, ProtoTxRequest, TirInfo};
 
-#[cynic::schema("tx3")]
-mod schema {}
+use super::address::Network;
+use super::breaker::{self, CircuitBreaker};
+use super::check::{CheckReport, ProtocolCheck, RegistryCheck, TrpCheck};
+use super::compiler::{self, CompileCache, CompiledProtocol, CompiledTx};
+use super::decode;
+use super::generic;
+use super::health::UpstreamHealth;
+use super::history::{History, Resolution};
+use super::metrics::Metrics;
+use super::params;
+use super::prompts;
+use super::ratelimit::RateLimiter;
+use super::recent::{self, RecentTxs};
+use super::registry::{ProtocolSource, Registry};
+use super::resolver::{MockResolver, Resolver};
+use super::resources::{self, ResourceUri};
+use super::retry::RetryPolicy;
+use super::roots::RootScan;
+use super::secret::{self, Secret};
+use super::snapshot::DiskSnapshot;
+use super::submit;
+use super::trp::{self, TrpEndpoint};
 
-#[derive(cynic::QueryFragment, Debug)]
-#[cynic(graphql_type = "Query")]
-pub struct ProtocolsQuery {
-    pub protocols: ProtocolConnection,
+/// Maximum number of characters of a registry description copied into tool descriptions.
+pub const DEFAULT_DESCRIPTION_MAX_LEN: usize = 500;
+
+/// Protocols with a larger tx3 source are skipped rather than compiled.
+pub const DEFAULT_MAX_PROTOCOL_SIZE: usize = 256 * 1024;
+
+pub const DEFAULT_TRP_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resolves sent to TRP at once; further calls wait for a slot.
+pub const DEFAULT_TRP_MAX_CONCURRENCY: usize = 8;
+
+/// Resolves queued for longer than this are logged.
+const TRP_QUEUE_LOG_THRESHOLD: Duration = Duration::from_secs(1);
+
+/// How long an upstream may keep failing before `ping` reports the server as unhealthy.
+pub const DEFAULT_UNHEALTHY_AFTER: Duration = Duration::from_secs(60);
+
+/// Protocols named in the server instructions before the list is cut short.
+pub const DEFAULT_INSTRUCTIONS_MAX_PROTOCOLS: usize = 20;
+
+/// How long a registry snapshot is served before a background refresh is triggered.
+pub const DEFAULT_CACHE_TTL: Duration = Duration::from_secs(300);
+
+#[derive(Default)]
+struct CachedProtocols {
+    protocols: Arc<Vec<ProtocolSource>>,
+    fetched_at: Option<Instant>,
+}
+
+/// How protocol transactions are exposed as MCP tools.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ToolMode {
+    /// A resolve and a describe tool for every transaction.
+    #[default]
+    PerTransaction,
+    /// A fixed set of tools taking the protocol and transaction as arguments.
+    Generic,
+}
+
+impl FromStr for ToolMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "per-transaction" => Ok(Self::PerTransaction),
+            "generic" => Ok(Self::Generic),
+            _ => Err(format!("unknown tool mode {}, expected per-transaction or generic", value)),
+        }
+    }
+}
+
+/// When a new session fetches the registry and compiles its protocols.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub enum Warmup {
+    /// In the background as soon as the client is initialized.
+    #[default]
+    Eager,
+    /// On the client's first request that needs them.
+    Lazy,
+}
+
+impl FromStr for Warmup {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "eager" => Ok(Self::Eager),
+            "lazy" => Ok(Self::Lazy),
+            _ => Err(format!("unknown warmup {}, expected eager or lazy", value)),
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum ToolOperation {
+    Resolve,
+    Describe,
 }
 
-#[derive(cynic::QueryFragment, Debug)]
-pub struct ProtocolConnection {
-    pub nodes: Vec<Protocol>,
+/// The protocol transaction behind a generated tool name.
+#[derive(Clone)]
+struct ToolTarget {
+    operation: ToolOperation,
+    protocol: String,
+    transaction: String,
+}
+
+impl ToolTarget {
+    fn new(operation: ToolOperation, protocol: &str, transaction: &str) -> Self {
+        Self {
+            operation,
+            protocol: protocol.to_string(),
+            transaction: transaction.to_string(),
+        }
+    }
 }
 
-#[derive(cynic::QueryFragment, Debug)]
-pub struct Protocol {
-    pub scope: String,
-    pub name: String,
-    pub source: Option<String>,
+type RefreshFlight = Shared<BoxFuture<'static, Result<Arc<Vec<ProtocolSource>>, McpError>>>;
+
+/// Tools generated from one protocol listing.
+struct ToolSnapshot {
+    protocols: Arc<Vec<ProtocolSource>>,
+    tools: Vec<Tool>,
+    targets: HashMap<String, ToolTarget>,
 }
 
+/// A session's peer, shared by every clone of its handler so tasks spawned before the
+/// handshake see it once it's set.
+type SharedPeer = Arc<std::sync::RwLock<Option<Peer<RoleServer>>>>;
 
+/// A connected client, kept to push notifications to it.
 #[derive(Clone)]
-pub struct ProtocolSource {
-    name: String,
-    content: String,
+struct Session {
+    id: u64,
+    peer: SharedPeer,
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    log_level: Arc<Mutex<LoggingLevel>>,
+}
+
+/// Notifications the server pushes to its clients.
+enum Notification {
+    ToolListChanged,
+    ResourceUpdated(String),
+    Log(LoggingLevel, serde_json::Value),
+}
+
+/// What became of a notification sent to one session.
+#[derive(Debug, PartialEq, Eq)]
+enum Delivery {
+    Sent,
+    /// Not sent: the session has no peer yet, or didn't ask for it.
+    Skipped,
+    Disconnected,
+}
+
+impl Session {
+    /// Whether the session asked for `notification`: resource updates need a
+    /// subscription to the URI and logs need to meet the session's level.
+    fn wants(&self, notification: &Notification) -> bool {
+        match notification {
+            Notification::ToolListChanged => true,
+            Notification::ResourceUpdated(uri) => self.subscriptions.lock().unwrap().contains(uri),
+            Notification::Log(level, _) => log_rank(*level) >= log_rank(*self.log_level.lock().unwrap()),
+        }
+    }
 }
 
+/// MCP server handler. Clones share every cache, so the SSE binary hands one clone to
+/// each session and only the peer is per connection.
 #[derive(Clone)]
 pub struct ProtocolTool {
-    registry_url: String,
-    trp_url: String,
-    trp_key: String,
+    registries: Vec<Registry>,
+    local_protocols: Vec<ProtocolSource>,
+    root_scan: Option<RootScan>,
+    root_protocols: Arc<RwLock<Vec<ProtocolSource>>>,
+    trp: TrpEndpoint,
+    networks: BTreeMap<String, TrpEndpoint>,
+    trp_endpoints: BTreeMap<String, TrpEndpoint>,
+    trp_routes: HashMap<String, String>,
+    trp_timeout: Duration,
+    trp_retry: RetryPolicy,
+    trp_permits: Arc<Semaphore>,
+    trp_clients: Arc<Mutex<HashMap<String, (HashMap<String, String>, Arc<dyn Resolver>)>>>,
+    default_network: Option<String>,
+    allowlist: Vec<String>,
+    denylist: Vec<String>,
+    version_pins: HashMap<String, String>,
+    description_max_len: usize,
+    max_protocol_size: usize,
+    tools_page_size: Option<usize>,
+    cache_ttl: Duration,
+    fail_on_registry_error: bool,
+    cache: Arc<RwLock<CachedProtocols>>,
+    /// Each registry's protocols from its last successful fetch, served while it fails.
+    registry_protocols: Arc<RwLock<HashMap<String, Vec<ProtocolSource>>>>,
+    pinned: Arc<RwLock<HashMap<String, ProtocolSource>>>,
+    breaker: Arc<CircuitBreaker>,
+    trp_health: Arc<UpstreamHealth>,
+    metrics: Arc<Metrics>,
+    history: Arc<History>,
+    unhealthy_after: Duration,
+    disk_snapshot: Option<Arc<DiskSnapshot>>,
+    compiled: Arc<CompileCache>,
+    compile_concurrency: usize,
+    tool_mode: ToolMode,
+    recent_txs_capacity: usize,
+    recent_txs_ttl: Duration,
+    instructions_extra: Option<String>,
+    instructions_max_protocols: usize,
+    warmup: Warmup,
+    expose_describe_tools: bool,
+    network: Option<Network>,
+    ignore_unknown_args: bool,
+    known_addresses: BTreeMap<String, String>,
+    http: reqwest::Client,
+    tool_snapshot: Arc<RwLock<Option<Arc<ToolSnapshot>>>>,
+    peer: SharedPeer,
+    sessions: Arc<Mutex<Vec<Session>>>,
+    subscriptions: Arc<Mutex<HashSet<String>>>,
+    log_level: Arc<Mutex<LoggingLevel>>,
+    protocol_version: Arc<Mutex<ProtocolVersion>>,
+    refreshing: Arc<AtomicBool>,
+    in_flight: Arc<Mutex<Option<RefreshFlight>>>,
+    /// Tool calls running across every session, awaited by `drain`.
+    active_calls: Arc<AtomicUsize>,
+    draining: Arc<AtomicBool>,
+    /// Cancelled when the drain deadline passes, aborting the calls still running.
+    shutdown: CancellationToken,
+    recent_txs: Arc<RecentTxs>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    /// Identifies the session in rate limits when no authenticated client does.
+    session_id: u64,
+    last_request_at: Arc<Mutex<Instant>>,
+    /// Authenticated client the session belongs to, recorded on every tool call.
+    client: Option<String>,
 }
 
 #[tool(tool_box)]
 impl ProtocolTool {
     #[allow(dead_code)]
-    pub fn new(registry_url: &str, trp_url: &str, trp_key: &str) -> Self {
+    pub fn new(registry_url: &str, trp: TrpEndpoint) -> Self {
         Self {
-            registry_url: registry_url.to_string(),
-            trp_url: trp_url.to_string(),
-            trp_key: trp_key.to_string(),
+            registries: vec![Registry::new(registry_url)],
+            local_protocols: Vec::new(),
+            root_scan: None,
+            root_protocols: Arc::default(),
+            trp,
+            networks: BTreeMap::new(),
+            trp_endpoints: BTreeMap::new(),
+            trp_routes: HashMap::new(),
+            trp_timeout: DEFAULT_TRP_TIMEOUT,
+            trp_retry: RetryPolicy::none(),
+            trp_permits: Arc::new(Semaphore::new(DEFAULT_TRP_MAX_CONCURRENCY)),
+            trp_clients: Arc::default(),
+            default_network: None,
+            allowlist: Vec::new(),
+            denylist: Vec::new(),
+            version_pins: HashMap::new(),
+            description_max_len: DEFAULT_DESCRIPTION_MAX_LEN,
+            max_protocol_size: DEFAULT_MAX_PROTOCOL_SIZE,
+            tools_page_size: None,
+            cache_ttl: DEFAULT_CACHE_TTL,
+            fail_on_registry_error: false,
+            cache: Arc::default(),
+            registry_protocols: Arc::default(),
+            pinned: Arc::default(),
+            breaker: Arc::new(CircuitBreaker::new("Registry", breaker::DEFAULT_THRESHOLD, breaker::DEFAULT_COOLDOWN)),
+            trp_health: Arc::default(),
+            metrics: Arc::default(),
+            history: Arc::default(),
+            unhealthy_after: DEFAULT_UNHEALTHY_AFTER,
+            disk_snapshot: None,
+            compiled: Arc::new(CompileCache::new(compiler::DEFAULT_CAPACITY)),
+            compile_concurrency: compiler::default_concurrency(),
+            tool_mode: ToolMode::default(),
+            recent_txs_capacity: recent::DEFAULT_CAPACITY,
+            recent_txs_ttl: recent::DEFAULT_TTL,
+            instructions_extra: None,
+            instructions_max_protocols: DEFAULT_INSTRUCTIONS_MAX_PROTOCOLS,
+            warmup: Warmup::default(),
+            expose_describe_tools: true,
+            network: None,
+            ignore_unknown_args: false,
+            known_addresses: BTreeMap::new(),
+            http: reqwest::Client::new(),
+            tool_snapshot: Arc::default(),
+            peer: Arc::default(),
+            sessions: Arc::default(),
+            subscriptions: Arc::default(),
+            log_level: Arc::new(Mutex::new(DEFAULT_LOG_LEVEL)),
+            protocol_version: Arc::new(Mutex::new(ProtocolVersion::V_2024_11_05)),
+            refreshing: Arc::default(),
+            in_flight: Arc::default(),
+            active_calls: Arc::default(),
+            draining: Arc::default(),
+            shutdown: CancellationToken::new(),
+            recent_txs: Arc::default(),
+            rate_limiter: None,
+            session_id: next_session_id(),
+            last_request_at: Arc::new(Mutex::new(Instant::now())),
+            client: None,
+        }
+    }
+
+    /// A handler for a new session, with its own peer, subscriptions and log level;
+    /// every cache stays shared.
+    #[allow(dead_code)]
+    pub fn for_session(&self) -> Self {
+        Self {
+            peer: Arc::default(),
+            recent_txs: Arc::new(RecentTxs::new(self.recent_txs_capacity, self.recent_txs_ttl)),
+            session_id: next_session_id(),
+            last_request_at: Arc::new(Mutex::new(Instant::now())),
+            subscriptions: Arc::default(),
+            log_level: Arc::new(Mutex::new(DEFAULT_LOG_LEVEL)),
+            protocol_version: Arc::new(Mutex::new(ProtocolVersion::V_2024_11_05)),
+            ..self.clone()
+        }
+    }
+
+    /// A handler resolving with a client's own TRP credentials, so its usage is billed
+    /// to its key. Named networks and routed endpoints keep the shared credentials.
+    #[allow(dead_code)]
+    pub fn for_client(&self, name: &str, trp_key: Option<Secret>, trp_url: Option<&str>) -> Self {
+        let mut trp = self.trp.clone();
+        if let Some(url) = trp_url {
+            trp.url = url.to_string();
+        }
+        if trp_key.is_some() {
+            trp.key = trp_key;
+        }
+
+        Self {
+            trp,
+            trp_clients: Arc::default(),
+            client: Some(name.to_string()),
+            ..self.for_session()
+        }
+    }
+
+    /// Replaces the registries protocols are fetched from. When several registries
+    /// publish the same protocol, the one listed first wins.
+    pub fn with_registries(mut self, registries: Vec<Registry>) -> Self {
+        self.registries = registries;
+        self
+    }
+
+    /// Adds a protocol that isn't published in any registry. Local protocols take
+    /// precedence over registry protocols with the same name.
+    pub fn with_local_protocol(mut self, protocol: ProtocolSource) -> Self {
+        self.local_protocols.push(protocol);
+        self
+    }
+
+    /// Asks the client for its filesystem roots once initialized, and again whenever they
+    /// change, exposing the `.tx3` files found in them as local protocols.
+    #[allow(dead_code)]
+    pub fn with_root_discovery(mut self, scan: RootScan) -> Self {
+        self.root_scan = Some(scan);
+        self
+    }
+
+    /// Limits the exposed protocols by `scope_name` identifier. An empty allowlist admits
+    /// every protocol; the denylist always takes precedence.
+    pub fn with_protocol_filter(mut self, allowlist: Vec<String>, denylist: Vec<String>) -> Self {
+        self.allowlist = allowlist;
+        self.denylist = denylist;
+        self
+    }
+
+    /// Pins protocols, keyed by `scope_name`, to a published version. The registry only
+    /// serves the latest version, so the source seen for the pinned version is kept and
+    /// served even after the protocol is republished.
+    pub fn with_version_pins(mut self, version_pins: HashMap<String, String>) -> Self {
+        self.version_pins = version_pins;
+        self
+    }
+
+    /// Stops querying the registries after `threshold` consecutive failed refreshes and
+    /// serves the last fetched protocol list until `cooldown` has elapsed.
+    pub fn with_circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.breaker = Arc::new(CircuitBreaker::new("Registry", threshold, cooldown));
+        self
+    }
+
+    pub fn with_unhealthy_after(mut self, unhealthy_after: Duration) -> Self {
+        self.unhealthy_after = unhealthy_after;
+        self
+    }
+
+    /// Persists every successful registry fetch and falls back to it when the
+    /// registries are unreachable before anything was fetched.
+    pub fn with_disk_snapshot(mut self, snapshot: DiskSnapshot) -> Self {
+        self.disk_snapshot = Some(Arc::new(snapshot));
+        self
+    }
+
+    /// Bounds how many compiled protocols are kept in memory.
+    pub fn with_compile_cache_size(mut self, capacity: usize) -> Self {
+        self.compiled = Arc::new(CompileCache::new(capacity));
+        self
+    }
+
+    /// Turning describe tools off halves the per-transaction tool count; the generic
+    /// `describe-transaction` tool is listed instead.
+    pub fn with_describe_tools(mut self, expose_describe_tools: bool) -> Self {
+        self.expose_describe_tools = expose_describe_tools;
+        self
+    }
+
+    /// Upper bound for a single TRP resolve or submit call.
+    pub fn with_trp_timeout(mut self, trp_timeout: Duration) -> Self {
+        self.trp_timeout = trp_timeout;
+        self
+    }
+
+    /// Retries resolves that fail with a transport error or a 5xx answer. Resolving has
+    /// no side effects, so repeating it is safe. Submits are never retried.
+    pub fn with_trp_retry(mut self, trp_retry: RetryPolicy) -> Self {
+        self.trp_retry = trp_retry;
+        self
+    }
+
+    /// Named TRP endpoints selectable per call through the `network` argument.
+    pub fn with_networks(mut self, networks: BTreeMap<String, TrpEndpoint>, default_network: Option<String>) -> Self {
+        self.networks = networks;
+        self.default_network = default_network;
+        self
+    }
+
+    /// Bounds the resolves in flight across every session, queueing the rest so a
+    /// client fanning out calls doesn't trip the TRP rate limits.
+    pub fn with_trp_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.trp_permits = Arc::new(Semaphore::new(max_concurrency.max(1)));
+        self
+    }
+
+    /// Dedicated TRP endpoints and the protocols routed to them, keyed by protocol name
+    /// or scope. Routed protocols always resolve through their endpoint, whatever the
+    /// `network` argument says.
+    pub fn with_trp_routes(mut self, endpoints: BTreeMap<String, TrpEndpoint>, routes: HashMap<String, String>) -> Self {
+        self.trp_endpoints = endpoints;
+        self.trp_routes = routes;
+        self
+    }
+
+    /// Drops arguments that match no parameter instead of rejecting the call, for
+    /// clients that inject their own metadata fields.
+    pub fn with_ignore_unknown_args(mut self, ignore_unknown_args: bool) -> Self {
+        self.ignore_unknown_args = ignore_unknown_args;
+        self
+    }
+
+    /// Labelled addresses suggested when completing Address parameters.
+    pub fn with_known_addresses(mut self, known_addresses: BTreeMap<String, String>) -> Self {
+        self.known_addresses = known_addresses;
+        self
+    }
+
+    /// Rejects address arguments for any other network before calling TRP.
+    pub fn with_network(mut self, network: Network) -> Self {
+        self.network = Some(network);
+        self
+    }
+
+    /// HTTP client used for calls made outside the tx3 SDK, such as TRP submissions.
+    pub fn with_http_client(mut self, http: reqwest::Client) -> Self {
+        self.http = http;
+        self
+    }
+
+    pub fn with_tool_mode(mut self, tool_mode: ToolMode) -> Self {
+        self.tool_mode = tool_mode;
+        self
+    }
+
+    /// Limits resolves and submits to `per_minute` per authenticated client, or per
+    /// session without one, allowing bursts of `burst` calls. Listing and describing
+    /// aren't limited.
+    pub fn with_rate_limit(mut self, per_minute: u32, burst: u32) -> Self {
+        self.rate_limiter = Some(Arc::new(RateLimiter::new(per_minute, burst)));
+        self
+    }
+
+    /// How many resolved transactions each session remembers for a follow-up submit by
+    /// hash, and for how long.
+    pub fn with_recent_txs(mut self, capacity: usize, ttl: Duration) -> Self {
+        self.recent_txs_capacity = capacity;
+        self.recent_txs_ttl = ttl;
+        self.recent_txs = Arc::new(RecentTxs::new(capacity, ttl));
+        self
+    }
+
+    /// Keeps the last `capacity` resolutions for the `tx3://history/resolutions` resource,
+    /// also appending them to `audit_log` when set. A capacity of zero disables the resource,
+    /// which then reads as empty.
+    pub fn with_history(mut self, capacity: usize, audit_log: Option<PathBuf>) -> Self {
+        self.history = Arc::new(History::new(capacity, audit_log));
+        self
+    }
+
+    /// Operator text appended to the instructions sent on initialize.
+    pub fn with_instructions_extra(mut self, extra: String) -> Self {
+        self.instructions_extra = Some(extra);
+        self
+    }
+
+    /// Protocols named in the instructions before the list is cut short.
+    pub fn with_instructions_max_protocols(mut self, max_protocols: usize) -> Self {
+        self.instructions_max_protocols = max_protocols;
+        self
+    }
+
+    pub fn with_warmup(mut self, warmup: Warmup) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    pub fn with_compile_concurrency(mut self, compile_concurrency: usize) -> Self {
+        self.compile_concurrency = compile_concurrency;
+        self
+    }
+
+    pub fn with_description_max_len(mut self, description_max_len: usize) -> Self {
+        self.description_max_len = description_max_len;
+        self
+    }
+
+    pub fn with_max_protocol_size(mut self, max_protocol_size: usize) -> Self {
+        self.max_protocol_size = max_protocol_size;
+        self
+    }
+
+    /// Splits `tools/list` responses into pages of `page_size` tools. Without a page size
+    /// every tool is returned at once.
+    pub fn with_tools_page_size(mut self, page_size: Option<usize>) -> Self {
+        self.tools_page_size = page_size.filter(|page_size| *page_size > 0);
+        self
+    }
+
+    pub fn with_cache_ttl(mut self, cache_ttl: Duration) -> Self {
+        self.cache_ttl = cache_ttl;
+        self
+    }
+
+    /// When enabled, `list_tools` reports registry failures as an error instead of
+    /// returning an empty tool list.
+    pub fn with_fail_on_registry_error(mut self, fail_on_registry_error: bool) -> Self {
+        self.fail_on_registry_error = fail_on_registry_error;
+        self
+    }
+
+    /// Returns the cached protocol list, fetching it from the registry on first use.
+    /// Once the TTL has elapsed the stale list keeps being served while a single
+    /// background task refreshes it.
+    async fn protocols(&self) -> Result<Arc<Vec<ProtocolSource>>, McpError> {
+        {
+            let cache = self.cache.read().await;
+            if let Some(fetched_at) = cache.fetched_at {
+                if fetched_at.elapsed() >= self.cache_ttl {
+                    self.spawn_refresh();
+                }
+                return Ok(cache.protocols.clone());
+            }
         }
+        self.refresh().await
     }
 
-    async fn run_protocols_query(&self) -> Vec<ProtocolSource> {
-        let query = ProtocolsQuery::build({});
-        let response = surf::post(self.registry_url.clone()).run_graphql(query).await.unwrap().data;
-        match response {
-            Some(data) => data.protocols.nodes.into_iter()
-                .filter(|protocol| protocol.source.is_some())
-                .map(|protocol| {
-                    ProtocolSource {
-                        name: format!("{}_{}", protocol.scope, protocol.name),
-                        content: protocol.source.unwrap(),
+    /// Refreshes the protocol list. Concurrent callers share a single in-flight refresh
+    /// instead of each querying the registries and compiling everything.
+    async fn refresh(&self) -> Result<Arc<Vec<ProtocolSource>>, McpError> {
+        let flight = {
+            let mut in_flight = self.in_flight.lock().unwrap();
+            match in_flight.as_ref() {
+                Some(flight) => flight.clone(),
+                None => {
+                    let tool = self.clone();
+                    let flight = async move {
+                        let result = tool.refresh_now().await;
+                        *tool.in_flight.lock().unwrap() = None;
+                        result
                     }
-                })
-                .collect(),
-            None => Vec::new(),
+                    .boxed()
+                    .shared();
+                    *in_flight = Some(flight.clone());
+                    flight
+                }
+            }
+        };
+
+        flight.await
+    }
+
+    async fn refresh_now(&self) -> Result<Arc<Vec<ProtocolSource>>, McpError> {
+        let registry = if self.breaker.allow() {
+            let started = Instant::now();
+            let result = self.fetch_registries().await;
+            self.metrics.record_registry_fetch(started.elapsed(), result.is_ok());
+
+            match result {
+                Ok((protocols, complete)) => {
+                    self.breaker.record_success();
+                    // A registry served from its last fetch would be saved as if it answered.
+                    if let Some(snapshot) = self.disk_snapshot.as_ref().filter(|_| complete) {
+                        snapshot.save(&protocols).await;
+                    }
+                    Ok(protocols)
+                }
+                Err(err) => {
+                    self.breaker.record_failure(&err.message);
+                    self.broadcast_log(LoggingLevel::Warning, json!({
+                        "message": "Failed to refresh protocols from the registry",
+                        "error": err.message,
+                    })).await;
+                    self.fallback(err).await
+                }
+            }
+        } else {
+            self.fallback(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                "tx3 registry is unavailable, its circuit is open",
+                Some(self.breaker.status()),
+            )).await
+        };
+
+        let registry = match registry {
+            Ok(protocols) => protocols,
+            Err(err) if self.local_protocols.is_empty() && self.root_protocols.read().await.is_empty() => return Err(err),
+            Err(err) => {
+                tracing::warn!("Serving only the local protocols: {}", err.message);
+                Vec::new()
+            }
+        };
+
+        let protocols = self.load_protocols(registry).await;
+        Ok(self.store(protocols).await)
+    }
+
+    async fn store(&self, protocols: Vec<ProtocolSource>) -> Arc<Vec<ProtocolSource>> {
+        let protocols = Arc::new(protocols);
+        let changed: HashSet<String> = {
+            let mut cache = self.cache.write().await;
+            let changed = match cache.fetched_at {
+                Some(_) => {
+                    let old: HashSet<(&str, u64)> = fingerprint(&cache.protocols).into_iter().collect();
+                    let new: HashSet<(&str, u64)> = fingerprint(&protocols).into_iter().collect();
+                    old.symmetric_difference(&new).map(|(name, _)| name.to_string()).collect()
+                }
+                None => HashSet::new(),
+            };
+            cache.protocols = protocols.clone();
+            cache.fetched_at = Some(Instant::now());
+            changed
+        };
+
+        if !changed.is_empty() {
+            tracing::info!("Registry content changed, notifying connected clients");
+            let mut names: Vec<&String> = changed.iter().collect();
+            names.sort();
+            self.broadcast_log(LoggingLevel::Info, json!({
+                "message": "Registry content changed, tools were refreshed",
+                "protocols": names,
+            })).await;
+            self.notify_tool_list_changed().await;
+            self.notify_resources_updated(&changed).await;
         }
+
+        protocols
     }
-}
 
-impl ServerHandler for ProtocolTool {
-    fn get_info(&self) -> ServerInfo {
-        ServerInfo {
-            protocol_version: ProtocolVersion::V_2024_11_05,
-            capabilities: ServerCapabilities::builder()
-                .enable_tools()
-                .build(),
-            server_info: Implementation::from_build_env(),
-            instructions: Some("This server provides a protocol tool that can be use to comunicate with tx3 files for listing and resolving the transactions inside them.".to_string()),
+    /// Who is calling: the named client when the session has one, the session otherwise.
+    /// Rate limits and the history are kept per caller.
+    fn caller(&self) -> String {
+        match &self.client {
+            Some(client) => format!("client:{}", client),
+            None => format!("session:{}", self.session_id),
         }
     }
 
-    async fn list_tools(
-        &self,
-        _request: Option<PaginatedRequestParam>,
-        _context: RequestContext<RoleServer>,
-    ) -> Result<ListToolsResult, McpError> {
-        let protocols = self.run_protocols_query().await;
+    /// This handler's own session.
+    fn session(&self) -> Session {
+        Session {
+            id: self.session_id,
+            peer: self.peer.clone(),
+            subscriptions: self.subscriptions.clone(),
+            log_level: self.log_level.clone(),
+        }
+    }
+
+    /// Whether this server advertises the capability `notification` belongs to.
+    fn advertises(notification: &Notification) -> bool {
+        // Notifications belong to capabilities every revision has.
+        let capabilities = capabilities(&SUPPORTED_VERSIONS[0]);
+        match notification {
+            Notification::ToolListChanged => capabilities.tools.and_then(|tools| tools.list_changed).unwrap_or(false),
+            Notification::ResourceUpdated(_) => capabilities.resources.and_then(|resources| resources.subscribe).unwrap_or(false),
+            Notification::Log(..) => capabilities.logging.is_some(),
+        }
+    }
 
-        let mut property = Map::new();
-        property.insert("type".to_string(), serde_json::Value::String("string".to_string()));
+    /// Every notification goes through here. It's skipped when the capability isn't
+    /// advertised, the session has no peer yet or didn't ask for it.
+    async fn notify(session: &Session, notification: &Notification) -> Delivery {
+        if !Self::advertises(notification) || !session.wants(notification) {
+            return Delivery::Skipped;
+        }
+        let Some(peer) = session.peer.read().unwrap().clone() else {
+            return Delivery::Skipped;
+        };
 
-        let mut tools = Vec::new();
-        for protocol in protocols.iter() {
-            let tx3_protocol_load = tx3_lang::Protocol::from_string(protocol.content.to_string()).load();
-            if tx3_protocol_load.is_err() {
-                tracing::warn!("Failed to load protocol {}", protocol.name);
-                continue;
+        let result = match notification {
+            Notification::ToolListChanged => peer.notify_tool_list_changed().await,
+            Notification::ResourceUpdated(uri) => {
+                peer.notify_resource_updated(ResourceUpdatedNotificationParam { uri: uri.clone() }).await
+            }
+            Notification::Log(level, data) => {
+                peer.notify_logging_message(LoggingMessageNotificationParam {
+                    level: *level,
+                    logger: Some(LOGGER.to_string()),
+                    data: data.clone(),
+                })
+                .await
             }
+        };
+
+        match result {
+            Ok(()) => Delivery::Sent,
+            Err(err) => {
+                tracing::debug!("Dropping disconnected session {}: {}", session.id, err);
+                Delivery::Disconnected
+            }
+        }
+    }
+
+    /// Sends each session its notifications, forgetting the sessions that disconnected.
+    async fn notify_sessions(&self, notifications: impl Fn(&Session) -> Vec<Notification>) {
+        let sessions: Vec<Session> = self.sessions.lock().unwrap().clone();
 
-            let tx3_protocol = tx3_protocol_load.unwrap();
-            for tx in tx3_protocol.txs() {
-                let prototx = tx3_protocol.new_tx(tx.name.as_str()).unwrap();
-                let mut properties = Map::new();
-                let mut required = Vec::new();
-                for param in prototx.find_params() {
-                    properties.insert(param.0.clone(), serde_json::Value::Object(property.clone()));
-                    required.push(serde_json::Value::String(param.0.clone()));  
+        let mut disconnected = HashSet::new();
+        for session in sessions {
+            for notification in notifications(&session) {
+                if Self::notify(&session, &notification).await == Delivery::Disconnected {
+                    disconnected.insert(session.id);
+                    break;
                 }
+            }
+        }
 
-                let mut input_schema = Map::new();
-                input_schema.insert("type".to_string(), serde_json::Value::String("object".to_string()));
-                input_schema.insert("$schema".to_string(), serde_json::Value::String("http://json-schema.org/draft-07/schema#".to_string()));
-                input_schema.insert("title".to_string(), serde_json::Value::String(format!("resolve_{}_{}_params", protocol.name.clone(), tx.name)));
-                input_schema.insert("properties".to_string(), serde_json::Value::Object(properties));
-                input_schema.insert("required".to_string(), serde_json::Value::Array(required));
+        if !disconnected.is_empty() {
+            self.sessions.lock().unwrap().retain(|session| !disconnected.contains(&session.id));
+        }
+    }
 
-                tools.push(Tool {
-                    name: std::borrow::Cow::Owned(format!("resolve-{}-{}", protocol.name.clone(), tx.name)),
-                    description: Some(std::borrow::Cow::Owned(format!("Resolves the transaction '{}' from the protocol '{}'", tx.name, protocol.name))),
-                    annotations: Some(ToolAnnotations {
-                        title: Some(format!("Resolve {} {}", protocol.name, tx.name)),
-                        read_only_hint: Some(true),
-                        destructive_hint: Some(false),
-                        idempotent_hint: Some(false),
-                        open_world_hint: Some(true),
-                    }),
-                    input_schema: Arc::new(input_schema),
-                });
+    async fn notify_tool_list_changed(&self) {
+        self.notify_sessions(|_| vec![Notification::ToolListChanged]).await;
+    }
 
-                tools.push(Tool {
-                    name: std::borrow::Cow::Owned(format!("describe-{}-{}", protocol.name.clone(), tx.name)),
-                    description: Some(std::borrow::Cow::Owned(format!("Describes the transaction '{}' from the protocol '{}' and shows the required parameters", tx.name, protocol.name))),
-                    annotations: Some(ToolAnnotations {
-                        title: Some(format!("Describe {} {}", protocol.name, tx.name)),
-                        read_only_hint: Some(true),
-                        destructive_hint: Some(false),
-                        idempotent_hint: Some(false),
-                        open_world_hint: Some(true),
-                    }),
-                    input_schema: Arc::new(Map::new()),
-                });
+    /// Sends a log notification to this session if it asked for messages of `level`.
+    async fn log(&self, level: LoggingLevel, data: serde_json::Value) {
+        Self::notify(&self.session(), &Notification::Log(level, data)).await;
+    }
+
+    /// Sends a log notification about a server-wide event to every session that asked
+    /// for messages of `level`.
+    async fn broadcast_log(&self, level: LoggingLevel, data: serde_json::Value) {
+        self.notify_sessions(|_| vec![Notification::Log(level, data.clone())]).await;
+    }
+
+    /// Sends `notifications/resources/updated` for every subscribed URI of a changed
+    /// protocol, including its per-transaction documents.
+    async fn notify_resources_updated(&self, changed: &HashSet<String>) {
+        self.notify_sessions(|session| {
+            session.subscriptions.lock().unwrap().iter()
+                .filter(|uri| match resources::parse_uri(uri) {
+                    Ok(ResourceUri::Protocol(protocol))
+                    | Ok(ResourceUri::Tir { protocol, .. })
+                    | Ok(ResourceUri::Schema { protocol, .. }) => changed.contains(&protocol),
+                    Ok(ResourceUri::History) | Err(_) => false,
+                })
+                .map(|uri| Notification::ResourceUpdated(uri.clone()))
+                .collect()
+        })
+        .await;
+    }
+
+    /// The registry protocols to serve when the registries can't be queried: those of
+    /// the last successful fetch if there was one, otherwise the snapshot persisted on disk.
+    async fn fallback(&self, err: McpError) -> Result<Vec<ProtocolSource>, McpError> {
+        {
+            let last_good = self.registry_protocols.read().await;
+            if !last_good.is_empty() {
+                tracing::warn!("Serving the protocols of the last registry fetch: {}", err.message);
+                return Ok(self.registries.iter()
+                    .filter_map(|registry| last_good.get(registry.url()))
+                    .flatten()
+                    .cloned()
+                    .collect());
             }
         }
-        Ok(ListToolsResult { tools, next_cursor: None })
+
+        let loaded = match &self.disk_snapshot {
+            Some(snapshot) => snapshot.load().await,
+            None => None,
+        };
+        let Some((protocols, age)) = loaded else {
+            return Err(err);
+        };
+
+        tracing::warn!(
+            "Serving {} protocols from the disk snapshot saved {}s ago: {}",
+            protocols.len(),
+            age.as_secs(),
+            err.message
+        );
+        Ok(protocols)
     }
 
-    async fn call_tool(
-        &self,
-        request: CallToolRequestParam,
-        _context: RequestContext<RoleServer>,
-    ) -> Result<CallToolResult, McpError> {
-        let name = request.name.split("-").collect::<Vec<&str>>();
+    fn spawn_refresh(&self) {
+        if self.refreshing.swap(true, Ordering::AcqRel) {
+            return;
+        }
 
-        let operation_name = name.get(0)
-            .ok_or_else(|| {
-                McpError::new(
-                    ErrorCode::RESOURCE_NOT_FOUND,
-                    format!("Operation not found"),
-                    None,
-                )
-            })
-            .unwrap().to_string();
+        let tool = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = tool.refresh().await {
+                tracing::warn!("Failed to refresh protocols from registry, serving cached list: {}", err.message);
+            }
+            tool.refreshing.store(false, Ordering::Release);
+        });
+    }
 
-        let protocol_name = name.get(1)
-            .ok_or_else(|| {
-                McpError::new(
-                    ErrorCode::RESOURCE_NOT_FOUND,
-                    format!("Protocol name not found"),
-                    None,
-                )
+    async fn apply_version_pins(&self, protocols: Vec<ProtocolSource>) -> Vec<ProtocolSource> {
+        if self.version_pins.is_empty() {
+            return protocols;
+        }
+
+        let mut pinned = self.pinned.write().await;
+        protocols.into_iter()
+            .filter_map(|protocol| {
+                let Some(pin) = self.version_pins.get(&protocol.name) else {
+                    return Some(protocol);
+                };
+
+                if protocol.version.as_deref() == Some(pin.as_str()) {
+                    pinned.insert(protocol.name.clone(), protocol.clone());
+                    return Some(protocol);
+                }
+
+                match pinned.get(&protocol.name) {
+                    Some(snapshot) => {
+                        tracing::warn!(
+                            "Protocol {} is pinned to version {} but the registry publishes {}, serving the pinned source",
+                            protocol.name,
+                            pin,
+                            protocol.version.as_deref().unwrap_or("unknown")
+                        );
+                        Some(snapshot.clone())
+                    }
+                    None => {
+                        tracing::warn!(
+                            "Protocol {} is pinned to version {} which the registry doesn't serve, skipping it",
+                            protocol.name,
+                            pin
+                        );
+                        None
+                    }
+                }
             })
-            .unwrap().to_string();
+            .collect()
+    }
+
+    fn is_protocol_sane(&self, protocol: &ProtocolSource) -> bool {
+        if protocol.content.len() > self.max_protocol_size {
+            tracing::warn!(
+                "Skipping protocol {} from {}: source is {} bytes, over the {} bytes limit",
+                protocol.name,
+                protocol.registry_url,
+                protocol.content.len(),
+                self.max_protocol_size
+            );
+            return false;
+        }
+
+        if protocol.content.trim().is_empty() {
+            tracing::warn!("Skipping protocol {} from {}: source is empty", protocol.name, protocol.registry_url);
+            return false;
+        }
+
+        true
+    }
 
-        let transaction_name = name.get(2)
-            .ok_or_else(|| {
+    /// Compiles on the blocking pool, tx3 compilation is CPU-bound and would otherwise
+    /// stall the executor.
+    async fn compile(&self, protocol: &ProtocolSource) -> Result<Arc<CompiledProtocol>, McpError> {
+        let cache = self.compiled.clone();
+        let name = protocol.name.clone();
+        let hash = protocol.content_hash();
+        let content = protocol.content.clone();
+
+        let compiled = tokio::task::spawn_blocking(move || cache.get_or_compile(&name, hash, &content))
+            .await
+            .map_err(|err| {
                 McpError::new(
-                    ErrorCode::RESOURCE_NOT_FOUND,
-                    format!("Transaction name not found"),
+                    ErrorCode::INTERNAL_ERROR,
+                    format!("Compilation of protocol {} was aborted: {}", protocol.name, err),
                     None,
                 )
-            })
-            .unwrap().to_string();
+            })?;
 
-        let protocols = self.run_protocols_query().await;
-        let protocol = protocols.iter().find(|p| p.name == protocol_name).ok_or_else(|| {
+        compiled.map_err(|err| {
+            tracing::warn!("Failed to load protocol {}: {}", protocol.name, err.diagnostics);
             McpError::new(
-                ErrorCode::RESOURCE_NOT_FOUND,
-                format!("Protocol {} not found", protocol_name),
+                ErrorCode::INTERNAL_ERROR,
+                format!("Protocol {} failed to compile: {}", protocol.name, err),
                 None,
             )
-        }).unwrap();
+        })
+    }
+
+    /// Compiles protocols with at most `compile_concurrency` in flight, returning the
+    /// results in input order.
+    async fn compile_all(&self, protocols: &[ProtocolSource]) -> Vec<Result<Arc<CompiledProtocol>, McpError>> {
+        stream::iter(protocols.iter())
+            .map(|protocol| self.compile(protocol))
+            .buffered(self.compile_concurrency.max(1))
+            .collect()
+            .await
+    }
+
+    /// The protocols the allowlist, denylist and sanity checks let through.
+    fn admissible(&self, protocols: Vec<ProtocolSource>) -> Vec<ProtocolSource> {
+        protocols.into_iter()
+            .filter(|protocol| self.is_protocol_allowed(&protocol.name))
+            .filter(|protocol| self.is_protocol_sane(protocol))
+            .collect()
+    }
+
+    fn is_protocol_allowed(&self, name: &str) -> bool {
+        if self.denylist.iter().any(|denied| denied == name) {
+            return false;
+        }
+        self.allowlist.is_empty() || self.allowlist.iter().any(|allowed| allowed == name)
+    }
+
+    /// Looks up a single protocol by `scope_name`. Before the protocol list has been
+    /// fetched this asks the registries for just that protocol, falling back to the
+    /// full listing when the targeted query finds nothing or isn't supported.
+    async fn find_protocol(&self, name: &str) -> Result<ProtocolSource, McpError> {
+        if self.cache.read().await.fetched_at.is_none() {
+            match self.fetch_single_protocol(name).await {
+                Ok(Some(protocol)) => return Ok(protocol),
+                Ok(None) => {}
+                Err(err) => tracing::debug!("Targeted registry query for {} failed, listing all protocols: {}", name, err.message),
+            }
+        }
+
+        let protocols = self.protocols().await?;
+        protocols.iter().find(|p| p.name == name).cloned().ok_or_else(|| {
+            McpError::new(
+                ErrorCode::RESOURCE_NOT_FOUND,
+                format!("Protocol {} not found", name),
+                None,
+            )
+        })
+    }
+
+    /// The protocol `name` from the first source serving it, passed through the same
+    /// dedupe, pins and checks as a full listing so it isn't compiled when that would
+    /// leave it out.
+    async fn fetch_single_protocol(&self, name: &str) -> Result<Option<ProtocolSource>, McpError> {
+        let mut found: Vec<ProtocolSource> = self.local_protocols.iter().filter(|p| p.name == name).cloned().collect();
+        found.extend(self.root_protocols.read().await.iter().filter(|p| p.name == name).cloned());
+
+        if found.is_empty() && self.is_protocol_allowed(name) && !self.version_pins.contains_key(name) {
+            // Scopes and names may both contain underscores, so try every split point.
+            'split: for (index, _) in name.match_indices('_') {
+                let (scope, protocol_name) = (&name[..index], &name[index + 1..]);
+                for registry in self.registries.iter() {
+                    if let Some(protocol) = registry.fetch_protocol(scope, protocol_name).await? {
+                        found.push(protocol);
+                        break 'split;
+                    }
+                }
+            }
+        }
+
+        let protocols = self.admissible(self.apply_version_pins(dedupe_protocols(found)).await);
+        Ok(protocols.into_iter().find(|p| p.name == name))
+    }
+
+    /// Returns the tool list for the current protocol list, in a deterministic order so
+    /// pagination cursors stay stable. The list is built once per protocol listing and shared by
+    /// every session; later calls are a memory read.
+    async fn build_tools(&self) -> Result<Arc<ToolSnapshot>, McpError> {
+        let protocols = match self.tool_mode {
+            ToolMode::PerTransaction => match self.protocols().await {
+                Ok(protocols) => protocols,
+                Err(err) if !self.fail_on_registry_error => {
+                    tracing::warn!("Listing no tools: {}", err.message);
+                    Arc::default()
+                }
+                Err(err) => return Err(err),
+            },
+            ToolMode::Generic => Arc::default(),
+        };
+
+        if let Some(snapshot) = self.tool_snapshot.read().await.as_ref() {
+            if self.tool_mode == ToolMode::Generic || Arc::ptr_eq(&snapshot.protocols, &protocols) {
+                return Ok(snapshot.clone());
+            }
+        }
+
+        let (mut tools, targets) = match self.tool_mode {
+            ToolMode::PerTransaction => self.build_transaction_tools(&protocols).await,
+            ToolMode::Generic => (generic::tools(), HashMap::new()),
+        };
+
+        // Without per-transaction describe tools, describing stays reachable generically.
+        if self.tool_mode == ToolMode::PerTransaction && !self.expose_describe_tools {
+            tools.extend(generic::tools().into_iter().filter(|tool| tool.name == generic::DESCRIBE_TRANSACTION));
+        }
+
+        tools.push(Tool {
+            name: std::borrow::Cow::Borrowed("registry-status"),
+            description: Some(std::borrow::Cow::Borrowed("Reports the health of the tx3 registries: circuit breaker state, last error and age of the cached protocol list")),
+            annotations: Some(ToolAnnotations {
+                title: Some("Registry status".to_string()),
+                read_only_hint: Some(true),
+                destructive_hint: Some(false),
+                idempotent_hint: Some(true),
+                open_world_hint: Some(false),
+            }),
+            input_schema: Arc::new(empty_object_schema()),
+        });
+        tools.push(Tool {
+            name: std::borrow::Cow::Borrowed("trp-status"),
+            description: Some(std::borrow::Cow::Borrowed("Reports the health of the TRP endpoints: redacted URLs, last successful call, last error and latency. Use it to find out why resolves are failing")),
+            annotations: Some(ToolAnnotations {
+                title: Some("TRP status".to_string()),
+                read_only_hint: Some(true),
+                destructive_hint: Some(false),
+                idempotent_hint: Some(true),
+                open_world_hint: Some(false),
+            }),
+            input_schema: Arc::new(empty_object_schema()),
+        });
+        tools.push(submit::tool());
+        tools.push(generic::get_tir_tool());
+        tools.push(generic::validate_tx3_tool());
+        if self.tool_mode == ToolMode::PerTransaction {
+            tools.extend(generic::tools().into_iter().filter(|tool| {
+                tool.name == generic::LIST_PROTOCOLS || tool.name == generic::LIST_TRANSACTIONS
+            }));
+        }
+
+        for tool in tools.iter_mut() {
+            if tool.name == generic::RESOLVE_TRANSACTION || tool.name == submit::SUBMIT_TRANSACTION {
+                self.add_network_argument(Arc::make_mut(&mut tool.input_schema));
+            }
+            if tool.name == generic::RESOLVE_TRANSACTION {
+                add_decode_argument(Arc::make_mut(&mut tool.input_schema));
+            }
+        }
+
+        // Fixed tools first by name, then generated tools by protocol, transaction and
+        // operation, independent of the order registries return protocols in.
+        tools.sort_by_cached_key(|tool| match targets.get(tool.name.as_ref()) {
+            Some(target) => (1, target.protocol.clone(), target.transaction.clone(), target.operation),
+            None => (0, tool.name.to_string(), String::new(), ToolOperation::Resolve),
+        });
+
+        // Tools and targets are swapped together so in-flight calls see one listing.
+        let snapshot = Arc::new(ToolSnapshot { protocols, tools, targets });
+        *self.tool_snapshot.write().await = Some(snapshot.clone());
+
+        Ok(snapshot)
+    }
+
+    /// Generates a resolve and a describe tool for every transaction of every protocol.
+    async fn build_transaction_tools(&self, protocols: &[ProtocolSource]) -> (Vec<Tool>, HashMap<String, ToolTarget>) {
+        let compiled = self.compile_all(protocols).await;
+
+        let mut tools = Vec::new();
+        let mut targets = HashMap::new();
+        let mut broken = Vec::new();
+        for (protocol, compiled) in protocols.iter().zip(compiled) {
+            let Ok(compiled) = compiled else {
+                broken.push(protocol.name.clone());
+                continue;
+            };
+
+            let protocol_description = protocol.description.as_deref()
+                .map(|description| truncate(description.trim(), self.description_max_len));
+
+            let metadata = protocol_metadata(protocol);
+
+            for tx in compiled.txs.iter() {
+                let mut input_schema = resolve_input_schema(&protocol.name, tx);
+                input_schema.insert("x-tx3".to_string(), metadata.clone());
+                self.add_network_argument(&mut input_schema);
+                add_decode_argument(&mut input_schema);
+
+                let mut resolve_description = format!("Resolves the transaction '{}' from the protocol '{}'", tx.name, protocol.name);
+                let mut describe_description = format!("Describes the transaction '{}' from the protocol '{}' and shows the required parameters", tx.name, protocol.name);
+                if let Some(docs) = tx.docs.as_deref() {
+                    for description in [&mut resolve_description, &mut describe_description] {
+                        description.push_str(": ");
+                        description.push_str(docs);
+                    }
+                }
+                if let Some(description) = protocol_description.as_deref() {
+                    resolve_description.push_str("\n\n");
+                    resolve_description.push_str(description);
+                }
+                resolve_description.push_str("\n\nReturns the CBOR hex as text, followed by a JSON block with `tx`, `encoding` and the transaction `hash`. Describing the transaction returns that block's JSON Schema as `output_schema`.");
+
+                let resolve_name = tool_name("resolve", &protocol.name, &tx.name);
+                targets.insert(resolve_name.clone(), ToolTarget::new(ToolOperation::Resolve, &protocol.name, &tx.name));
+                tools.push(Tool {
+                    name: std::borrow::Cow::Owned(resolve_name),
+                    description: Some(std::borrow::Cow::Owned(resolve_description)),
+                    annotations: Some(ToolAnnotations {
+                        title: Some(format!("Resolve {} {}", protocol.name, tx.name)),
+                        read_only_hint: Some(true),
+                        destructive_hint: Some(false),
+                        idempotent_hint: Some(false),
+                        open_world_hint: Some(true),
+                    }),
+                    input_schema: Arc::new(input_schema),
+                });
+
+                if !self.expose_describe_tools {
+                    continue;
+                }
+
+                let describe_name = tool_name("describe", &protocol.name, &tx.name);
+                targets.insert(describe_name.clone(), ToolTarget::new(ToolOperation::Describe, &protocol.name, &tx.name));
+                tools.push(Tool {
+                    name: std::borrow::Cow::Owned(describe_name),
+                    description: Some(std::borrow::Cow::Owned(describe_description)),
+                    annotations: Some(ToolAnnotations {
+                        title: Some(format!("Describe {} {}", protocol.name, tx.name)),
+                        read_only_hint: Some(true),
+                        destructive_hint: Some(false),
+                        idempotent_hint: Some(false),
+                        open_world_hint: Some(true),
+                    }),
+                    input_schema: Arc::new(Map::from_iter([("x-tx3".to_string(), metadata.clone())])),
+                });
+            }
+        }
+
+        if !broken.is_empty() {
+            tracing::warn!("{} protocols publish invalid tx3 source and were skipped: {}", broken.len(), broken.join(", "));
+            self.broadcast_log(LoggingLevel::Warning, json!({
+                "message": "Protocols with invalid tx3 source were skipped",
+                "protocols": broken,
+            })).await;
+        }
+
+        (tools, targets)
+    }
+
+    /// Resolves a tool name to the protocol transaction it was generated for. Names are
+    /// looked up exactly since protocol and transaction names may contain dashes.
+    async fn tool_target(&self, name: &str) -> Result<ToolTarget, McpError> {
+        if let Some(snapshot) = self.tool_snapshot.read().await.as_ref() {
+            if let Some(target) = snapshot.targets.get(name) {
+                return Ok(target.clone());
+            }
+        }
+
+        if !self.expose_describe_tools && name.starts_with("describe-") {
+            return Err(McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Tool {} is disabled, use {} instead", name, generic::DESCRIBE_TRANSACTION),
+                None,
+            ));
+        }
+
+        // The tool list may not have been built yet, or may be stale.
+        let snapshot = self.build_tools().await?;
+
+        snapshot.targets.get(name).cloned().ok_or_else(|| {
+            McpError::new(
+                ErrorCode::RESOURCE_NOT_FOUND,
+                format!("Tool {} not found", name),
+                None,
+            )
+        })
+    }
+
+    /// Finds a protocol and compiles it, failing when it has no such transaction.
+    async fn find_transaction(&self, protocol_name: &str, transaction_name: &str) -> Result<(ProtocolSource, Arc<CompiledProtocol>), McpError> {
+        let protocol = self.find_protocol(protocol_name).await?;
+        let compiled = self.compile(&protocol).await?;
+
+        if compiled.tx(transaction_name).is_none() {
+            return Err(McpError::new(
+                ErrorCode::RESOURCE_NOT_FOUND,
+                format!("Transaction {} not found for protocol {}", transaction_name, protocol_name),
+                None,
+            ));
+        }
+
+        Ok((protocol, compiled))
+    }
+
+    async fn describe_transaction(&self, protocol_name: &str, transaction_name: &str) -> Result<CallToolResult, McpError> {
+        let (protocol, compiled) = self.find_transaction(protocol_name, transaction_name).await?;
+        let tx = compiled.tx(transaction_name).unwrap();
+
+        let mut summary = format!("Transaction '{}' of protocol '{}'", transaction_name, protocol_name);
+        if let Some(version) = &protocol.version {
+            summary.push_str(&format!(" (version {})", version));
+        }
+        if tx.params.is_empty() {
+            summary.push_str(" takes no parameters.");
+        } else {
+            summary.push_str(" takes:");
+            for (param_name, param_type) in tx.params.iter() {
+                summary.push_str(&format!("\n- {}: {:?}", param_name, param_type));
+            }
+        }
+
+        let response = json!({
+            "protocol": protocol_name,
+            "transaction": transaction_name,
+            "version": protocol.version,
+            "docs": tx.docs,
+            "input_schema": resolve_input_schema(protocol_name, tx),
+            "output_schema": resolve_output_schema(),
+            "networks": self.networks.keys().collect::<Vec<_>>(),
+            "default_network": self.default_network,
+        });
+
+        Ok(CallToolResult::success(vec![Content::json(response)?, Content::text(summary)]))
+    }
+
+    async fn get_tir(&self, protocol_name: &str, transaction_name: &str) -> Result<CallToolResult, McpError> {
+        let (_, compiled) = self.find_transaction(protocol_name, transaction_name).await?;
+        let tx = compiled.tx(transaction_name).unwrap();
+
+        Ok(CallToolResult::success(vec![Content::json(tir_document(tx))?]))
+    }
+
+    async fn validate_tx3(&self, source: &str) -> Result<CallToolResult, McpError> {
+        if source.len() > self.max_protocol_size {
+            return Err(McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Source is {} bytes, over the {} bytes limit", source.len(), self.max_protocol_size),
+                None,
+            ));
+        }
+
+        let content = source.to_string();
+        let compiled = tokio::task::spawn_blocking(move || CompiledProtocol::compile(&content))
+            .await
+            .map_err(|err| {
+                McpError::new(
+                    ErrorCode::INTERNAL_ERROR,
+                    format!("Compilation was aborted: {}", err),
+                    None,
+                )
+            })?;
+
+        let response = match compiled {
+            Ok(compiled) => json!({
+                "valid": true,
+                "transactions": compiled.txs.iter()
+                    .map(|tx| json!({
+                        "name": tx.name,
+                        "docs": tx.docs,
+                        "input_schema": resolve_input_schema("source", tx),
+                    }))
+                    .collect::<Vec<_>>(),
+            }),
+            Err(err) => json!({
+                "valid": false,
+                "error": {
+                    "message": err.message,
+                    "line": err.location().map(|(line, _)| line),
+                    "column": err.location().map(|(_, column)| column),
+                    "diagnostics": err.diagnostics,
+                },
+            }),
+        };
+
+        Ok(CallToolResult::success(vec![Content::json(response)?]))
+    }
+
+    async fn resolve_transaction(
+        &self,
+        protocol_name: &str,
+        transaction_name: &str,
+        parameters: &Map<String, serde_json::Value>,
+        network: Option<&str>,
+    ) -> Result<CallToolResult, McpError> {
+        tracing::Span::current()
+            .record("protocol", protocol_name)
+            .record("transaction", transaction_name);
+
+        let (protocol, compiled) = self.find_transaction(protocol_name, transaction_name).await?;
+        let tx = compiled.tx(transaction_name).unwrap();
+
+        // Per-transaction tools take the network next to the parameters, unless the
+        // transaction has a parameter of that name.
+        let mut parameters = parameters.clone();
+        let network = match network {
+            Some(network) => Some(network.to_string()),
+            None if !tx.params.contains_key(NETWORK_ARG) => match parameters.remove(NETWORK_ARG) {
+                None | Some(serde_json::Value::Null) => None,
+                Some(serde_json::Value::String(network)) => Some(network),
+                Some(_) => return Err(McpError::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("Argument {} must be a string", NETWORK_ARG),
+                    None,
+                )),
+            },
+            None => None,
+        };
+        let decode = if tx.params.contains_key(DECODE_ARG) {
+            false
+        } else {
+            match parameters.remove(DECODE_ARG) {
+                None | Some(serde_json::Value::Null) => false,
+                Some(serde_json::Value::Bool(decode)) => decode,
+                Some(_) => return Err(McpError::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("Argument {} must be a boolean", DECODE_ARG),
+                    None,
+                )),
+            }
+        };
+        let (client_key, endpoint) = match self.routed_endpoint(&protocol)? {
+            Some((name, endpoint)) => (format!("endpoint:{}", name), endpoint),
+            None => self.trp_endpoint(network.as_deref())?,
+        };
+        let resolver = self.resolver(client_key, &endpoint)?;
+
+        let missing_env = endpoint.missing_env_args(&compiled.env);
+        if !missing_env.is_empty() {
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!(
+                    "Protocol {} needs env values this server doesn't configure: {}. Set them in TRP_ENV_ARGS",
+                    protocol_name,
+                    missing_env.join(", "),
+                ),
+                Some(json!({ "missing_env": missing_env })),
+            ));
+        }
+
+        let missing: Map<String, serde_json::Value> = tx.params.iter()
+            .filter(|(name, _)| parameters.get(*name).is_none_or(serde_json::Value::is_null))
+            .map(|(name, ty)| (name.clone(), params::param_schema(ty)))
+            .collect();
+        if !missing.is_empty() {
+            return Err(McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!(
+                    "Missing parameters for transaction {} in protocol {}: {}",
+                    transaction_name,
+                    protocol_name,
+                    missing.keys().cloned().collect::<Vec<_>>().join(", "),
+                ),
+                Some(json!({ "missing": missing })),
+            ));
+        }
+
+        let unknown: Vec<&String> = parameters.keys().filter(|name| !tx.params.contains_key(*name)).collect();
+        if !unknown.is_empty() {
+            if !self.ignore_unknown_args {
+                return Err(McpError::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!(
+                        "Unknown parameters for transaction {} in protocol {}: {}",
+                        transaction_name,
+                        protocol_name,
+                        unknown.iter().map(|name| name.as_str()).collect::<Vec<_>>().join(", "),
+                    ),
+                    Some(json!({ "unknown": unknown, "valid": tx.params.keys().collect::<Vec<_>>() })),
+                ));
+            }
+            tracing::debug!("Ignoring unknown arguments for {} {}: {:?}", protocol_name, transaction_name, unknown);
+        }
+
+        let mut args: HashMap<String, tx3_lang::ArgValue> = HashMap::new();
+        for (arg_name, arg_type) in tx.params.iter() {
+            // Null is treated as an absent argument.
+            if let Some(value) = parameters.get(arg_name).filter(|value| !value.is_null()) {
+                args.insert(arg_name.clone(), params::coerce_arg(arg_name, arg_type, value, self.network)?);
+            }
+        }
+
+        let resolved_args: Map<String, serde_json::Value> = args.iter()
+            .map(|(name, value)| (name.clone(), params::echo_arg(&tx.params[name], value)))
+            .collect();
+
+        let what = format!("TRP resolve of {} {}", protocol_name, transaction_name);
+        let request = self.trp_retry.retry(
+            &what,
+            || resolver.resolve(protocol_name, transaction_name, ProtoTxRequest {
+                tir: TirInfo {
+                    bytecode: hex::encode(&tx.tir),
+                    encoding: "hex".to_string(),
+                    version: tx3_lang::ir::IR_VERSION.to_string(),
+                },
+                args: args.clone(),
+            }),
+            trp::is_transient,
+        );
+
+        let queued = Instant::now();
+        let _permit = self.trp_permits.acquire().await.expect("TRP semaphore is never closed");
+        if queued.elapsed() > TRP_QUEUE_LOG_THRESHOLD {
+            tracing::debug!("{} waited {:?} for a TRP slot", what, queued.elapsed());
+        }
+
+        let started = Instant::now();
+        let mut resolution = Resolution::new(protocol_name, transaction_name, args.keys().cloned().collect(), self.client.as_deref());
+
+        let Ok(result) = tokio::time::timeout(self.trp_timeout, request).await else {
+            let message = format!("TRP did not answer within {}ms", self.trp_timeout.as_millis());
+            self.trp_health.record_failure(&message);
+            self.metrics.record_trp_error(protocol_name, transaction_name, "timeout");
+            resolution.error = Some(message.clone());
+            self.history.record(&self.caller(), resolution);
+            return Err(McpError::new(ErrorCode::INTERNAL_ERROR, message, None));
+        };
+
+        tracing::debug!("{} took {:?}", what, started.elapsed());
+        self.trp_health.record_latency(started.elapsed());
+
+        if result.is_err() {
+            let err = result.unwrap_err();
+            self.trp_health.record_failure(&err.to_string());
+            let err = trp::resolve_error(&err, &endpoint);
+            let kind = err.data.as_ref().and_then(|data| data["kind"].as_str()).unwrap_or("unknown");
+            self.metrics.record_trp_error(protocol_name, transaction_name, kind);
+            resolution.error = Some(err.message.to_string());
+            self.history.record(&self.caller(), resolution);
+            self.log(LoggingLevel::Error, json!({
+                "message": err.message,
+                "protocol": protocol_name,
+                "transaction": transaction_name,
+                "kind": kind,
+            })).await;
+            return Err(err);
+        }
+
+        self.trp_health.record_success();
+        self.metrics.record_resolve(protocol_name, transaction_name, started.elapsed());
+
+        let tx = result.unwrap();
+        let mut structured = json!({
+            "tx": tx,
+            "encoding": "hex",
+            "resolved_args": resolved_args,
+        });
+        match decode::tx_hash(&tx) {
+            Ok(hash) => {
+                self.recent_txs.insert(&hash, &tx);
+                resolution.tx_hash = Some(hash.clone());
+                structured["hash"] = json!(hash);
+            }
+            Err(err) => {
+                tracing::warn!("Failed to hash resolved transaction: {}", err);
+                structured["warning"] = json!(format!("Could not compute the transaction hash: {}", err));
+            }
+        }
+
+        self.history.record(&self.caller(), resolution);
+
+        // The text block comes first so clients that only read plain text keep working.
+        let mut content = vec![Content::text(tx.clone()), Content::json(structured)?];
+        if decode {
+            let summary = decode::summarize(&tx).unwrap_or_else(|err| {
+                tracing::warn!("Failed to decode resolved transaction: {}", err);
+                json!({ "note": format!("The transaction could not be decoded: {}", err) })
+            });
+            content.push(Content::json(summary)?);
+        }
+
+        Ok(CallToolResult::success(content))
+    }
+
+    /// Picks the TRP endpoint for a call: the named network, else the default one, else
+    /// the single `TRP_URL` endpoint when no networks are configured.
+    fn trp_endpoint(&self, network: Option<&str>) -> Result<(String, TrpEndpoint), McpError> {
+        if self.networks.is_empty() {
+            return match network {
+                None => Ok((String::new(), self.trp.clone())),
+                Some(network) => Err(McpError::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("Network {} is not available, this server has no named networks", network),
+                    None,
+                )),
+            };
+        }
+
+        let name = network.or(self.default_network.as_deref()).ok_or_else(|| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Argument {} is required, this server has no default network", NETWORK_ARG),
+                Some(json!({ "networks": self.networks.keys().collect::<Vec<_>>() })),
+            )
+        })?;
+
+        let endpoint = self.networks.get(name).cloned().ok_or_else(|| {
+            McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Unknown network {}", name),
+                Some(json!({ "networks": self.networks.keys().collect::<Vec<_>>() })),
+            )
+        })?;
+
+        Ok((name.to_string(), endpoint))
+    }
+
+    /// The dedicated endpoint a protocol is routed to by name or, failing that, by scope.
+    fn routed_endpoint(&self, protocol: &ProtocolSource) -> Result<Option<(String, TrpEndpoint)>, McpError> {
+        let route = self.trp_routes.get(&protocol.name)
+            .or_else(|| protocol.scope.as_ref().and_then(|scope| self.trp_routes.get(scope)));
+        let Some(name) = route else {
+            return Ok(None);
+        };
+
+        match self.trp_endpoints.get(name) {
+            Some(endpoint) => Ok(Some((name.clone(), endpoint.clone()))),
+            None => Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Protocol {} is routed to TRP endpoint {}, which is not configured", protocol.name, name),
+                None,
+            )),
+        }
+    }
+
+    /// Resolver for an endpoint, built on first use and reused so calls share its
+    /// connection pool. A rotated key changes the headers, which rebuilds it. `mock://`
+    /// endpoints get a `MockResolver` instead of a TRP client.
+    fn resolver(&self, name: String, endpoint: &TrpEndpoint) -> Result<Arc<dyn Resolver>, McpError> {
+        let headers = endpoint.headers();
+        let mut clients = self.trp_clients.lock().unwrap();
+        if let Some((client_headers, client)) = clients.get(&name) {
+            if *client_headers == headers {
+                return Ok(client.clone());
+            }
+        }
+
+        let client: Arc<dyn Resolver> = if endpoint.is_mock() {
+            let mock = MockResolver::from_url(&endpoint.url).map_err(|err| {
+                McpError::new(ErrorCode::INTERNAL_ERROR, format!("{:#}", err), None)
+            })?;
+            Arc::new(mock)
+        } else {
+            tracing::debug!(
+                "Building TRP client {} for {} with headers {:?} and env args {:?}",
+                name,
+                endpoint.redacted_url(),
+                secret::redact(headers.iter()),
+                secret::redact(endpoint.env_args.iter()),
+            );
+            Arc::new(TrpClient::new(ClientOptions {
+                endpoint: endpoint.url.clone(),
+                headers: Some(headers.clone()),
+                env_args: endpoint.trp_env_args(),
+            }))
+        };
+        clients.insert(name, (headers, client.clone()));
+        Ok(client)
+    }
+
+    /// Adds the optional network argument to a tool schema when named networks exist.
+    fn add_network_argument(&self, schema: &mut Map<String, serde_json::Value>) {
+        if self.networks.is_empty() {
+            return;
+        }
+
+        let description = match &self.default_network {
+            Some(default) => format!("TRP network to use, {} when omitted", default),
+            None => "TRP network to use".to_string(),
+        };
+        if let Some(serde_json::Value::Object(properties)) = schema.get_mut("properties") {
+            properties.entry(NETWORK_ARG).or_insert(json!({
+                "type": "string",
+                "enum": self.networks.keys().collect::<Vec<_>>(),
+                "description": description,
+            }));
+        }
+    }
+
+    async fn submit_transaction(&self, tx: &str, network: Option<&str>) -> Result<CallToolResult, McpError> {
+        let (_, endpoint) = self.trp_endpoint(network)?;
+        if endpoint.is_mock() {
+            return Err(McpError::new(
+                ErrorCode::INVALID_REQUEST,
+                format!("Transactions can't be submitted through the mock TRP endpoint {}", endpoint.url),
+                None,
+            ));
+        }
+        let request = submit::submit(&self.http, &endpoint.url, &endpoint.headers(), tx.trim());
+        let started = Instant::now();
+        let result = match tokio::time::timeout(self.trp_timeout, request).await {
+            Ok(result) => result,
+            Err(_) => Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("TRP did not answer within {}ms", self.trp_timeout.as_millis()),
+                None,
+            )),
+        };
+
+        self.trp_health.record_latency(started.elapsed());
+        match &result {
+            Ok(_) => self.trp_health.record_success(),
+            Err(err) => self.trp_health.record_failure(&err.message),
+        }
+
+        Ok(CallToolResult::success(vec![Content::json(result?)?]))
+    }
+
+    async fn call_generic_tool(&self, name: &str, arguments: &Map<String, serde_json::Value>) -> Result<CallToolResult, McpError> {
+        match name {
+            generic::LIST_PROTOCOLS => {
+                let filter = generic::optional_string_arg(arguments, "filter");
+                let offset = generic::optional_usize_arg(arguments, "offset")?.unwrap_or(0);
+                self.list_protocols(filter, offset).await
+            }
+            generic::LIST_TRANSACTIONS => self.list_transactions(generic::string_arg(arguments, "protocol")?).await,
+            generic::DESCRIBE_TRANSACTION => {
+                let protocol = generic::string_arg(arguments, "protocol")?;
+                let transaction = generic::string_arg(arguments, "transaction")?;
+                self.describe_transaction(protocol, transaction).await
+            }
+            generic::RESOLVE_TRANSACTION => {
+                let protocol = generic::string_arg(arguments, "protocol")?;
+                let transaction = generic::string_arg(arguments, "transaction")?;
+                let mut args = generic::args_arg(arguments)?;
+                if let Some(decode) = arguments.get(DECODE_ARG) {
+                    args.insert(DECODE_ARG.to_string(), decode.clone());
+                }
+                let network = generic::optional_string_arg(arguments, NETWORK_ARG);
+                self.resolve_transaction(protocol, transaction, &args, network).await
+            }
+            _ => Err(McpError::new(
+                ErrorCode::RESOURCE_NOT_FOUND,
+                format!("Tool {} not found", name),
+                None,
+            )),
+        }
+    }
+
+    async fn list_protocols(&self, filter: Option<&str>, offset: usize) -> Result<CallToolResult, McpError> {
+        let protocols = self.protocols().await?;
+        let matching: Vec<ProtocolSource> = protocols.iter()
+            .filter(|protocol| filter.is_none_or(|filter| protocol.name.contains(filter)))
+            .cloned()
+            .collect();
+
+        let page: Vec<ProtocolSource> = matching.iter().skip(offset).take(LIST_PROTOCOLS_PAGE_SIZE).cloned().collect();
+        let compiled = self.compile_all(&page).await;
+
+        let listing: Vec<serde_json::Value> = page.iter()
+            .zip(compiled)
+            .map(|(protocol, compiled)| json!({
+                "name": protocol.name,
+                "scope": protocol.scope,
+                "version": protocol.version,
+                "description": protocol.description.as_deref()
+                    .map(|description| truncate(description.trim(), self.description_max_len)),
+                "transactions": compiled.ok()
+                    .map(|compiled| compiled.txs.iter().map(|tx| tx.name.clone()).collect::<Vec<_>>())
+                    .unwrap_or_default(),
+                "source_registry": protocol.registry_url,
+            }))
+            .collect();
+
+        let end = offset + listing.len();
+        let response = json!({
+            "protocols": listing,
+            "total": matching.len(),
+            "next_offset": (end < matching.len()).then_some(end),
+        });
+
+        Ok(CallToolResult::success(vec![Content::json(response)?]))
+    }
+
+    async fn list_transactions(&self, protocol_name: &str) -> Result<CallToolResult, McpError> {
+        let protocols = self.protocols().await?;
+        let Some(protocol) = protocols.iter().find(|protocol| protocol.name == protocol_name) else {
+            return Err(McpError::new(
+                ErrorCode::RESOURCE_NOT_FOUND,
+                format!("Protocol {} not found", protocol_name),
+                Some(json!({ "protocols": protocols.iter().map(|protocol| &protocol.name).collect::<Vec<_>>() })),
+            ));
+        };
+
+        let compiled = self.compile(protocol).await?;
+        let listing: Vec<serde_json::Value> = compiled.txs.iter()
+            .map(|tx| {
+                let parameters: Map<String, serde_json::Value> = tx.params.iter()
+                    .map(|(name, ty)| (name.clone(), params::param_schema(ty)))
+                    .collect();
+                json!({
+                    "name": tx.name,
+                    "docs": tx.docs,
+                    "parameters": parameters,
+                    "required": tx.params.keys().collect::<Vec<_>>(),
+                })
+            })
+            .collect();
+
+        Ok(CallToolResult::success(vec![Content::json(listing)?]))
+    }
+
+    async fn dispatch_tool(&self, request: CallToolRequestParam) -> Result<CallToolResult, McpError> {
+        if request.name == "registry-status" {
+            return self.registry_status().await;
+        }
+
+        if request.name == "trp-status" {
+            return self.trp_status().await;
+        }
+
+        let arguments = request.arguments.unwrap_or_default();
+
+        if request.name == submit::SUBMIT_TRANSACTION {
+            let network = generic::optional_string_arg(&arguments, NETWORK_ARG);
+            let tx = match generic::optional_string_arg(&arguments, "tx_hash") {
+                Some(hash) => self.recent_txs.get(hash.trim()).ok_or_else(|| McpError::new(
+                    ErrorCode::INVALID_PARAMS,
+                    format!("No transaction with hash {} was resolved in this session recently, pass its CBOR as tx instead", hash),
+                    None,
+                ))?,
+                None => generic::string_arg(&arguments, "tx")?.to_string(),
+            };
+            return self.submit_transaction(&tx, network).await;
+        }
+
+        if request.name == generic::LIST_PROTOCOLS || request.name == generic::LIST_TRANSACTIONS {
+            return self.call_generic_tool(&request.name, &arguments).await;
+        }
+
+        if request.name == generic::VALIDATE_TX3 {
+            return self.validate_tx3(generic::string_arg(&arguments, "source")?).await;
+        }
+
+        if request.name == generic::GET_TIR {
+            let protocol = generic::string_arg(&arguments, "protocol")?;
+            let transaction = generic::string_arg(&arguments, "transaction")?;
+            return self.get_tir(protocol, transaction).await;
+        }
+
+        if self.tool_mode == ToolMode::Generic
+            || (!self.expose_describe_tools && request.name == generic::DESCRIBE_TRANSACTION)
+        {
+            return self.call_generic_tool(&request.name, &arguments).await;
+        }
+
+        let target = self.tool_target(&request.name).await?;
+        match target.operation {
+            ToolOperation::Describe => self.describe_transaction(&target.protocol, &target.transaction).await,
+            ToolOperation::Resolve => self.resolve_transaction(&target.protocol, &target.transaction, &arguments, None).await,
+        }
+    }
+
+    /// Metrics in the Prometheus text format.
+    #[allow(dead_code)]
+    pub fn render_metrics(&self) -> String {
+        let (hits, misses) = self.compiled.stats();
+        self.metrics.render(hits, misses, self.active_sessions())
+    }
+
+    /// Records a request from the client, called first thing by every request handler so
+    /// the idle watchdog only closes sessions that really went quiet.
+    fn touch(&self) {
+        *self.last_request_at.lock().unwrap() = Instant::now();
+    }
+
+    /// Time since the session's last request.
+    #[allow(dead_code)]
+    pub fn idle_for(&self) -> Duration {
+        self.last_request_at.lock().unwrap().elapsed()
+    }
+
+    /// Sessions currently connected.
+    pub fn active_sessions(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Forgets a session once its connection ends, releasing its subscriptions and
+    /// notification state.
+    #[allow(dead_code)]
+    pub fn close_session(&self) {
+        let mut sessions = self.sessions.lock().unwrap();
+        sessions.retain(|session| session.id != self.session_id);
+        tracing::info!("Session closed, {} active", sessions.len());
+    }
+
+    /// Values suggested for an argument of a transaction prompt, based on its tx3 type.
+    async fn prompt_completions(&self, prompt_name: &str, argument: &str) -> Result<Vec<String>, McpError> {
+        let protocols = self.protocols().await?;
+        let mut param_type = None;
+        for (protocol, compiled) in protocols.iter().zip(self.compile_all(&protocols).await) {
+            let Ok(compiled) = compiled else {
+                continue;
+            };
+            if let Some(tx) = compiled.txs.iter().find(|tx| tool_name("build", &protocol.name, &tx.name) == prompt_name) {
+                param_type = tx.params.get(argument).cloned();
+                break;
+            }
+        }
+
+        Ok(match param_type {
+            Some(tx3_lang::ir::Type::Bool) => vec!["true".to_string(), "false".to_string()],
+            Some(tx3_lang::ir::Type::Address) => self.known_addresses.values().cloned().collect(),
+            None if argument == NETWORK_ARG => self.networks.keys().cloned().collect(),
+            _ => Vec::new(),
+        })
+    }
+
+    async fn registry_status(&self) -> Result<CallToolResult, McpError> {
+        let cache = self.cache.read().await;
+        let mut status = self.breaker.status();
+        status["registries"] = json!(self.registries.iter().map(|registry| registry.url()).collect::<Vec<_>>());
+        status["cached_protocols"] = json!(cache.protocols.len());
+        status["cache_age_secs"] = json!(cache.fetched_at.map(|at| at.elapsed().as_secs()));
+        Ok(CallToolResult::success(vec![Content::json(status)?]))
+    }
+
+    async fn trp_status(&self) -> Result<CallToolResult, McpError> {
+        let mut status = self.trp_health.status();
+        if self.networks.is_empty() {
+            status["url"] = json!(self.trp.redacted_url());
+        }
+        status["networks"] = json!(self.networks.iter()
+            .map(|(name, endpoint)| (name.clone(), json!(endpoint.redacted_url())))
+            .collect::<Map<_, _>>());
+        status["endpoints"] = json!(self.trp_endpoints.iter()
+            .map(|(name, endpoint)| (name.clone(), json!(endpoint.redacted_url())))
+            .collect::<Map<_, _>>());
+        Ok(CallToolResult::success(vec![Content::json(status)?]))
+    }
+
+    /// Refuses new tool calls and waits up to `timeout` for the running ones to finish.
+    /// Calls still running past it are cancelled, so their clients get an error instead
+    /// of a dropped stream.
+    pub async fn drain(&self, timeout: Duration) {
+        self.draining.store(true, Ordering::Release);
+
+        let deadline = Instant::now() + timeout;
+        while self.active_calls.load(Ordering::Acquire) > 0 && Instant::now() < deadline {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+
+        let remaining = self.active_calls.load(Ordering::Acquire);
+        if remaining == 0 {
+            tracing::info!("All tool calls finished");
+            return;
+        }
+
+        tracing::warn!("Cancelling {} tool calls still running after {:?}", remaining, timeout);
+        self.shutdown.cancel();
+        // Gives the cancelled calls a moment to send their errors.
+        let deadline = Instant::now() + DRAIN_POLL_INTERVAL * 10;
+        while self.active_calls.load(Ordering::Acquire) > 0 && Instant::now() < deadline {
+            tokio::time::sleep(DRAIN_POLL_INTERVAL).await;
+        }
+    }
+
+    /// Ready once the protocols have been fetched and compiled, from the registries or a
+    /// snapshot. Until then a refresh is started, so probes bring the server up without
+    /// waiting for a client, and the error explains what's missing.
+    #[allow(dead_code)]
+    pub async fn readiness(&self) -> Result<(), serde_json::Value> {
+        if self.cache.read().await.fetched_at.is_some() {
+            return Ok(());
+        }
+
+        self.spawn_refresh();
+        Err(json!({
+            "ready": false,
+            "registry": "Protocols haven't been fetched from the registry yet",
+            "registry_status": self.breaker.status(),
+        }))
+    }
+
+    /// Probes every configured TRP endpoint, logging the outcome of each, and fails
+    /// with the first problem found. Meant to run once at startup.
+    pub async fn probe_trp(&self) -> Result<(), String> {
+        let first_error = self.probe_trp_endpoints().await.into_iter().find_map(|check| {
+            check.error.map(|err| format!("TRP {} at {}: {}", check.endpoint, check.url, err))
+        });
+
+        match first_error {
+            Some(err) => Err(err),
+            None => Ok(()),
+        }
+    }
+
+    async fn probe_trp_endpoints(&self) -> Vec<TrpCheck> {
+        let mut endpoints: Vec<(String, &TrpEndpoint)> = Vec::new();
+        if self.networks.is_empty() {
+            endpoints.push(("default".to_string(), &self.trp));
+        }
+        endpoints.extend(self.networks.iter().map(|(name, endpoint)| (format!("network {}", name), endpoint)));
+        endpoints.extend(self.trp_endpoints.iter().map(|(name, endpoint)| (format!("endpoint {}", name), endpoint)));
+
+        let mut checks = Vec::with_capacity(endpoints.len());
+        for (label, endpoint) in endpoints {
+            let url = endpoint.redacted_url();
+            match endpoint.probe(&self.http, self.trp_timeout).await {
+                Ok(latency) => {
+                    tracing::info!("TRP {} at {} is reachable ({}ms)", label, url, latency.as_millis());
+                    self.trp_health.record_latency(latency);
+                    self.trp_health.record_success();
+                    checks.push(TrpCheck { endpoint: label, url, latency_ms: Some(latency.as_millis()), error: None });
+                }
+                Err(err) => {
+                    let message = format!("TRP {} at {}: {}", label, url, err);
+                    tracing::error!("{}", message);
+                    self.trp_health.record_failure(&message);
+                    checks.push(TrpCheck { endpoint: label, url, latency_ms: None, error: Some(err) });
+                }
+            }
+        }
+        checks
+    }
+
+    /// Fetches every registry, compiles every protocol, lists the tools and probes TRP,
+    /// reporting each problem instead of stopping at the first. The compiled protocols
+    /// are kept, as after a refresh.
+    pub async fn check(&self) -> CheckReport {
+        let results = join_all(self.registries.iter().map(|registry| registry.fetch_protocols())).await;
+
+        let mut merged = self.local_protocols.clone();
+        let mut registries = Vec::with_capacity(self.registries.len());
+        for (registry, result) in self.registries.iter().zip(results) {
+            let url = registry.url().to_string();
+            match result {
+                Ok(protocols) => {
+                    registries.push(RegistryCheck { url, protocols: protocols.len(), error: None });
+                    merged.extend(protocols);
+                }
+                Err(err) => registries.push(RegistryCheck { url, protocols: 0, error: Some(err.message.to_string()) }),
+            }
+        }
+
+        let fetched = self.apply_version_pins(dedupe_protocols(merged)).await;
+        let (candidates, excluded): (Vec<ProtocolSource>, Vec<ProtocolSource>) = fetched.into_iter()
+            .partition(|protocol| self.is_protocol_allowed(&protocol.name) && self.is_protocol_sane(protocol));
+
+        let compiled = self.compile_all(&candidates).await;
+        let mut protocols = Vec::with_capacity(candidates.len());
+        let mut healthy = Vec::with_capacity(candidates.len());
+        for (protocol, compiled) in candidates.into_iter().zip(compiled) {
+            let (transactions, error) = match compiled {
+                Ok(compiled) => (compiled.txs.iter().map(|tx| tx.name.clone()).collect(), None),
+                Err(err) => (Vec::new(), Some(err.message.to_string())),
+            };
+            protocols.push(ProtocolCheck {
+                name: protocol.name.clone(),
+                source: protocol.registry_url.clone(),
+                transactions,
+                error: error.clone(),
+            });
+            if error.is_none() {
+                healthy.push(protocol);
+            }
+        }
+
+        self.store(healthy).await;
+        let tools = self.build_tools().await
+            .map(|snapshot| snapshot.tools.iter().map(|tool| tool.name.to_string()).collect())
+            .map_err(|err| err.message.to_string());
+
+        CheckReport::new(
+            registries,
+            protocols,
+            excluded.into_iter().map(|protocol| protocol.name).collect(),
+            tools,
+            self.probe_trp_endpoints().await,
+        )
+    }
+
+    /// Keeps the protocol list warm by refreshing it every `interval` until `ct` is
+    /// cancelled. Failed refreshes leave the previous snapshot in place.
+    pub fn spawn_refresh_task(&self, interval: Duration, ct: CancellationToken) -> JoinHandle<()> {
+        let tool = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            loop {
+                tokio::select! {
+                    _ = ct.cancelled() => break,
+                    _ = ticker.tick() => {
+                        if let Err(err) = tool.refresh().await {
+                            tracing::warn!("Failed to refresh protocols from registry: {}", err.message);
+                        }
+                    }
+                }
+            }
+            tracing::info!("registry refresh task stopped");
+        })
+    }
+
+    /// Reloads file backed TRP and registry keys on SIGHUP, and whenever a key file's
+    /// modification time changes, checked every `interval`.
+    pub fn spawn_secret_reload_task(&self, interval: Duration, ct: CancellationToken) -> Option<JoinHandle<()>> {
+        let secrets: Vec<Secret> = std::iter::once(&self.trp)
+            .chain(self.networks.values())
+            .chain(self.trp_endpoints.values())
+            .filter_map(|endpoint| endpoint.key.clone())
+            .chain(self.registries.iter().filter_map(|registry| registry.key().cloned()))
+            .filter(|secret| secret.path().is_some())
+            .collect();
+        if secrets.is_empty() {
+            return None;
+        }
+
+        Some(tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            let mut hangup = hangup_signal();
+            loop {
+                let forced = tokio::select! {
+                    _ = ct.cancelled() => break,
+                    _ = ticker.tick() => false,
+                    _ = hangup.recv() => true,
+                };
+                for secret in secrets.iter() {
+                    let path = secret.path().unwrap().display();
+                    let reloaded = if forced { secret.reload() } else { secret.reload_if_modified() };
+                    match reloaded {
+                        Ok(true) => tracing::info!("Reloaded key from {}", path),
+                        Ok(false) => {}
+                        Err(err) => tracing::warn!("Keeping the previous key, failed to reload {}: {}", path, err),
+                    }
+                }
+            }
+            tracing::info!("key reload task stopped");
+        }))
+    }
+
+    /// Fetches and compiles the protocols and builds the tool list in the background, so
+    /// the client's first `tools/list` is served from memory. Caches are shared, so this
+    /// is a no-op for sessions after the first. A failure leaves the caches empty and the
+    /// next request fetches them again.
+    fn spawn_warmup(&self) {
+        if self.warmup == Warmup::Lazy {
+            return;
+        }
+
+        let tool = self.clone();
+        tokio::spawn(async move {
+            let started = Instant::now();
+            match tool.build_tools().await {
+                Ok(snapshot) => tracing::debug!("Warmed up {} tools in {:?}", snapshot.tools.len(), started.elapsed()),
+                Err(err) => tracing::warn!("Warm-up failed, protocols will be fetched on demand: {}", err.message),
+            }
+        });
+    }
+
+    /// Instructions describing the tool naming and the protocols currently cached. Falls
+    /// back to a generic text before the first registry fetch, as clients may initialize
+    /// before any protocol is known.
+    fn instructions(&self) -> String {
+        let mut protocols: Vec<String> = match self.cache.try_read() {
+            Ok(cache) if cache.fetched_at.is_some() => cache.protocols.iter().map(|protocol| protocol.name.clone()).collect(),
+            _ => Vec::new(),
+        };
+
+        let mut text = if protocols.is_empty() {
+            STATIC_INSTRUCTIONS.to_string()
+        } else {
+            let usage = match self.tool_mode {
+                ToolMode::PerTransaction => "Each transaction of a tx3 protocol has a `resolve-<protocol>-<transaction>` tool that builds it \
+                    and a `describe-<protocol>-<transaction>` tool that lists its parameters. Describe a transaction before resolving it.",
+                ToolMode::Generic => "Use `list-protocols` and `list-transactions` to explore the tx3 protocols, `describe-transaction` \
+                    to see a transaction's parameters and `resolve-transaction` to build it.",
+            };
+
+            protocols.sort();
+            let names: Vec<&str> = protocols.iter().take(self.instructions_max_protocols).map(String::as_str).collect();
+            let mut listed = names.join(", ");
+            if protocols.len() > names.len() {
+                listed.push_str(&format!(" and {} more", protocols.len() - names.len()));
+            }
+
+            format!(
+                "{}\n\nAvailable protocols: {}.\n\nParameter formats: bytes are hex encoded, addresses are bech32 \
+                    (ask the user for them rather than guessing), UTxO references are {{tx_hash}}#{{index}}, and large integers \
+                    may be passed as decimal strings.",
+                usage, listed,
+            )
+        };
+
+        if let Some(extra) = &self.instructions_extra {
+            text.push_str("\n\n");
+            text.push_str(extra);
+        }
+        text
+    }
+
+    /// Rescans the client's roots in the background and refreshes the protocol list,
+    /// which notifies the client when the tools changed. The roots are requested outside
+    /// the notification handler so the client's response can be received.
+    fn spawn_root_scan(&self, peer: Peer<RoleServer>) {
+        let Some(scan) = self.root_scan.clone() else {
+            return;
+        };
+
+        let tool = self.clone();
+        tokio::spawn(async move {
+            let roots: Vec<String> = match peer.list_roots().await {
+                Ok(result) => result.roots.into_iter().map(|root| root.uri).collect(),
+                Err(err) => {
+                    tracing::debug!("Client didn't list its roots: {}", err);
+                    return;
+                }
+            };
+
+            let protocols = match tokio::task::spawn_blocking(move || scan.scan(&roots)).await {
+                Ok(protocols) => protocols,
+                Err(err) => {
+                    tracing::warn!("Failed to scan the client roots: {}", err);
+                    return;
+                }
+            };
+
+            tracing::info!("Found {} protocols in the client roots", protocols.len());
+            *tool.root_protocols.write().await = protocols;
+            if let Err(err) = tool.refresh().await {
+                tracing::warn!("Failed to refresh protocols after scanning the client roots: {}", err.message);
+            }
+        });
+    }
+
+    /// Queries every registry. One that fails is served from its last successful fetch
+    /// and the flag returned is false; when every registry fails the fetch fails.
+    async fn fetch_registries(&self) -> Result<(Vec<ProtocolSource>, bool), McpError> {
+        let results = join_all(self.registries.iter().map(|registry| registry.fetch_protocols())).await;
+
+        let mut fetched = Vec::new();
+        let mut failed = 0;
+        let mut last_error = None;
+        let mut last_good = self.registry_protocols.write().await;
+        for (registry, result) in self.registries.iter().zip(results) {
+            match result {
+                Ok(protocols) => {
+                    last_good.insert(registry.url().to_string(), protocols.clone());
+                    fetched.extend(protocols);
+                }
+                Err(err) => {
+                    match last_good.get(registry.url()) {
+                        Some(protocols) => {
+                            tracing::warn!(
+                                "Registry {} failed, serving its {} protocols from the last fetch: {}",
+                                registry.url(),
+                                protocols.len(),
+                                err.message
+                            );
+                            fetched.extend(protocols.iter().cloned());
+                        }
+                        None => tracing::warn!("Skipping registry {}: {}", registry.url(), err.message),
+                    }
+                    failed += 1;
+                    last_error = Some(err);
+                }
+            }
+        }
+
+        match last_error {
+            Some(err) if failed == self.registries.len() => Err(err),
+            last_error => Ok((fetched, last_error.is_none())),
+        }
+    }
+
+    /// Layers the local and client root protocols over the registry ones, then keeps
+    /// those the pins, allowlist, denylist and sanity checks let through and that compile.
+    async fn load_protocols(&self, registry: Vec<ProtocolSource>) -> Vec<ProtocolSource> {
+        let mut merged = self.local_protocols.clone();
+        merged.extend(self.root_protocols.read().await.iter().cloned());
+        merged.extend(registry);
+
+        let fetched = self.apply_version_pins(dedupe_protocols(merged)).await;
+        let total = fetched.len();
+
+        let candidates = self.admissible(fetched);
+
+        let compiled = self.compile_all(&candidates).await;
+        let protocols: Vec<ProtocolSource> = candidates.into_iter()
+            .zip(compiled)
+            .filter_map(|(protocol, compiled)| compiled.is_ok().then_some(protocol))
+            .collect();
+
+        if protocols.len() < total {
+            tracing::info!("Excluded {} of {} protocols by allowlist/denylist or validation", total - protocols.len(), total);
+        }
+
+        protocols
+    }
+}
+
+/// SIGHUP as a stream of notifications. Without signals it never fires, leaving key
+/// rotation to the modification time checks.
+struct HangupSignal {
+    #[cfg(unix)]
+    signal: Option<tokio::signal::unix::Signal>,
+}
+
+fn hangup_signal() -> HangupSignal {
+    HangupSignal {
+        #[cfg(unix)]
+        signal: tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()).ok(),
+    }
+}
+
+impl HangupSignal {
+    async fn recv(&mut self) {
+        #[cfg(unix)]
+        if let Some(signal) = self.signal.as_mut() {
+            if signal.recv().await.is_some() {
+                return;
+            }
+            self.signal = None;
+        }
+        std::future::pending::<()>().await
+    }
+}
+
+/// Resolves `scope_name` collisions in registry order. A protocol shadowed by the same
+/// name from an earlier source, or republishing identical content, is dropped; any other
+/// collision, including names that only differ in case, gets a numeric suffix so its
+/// transactions stay reachable.
+fn dedupe_protocols(protocols: Vec<ProtocolSource>) -> Vec<ProtocolSource> {
+    let mut deduped: Vec<ProtocolSource> = Vec::new();
+    let mut taken: HashMap<String, usize> = HashMap::new();
+
+    for mut protocol in protocols {
+        let key = protocol.name.to_lowercase();
+        if let Some(&index) = taken.get(&key) {
+            let winner = &deduped[index];
+            let shadowed = winner.name == protocol.name
+                && (winner.registry_url != protocol.registry_url || winner.content == protocol.content);
+            if shadowed {
+                tracing::warn!(
+                    "Protocol {} from {} is shadowed by the one from {}",
+                    protocol.name,
+                    protocol.registry_url,
+                    winner.registry_url
+                );
+                continue;
+            }
+
+            let mut suffix = 2;
+            while taken.contains_key(&format!("{}_{}", key, suffix)) {
+                suffix += 1;
+            }
+            let renamed = format!("{}_{}", protocol.name, suffix);
+            tracing::warn!(
+                "Protocol {} from {} collides with {} from {}, exposing it as {}",
+                protocol.name,
+                protocol.registry_url,
+                winner.name,
+                winner.registry_url,
+                renamed
+            );
+            protocol.name = renamed;
+        }
+
+        taken.insert(protocol.name.to_lowercase(), deduped.len());
+        deduped.push(protocol);
+    }
+
+    deduped
+}
+
+fn fingerprint(protocols: &[ProtocolSource]) -> Vec<(&str, u64)> {
+    let mut fingerprint: Vec<(&str, u64)> = protocols.iter()
+        .map(|protocol| (protocol.name.as_str(), protocol.content_hash()))
+        .collect();
+    fingerprint.sort();
+    fingerprint
+}
+
+/// JSON Schema of a transaction's parameters, used both as the resolve tool input
+/// schema and as the describe output so clients can validate arguments up front.
+fn resolve_input_schema(protocol_name: &str, tx: &CompiledTx) -> Map<String, serde_json::Value> {
+    let mut properties = Map::new();
+    let mut required = Vec::new();
+    for param in tx.params.iter() {
+        properties.insert(param.0.clone(), params::param_schema(param.1));
+        required.push(serde_json::Value::String(param.0.clone()));
+    }
+
+    let mut input_schema = Map::new();
+    input_schema.insert("type".to_string(), serde_json::Value::String("object".to_string()));
+    input_schema.insert("$schema".to_string(), serde_json::Value::String("http://json-schema.org/draft-07/schema#".to_string()));
+    input_schema.insert("title".to_string(), serde_json::Value::String(format!("resolve_{}_{}_params", protocol_name, tx.name)));
+    input_schema.insert("properties".to_string(), serde_json::Value::Object(properties));
+    input_schema.insert("required".to_string(), serde_json::Value::Array(required));
+    if tx.params.is_empty() {
+        input_schema.insert("description".to_string(), serde_json::Value::String("This transaction takes no parameters, call it without arguments".to_string()));
+    }
+    input_schema
+}
+
+/// The TIR of a transaction as sent to TRP, shared by `get-tir` and the TIR resource.
+fn tir_document(tx: &CompiledTx) -> serde_json::Value {
+    json!({
+        "bytecode": hex::encode(&tx.tir),
+        "encoding": "hex",
+        "version": tx3_lang::ir::IR_VERSION,
+        "tx3_lang_version": compiler::TX3_LANG_VERSION,
+    })
+}
+
+fn json_resource(uri: String, document: serde_json::Value) -> ReadResourceResult {
+    ReadResourceResult {
+        contents: vec![ResourceContents::TextResourceContents {
+            uri,
+            mime_type: Some("application/json".to_string()),
+            text: document.to_string(),
+        }],
+    }
+}
+
+fn add_decode_argument(schema: &mut Map<String, serde_json::Value>) {
+    if let Some(serde_json::Value::Object(properties)) = schema.get_mut("properties") {
+        properties.entry(DECODE_ARG).or_insert(json!({
+            "type": "boolean",
+            "default": false,
+            "description": "Also return a readable summary of the transaction: inputs, outputs, fee, mint, metadata and validity",
+        }));
+    }
+}
+
+/// Origin of a generated tool, attached under `x-tx3` in its input schema since the
+/// pinned rmcp has no `_meta` on tools.
+fn protocol_metadata(protocol: &ProtocolSource) -> serde_json::Value {
+    let dapp_name = protocol.scope.as_deref()
+        .and_then(|scope| protocol.name.strip_prefix(scope))
+        .and_then(|name| name.strip_prefix('_'))
+        .unwrap_or(&protocol.name);
+
+    json!({
+        "scope": protocol.scope,
+        "dapp_name": dapp_name,
+        "registry_url": protocol.registry_url,
+        "content_hash": format!("{:016x}", protocol.content_hash()),
+    })
+}
+
+/// Shape of the JSON content block returned by resolve tools. The pinned rmcp has no
+/// `output_schema` on tools yet, so it's published through describe instead.
+fn resolve_output_schema() -> serde_json::Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "tx": { "type": "string", "description": "Resolved transaction as CBOR" },
+            "encoding": { "type": "string", "enum": ["hex"] },
+            "hash": { "type": "string", "description": "Transaction id, hex encoded" },
+            "warning": { "type": "string", "description": "Set when the hash couldn't be computed" },
+            "resolved_args": { "type": "object", "description": "Arguments sent to TRP after coercion, with their tx3 type" },
+        },
+        "required": ["tx", "encoding"],
+    })
+}
+
+fn empty_object_schema() -> Map<String, serde_json::Value> {
+    let mut schema = Map::new();
+    schema.insert("type".to_string(), serde_json::Value::String("object".to_string()));
+    schema.insert("properties".to_string(), serde_json::Value::Object(Map::new()));
+    schema
+}
+
+/// Argument selecting a named TRP network.
+const NETWORK_ARG: &str = "network";
+
+/// Argument asking resolve to include a decoded summary of the transaction.
+const DECODE_ARG: &str = "decode";
+
+/// Protocols returned per `list-protocols` call.
+const LIST_PROTOCOLS_PAGE_SIZE: usize = 50;
+
+/// Longest tool name accepted by common MCP clients.
+const MAX_TOOL_NAME_LEN: usize = 64;
+
+/// Pages through a listing with the numeric offset cursors handed out by `tools/list`
+/// and `resources/list`.
+fn paginate<T>(items: Vec<T>, cursor: Option<String>, page_size: usize, method: &str) -> Result<(Vec<T>, Option<String>), McpError> {
+    let start = match cursor {
+        Some(cursor) => cursor
+            .parse::<usize>()
+            .ok()
+            .filter(|start| *start <= items.len())
+            .ok_or_else(|| McpError::new(
+                ErrorCode::INVALID_PARAMS,
+                format!("Invalid {} cursor {}", method, cursor),
+                None,
+            ))?,
+        None => 0,
+    };
+
+    let end = (start + page_size).min(items.len());
+    let next_cursor = (end < items.len()).then(|| end.to_string());
+    Ok((items.into_iter().skip(start).take(page_size).collect(), next_cursor))
+}
+
+/// What the server advertises on initialize. Notifications are only sent for the
+/// capabilities listed here.
+/// What the server offers a client speaking `version`. Completions only exist since
+/// 2025-03-26, while tool annotations go to every client as older ones ignore them.
+fn capabilities(version: &ProtocolVersion) -> ServerCapabilities {
+    let mut capabilities = ServerCapabilities::builder()
+        .enable_tools()
+        .enable_tool_list_changed()
+        .enable_resources()
+        .enable_resources_subscribe()
+        .enable_prompts()
+        .enable_logging()
+        .build();
+    if *version != ProtocolVersion::V_2024_11_05 {
+        capabilities.completions = Some(Map::new());
+    }
+    capabilities
+}
+
+/// Counts a tool call as running until dropped, so calls whose future is dropped by a
+/// client cancel or a closed transport don't keep `drain` waiting.
+struct ActiveCall(Arc<AtomicUsize>);
+
+impl ActiveCall {
+    fn start(active_calls: &Arc<AtomicUsize>) -> Self {
+        active_calls.fetch_add(1, Ordering::AcqRel);
+        Self(active_calls.clone())
+    }
+}
+
+impl Drop for ActiveCall {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+/// Protocol revisions the server speaks, newest first.
+const SUPPORTED_VERSIONS: [ProtocolVersion; 2] = [ProtocolVersion::V_2025_03_26, ProtocolVersion::V_2024_11_05];
+
+/// The client's requested revision when supported, otherwise the newest one the server
+/// speaks, leaving it to the client to disconnect if it can't use it.
+fn negotiate_version(requested: &ProtocolVersion) -> ProtocolVersion {
+    SUPPORTED_VERSIONS.iter()
+        .find(|version| *version == requested)
+        .unwrap_or(&SUPPORTED_VERSIONS[0])
+        .clone()
+}
+
+static NEXT_SESSION_ID: AtomicU64 = AtomicU64::new(0);
+
+fn next_session_id() -> u64 {
+    NEXT_SESSION_ID.fetch_add(1, Ordering::Relaxed)
+}
+
+/// Tools that reach TRP count against the rate limit; listing and describing don't.
+fn is_rate_limited(tool: &str) -> bool {
+    tool.starts_with("resolve-") || tool == generic::RESOLVE_TRANSACTION || tool == submit::SUBMIT_TRANSACTION
+}
+
+/// How often `drain` checks whether the running tool calls finished.
+const DRAIN_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Instructions sent before any protocol is known.
+const STATIC_INSTRUCTIONS: &str = "This server provides a protocol tool that can be use to comunicate with tx3 files for listing and resolving the transactions inside them.";
+
+/// Logger name of the notifications sent to clients.
+const LOGGER: &str = "tx3-mcp";
+
+/// Clients get warnings and errors until they pick a level with `logging/setLevel`.
+const DEFAULT_LOG_LEVEL: LoggingLevel = LoggingLevel::Warning;
+
+/// Severity of a log level, for comparing against the client's threshold.
+fn log_rank(level: LoggingLevel) -> u8 {
+    match level {
+        LoggingLevel::Debug => 0,
+        LoggingLevel::Info => 1,
+        LoggingLevel::Notice => 2,
+        LoggingLevel::Warning => 3,
+        LoggingLevel::Error => 4,
+        LoggingLevel::Critical => 5,
+        LoggingLevel::Alert => 6,
+        LoggingLevel::Emergency => 7,
+    }
+}
+
+/// Most completion values a response may carry, per the MCP spec.
+const MAX_COMPLETIONS: usize = 100;
+
+/// Keeps the candidates matching what was typed so far, prefix matches first.
+fn rank_completions(candidates: Vec<String>, typed: &str) -> CompletionInfo {
+    let typed = typed.to_lowercase();
+    let mut matches: Vec<(bool, String)> = candidates.into_iter()
+        .filter_map(|candidate| {
+            let lower = candidate.to_lowercase();
+            if lower.starts_with(&typed) {
+                Some((false, candidate))
+            } else if lower.contains(&typed) {
+                Some((true, candidate))
+            } else {
+                None
+            }
+        })
+        .collect();
+    matches.sort();
+    matches.dedup();
+
+    let total = matches.len();
+    CompletionInfo {
+        values: matches.into_iter().take(MAX_COMPLETIONS).map(|(_, value)| value).collect(),
+        total: Some(total as u32),
+        has_more: Some(total > MAX_COMPLETIONS),
+    }
+}
+
+/// Builds a tool name restricted to `[a-zA-Z0-9_-]`. Names over the length limit are
+/// cut and suffixed with a hash of the full name so they stay unique.
+fn tool_name(operation: &str, protocol: &str, transaction: &str) -> String {
+    let name: String = format!("{}-{}-{}", operation, protocol, transaction)
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '_' || c == '-' { c } else { '_' })
+        .collect();
+
+    if name.len() <= MAX_TOOL_NAME_LEN {
+        return name;
+    }
 
-        let prototx = {
-            let tx3_protocol = tx3_lang::Protocol::from_string(protocol.content.to_string()).load().unwrap();
-            let prototx_result = tx3_protocol.new_tx(transaction_name.as_str());
-            if prototx_result.is_err() {
-                return Err(McpError::new(
-                    ErrorCode::RESOURCE_NOT_FOUND,
-                    format!("Transaction {} not found for protocol {}", transaction_name, protocol_name),
-                    None,
-                ));
-            }
-            prototx_result.unwrap()
-        };
+    let mut hasher = DefaultHasher::new();
+    name.hash(&mut hasher);
+    format!("{}-{:08x}", &name[..MAX_TOOL_NAME_LEN - 9], hasher.finish() as u32)
+}
 
-        let parameters_types = prototx.find_params();
+fn truncate(text: &str, max_len: usize) -> String {
+    match text.char_indices().nth(max_len) {
+        Some((index, _)) => format!("{}…", &text[..index]),
+        None => text.to_string(),
+    }
+}
 
-        if operation_name == "describe" {
-            let mut parameters = Map::new();
-            for (param_name, param_type) in parameters_types.iter() {
-                parameters.insert(param_name.clone(), serde_json::Value::String(format!("{:?}", param_type)));
-            }
-            let mut response = Map::new();
-            response.insert("protocol".to_string(), serde_json::Value::String(protocol_name));
-            response.insert("transaction".to_string(), serde_json::Value::String(transaction_name));
-            response.insert("parameters".to_string(), serde_json::Value::Object(parameters));
-            return Ok(CallToolResult::success(vec![Content::json(response)?]));
+impl ServerHandler for ProtocolTool {
+    fn get_info(&self) -> ServerInfo {
+        let protocol_version = self.protocol_version.lock().unwrap().clone();
+        ServerInfo {
+            capabilities: capabilities(&protocol_version),
+            protocol_version,
+            server_info: Implementation::from_build_env(),
+            instructions: Some(self.instructions()),
         }
+    }
 
-        let parameters = request.arguments.is_some()
-            .then(|| request.arguments.unwrap())
-            .unwrap_or_default();
+    async fn list_tools(
+        &self,
+        request: Option<PaginatedRequestParam>,
+        _context: RequestContext<RoleServer>,
+    ) -> Result<ListToolsResult, McpError> {
+        self.touch();
+        // Annotations are sent to 2024-11-05 clients too, which ignore fields they don't know.
+        let tools = self.build_tools().await?.tools.clone();
 
-        let mut args: HashMap<String, tx3_lang::ArgValue> = HashMap::new();
-        for (arg_name, value) in parameters.iter() {
-            let string_value = value.as_str().ok_or_else(|| {
-                McpError::new(
-                    ErrorCode::RESOURCE_NOT_FOUND,
-                    format!("Invalid value provided for parameter {}", arg_name),
-                    None
-                )
-            }).unwrap();
+        let Some(page_size) = self.tools_page_size else {
+            return Ok(ListToolsResult { tools, next_cursor: None });
+        };
 
-            let arg_type = parameters_types.get(arg_name).ok_or_else(|| {
-                McpError::new(
-                    ErrorCode::RESOURCE_NOT_FOUND,
-                    format!("Parameter {} not found for transaction {} in protocol {}", arg_name, transaction_name, protocol_name),
-                    None
-                )
-            }).unwrap();
+        let (tools, next_cursor) = paginate(tools, request.and_then(|request| request.cursor), page_size, "tools/list")?;
+        Ok(ListToolsResult { tools, next_cursor })
+    }
 
-            let mut arg_value: Option<tx3_lang::ArgValue> = None;
-            if *arg_type == tx3_lang::ir::Type::Int {
-                arg_value = Some(tx3_lang::ArgValue::Int(string_value.parse::<i128>().unwrap()));
-            }
-            if *arg_type == tx3_lang::ir::Type::Bool {
-                arg_value = Some(tx3_lang::ArgValue::Bool(string_value.parse::<bool>().unwrap()));
-            }
-            if *arg_type == tx3_lang::ir::Type::Bytes {
-                arg_value = Some(tx3_lang::ArgValue::String(string_value.to_string()));
-            }
-            if *arg_type == tx3_lang::ir::Type::Address {
-                arg_value = Some(tx3_lang::ArgValue::String(string_value.to_string()));
-            }
+    async fn call_tool(
+        &self,
+        request: CallToolRequestParam,
+        context: RequestContext<RoleServer>,
+    ) -> Result<CallToolResult, McpError> {
+        self.touch();
+        if self.draining.load(Ordering::Acquire) {
+            return Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                "Server is shutting down, retry the call once it's back",
+                None,
+            ));
+        }
 
-            if arg_value.is_none() {
+        if let Some(limiter) = self.rate_limiter.as_ref().filter(|_| is_rate_limited(&request.name)) {
+            let key = self.caller();
+            if let Err(retry_after) = limiter.acquire(&key, Instant::now()) {
+                let retry_after_ms = u64::try_from(retry_after.as_millis()).unwrap_or(u64::MAX);
+                tracing::debug!("Rate limited {} for {}", request.name, key);
                 return Err(McpError::new(
-                    ErrorCode::RESOURCE_NOT_FOUND,
-                    format!("Invalid value provided for parameter {}", arg_name),
-                    None
+                    ErrorCode::INVALID_REQUEST,
+                    format!(
+                        "Rate limit of {} calls per minute exceeded, retry in {}s",
+                        limiter.per_minute(),
+                        retry_after.as_secs_f64().ceil(),
+                    ),
+                    Some(json!({
+                        "kind": "rate_limited",
+                        "retry_after_ms": retry_after_ms,
+                        "limit_per_minute": limiter.per_minute(),
+                    })),
                 ));
             }
-
-            args.insert(arg_name.clone(), arg_value.unwrap());
         }
 
-        let client = TrpClient::new(ClientOptions {
-            endpoint: self.trp_url.clone(),
-            headers: Some(HashMap::from([("dmtr-api-key".to_string(), self.trp_key.clone())])),
-            env_args: None,
-        });
-
-        let result = client.resolve(ProtoTxRequest {
-            tir: TirInfo {
-                bytecode: hex::encode(prototx.ir_bytes()),
-                encoding: "hex".to_string(),
-                version: tx3_lang::ir::IR_VERSION.to_string(),
-            },
-            args
-        }).await;
+        // rmcp cancels the request's token when the client sends notifications/cancelled,
+        // which aborts the call, including any in-flight TRP request.
+        let _active = ActiveCall::start(&self.active_calls);
 
-        if result.is_err() {
-            return Err(McpError::new(
+        // Protocol and transaction are recorded once the tool is mapped to them.
+        let span = tracing::info_span!(
+            "call_tool",
+            tool = %request.name,
+            request_id = ?context.id,
+            client = self.client.as_deref().unwrap_or("-"),
+            protocol = tracing::field::Empty,
+            transaction = tracing::field::Empty,
+        );
+        let result = tokio::select! {
+            result = self.dispatch_tool(request).instrument(span) => result,
+            _ = context.ct.cancelled() => Err(McpError::new(
                 ErrorCode::INTERNAL_ERROR,
-                format!("Error resolving transaction: {}", result.unwrap_err()),
-                None
-            ));
-        }
+                "Tool call was cancelled by the client",
+                None,
+            )),
+            _ = self.shutdown.cancelled() => Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                "Server shut down before the tool call finished",
+                None,
+            )),
+        };
 
-        Ok(CallToolResult::success(vec![Content::text(result.unwrap().tx)]))
+        result
     }
 
     fn ping(
         &self,
         _context: RequestContext<RoleServer>,
     ) -> impl Future<Output = Result<(), McpError>> + Send + '_ {
-        std::future::ready(Ok(()))
+        self.touch();
+        let upstreams = [("registry", self.breaker.health()), ("trp", self.trp_health.as_ref())];
+
+        let mut failing = Map::new();
+        for (name, health) in upstreams {
+            if health.failing_for().is_some_and(|failing_for| failing_for >= self.unhealthy_after) {
+                failing.insert(name.to_string(), health.status());
+            }
+        }
+
+        if failing.is_empty() {
+            return std::future::ready(Ok(()));
+        }
+
+        std::future::ready(Err(McpError::new(
+            ErrorCode::INTERNAL_ERROR,
+            format!("Upstream services are failing: {}", failing.keys().cloned().collect::<Vec<_>>().join(", ")),
+            Some(serde_json::Value::Object(failing)),
+        )))
     }
     
     fn initialize(
         &self,
-        _request: InitializeRequestParam,
+        request: InitializeRequestParam,
         _context: RequestContext<RoleServer>,
     ) -> impl Future<Output = Result<InitializeResult, McpError>> + Send + '_ {
+        self.touch();
+        let version = negotiate_version(&request.protocol_version);
+        tracing::debug!(requested = ?request.protocol_version, negotiated = ?version, "Negotiated protocol version");
+        *self.protocol_version.lock().unwrap() = version;
         std::future::ready(Ok(self.get_info()))
     }
 
-    fn complete(
+    async fn complete(
         &self,
-        _request: CompleteRequestParam, 
+        request: CompleteRequestParam,
         _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<CompleteResult, McpError>> + Send + '_ {
-        std::future::ready(Err(McpError::method_not_found::<CompleteRequestMethod>()))
+    ) -> Result<CompleteResult, McpError> {
+        self.touch();
+        let argument = request.argument;
+        let candidates: Vec<String> = match request.r#ref {
+            Reference::Prompt(prompt) => self.prompt_completions(&prompt.name, &argument.name).await?,
+            Reference::Resource(_) if argument.name == "protocol" => {
+                self.protocols().await?.iter().map(|protocol| protocol.name.clone()).collect()
+            }
+            Reference::Resource(_) => Vec::new(),
+        };
+
+        Ok(CompleteResult { completion: rank_completions(candidates, &argument.value) })
     }
 
     fn set_level(
         &self,
-        _request: SetLevelRequestParam,
+        request: SetLevelRequestParam,
         _context: RequestContext<RoleServer>,
     ) -> impl Future<Output = Result<(), McpError>> + Send + '_ {
-        std::future::ready(Err(McpError::method_not_found::<SetLevelRequestMethod>()))
+        self.touch();
+        tracing::debug!("Client log level set to {:?}", request.level);
+        *self.log_level.lock().unwrap() = request.level;
+        std::future::ready(Ok(()))
     }
 
-    fn get_prompt(
+    async fn get_prompt(
         &self,
-        _request: GetPromptRequestParam,
+        request: GetPromptRequestParam,
         _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<GetPromptResult, McpError>> + Send + '_ {
-        std::future::ready(Err(McpError::method_not_found::<GetPromptRequestMethod>()))
+    ) -> Result<GetPromptResult, McpError> {
+        self.touch();
+        let protocols = self.protocols().await?;
+        for (protocol, compiled) in protocols.iter().zip(self.compile_all(&protocols).await) {
+            let Ok(compiled) = compiled else {
+                continue;
+            };
+            if let Some(tx) = compiled.txs.iter().find(|tx| tool_name("build", &protocol.name, &tx.name) == request.name) {
+                let call = match self.tool_mode {
+                    ToolMode::PerTransaction => format!(
+                        "call the tool `{}` with the parameters as arguments",
+                        tool_name("resolve", &protocol.name, &tx.name),
+                    ),
+                    ToolMode::Generic => format!(
+                        "call the tool `{}` with protocol `{}`, transaction `{}` and the parameters in `args`",
+                        generic::RESOLVE_TRANSACTION,
+                        protocol.name,
+                        tx.name,
+                    ),
+                };
+                return Ok(prompts::render(&protocol.name, tx, &call, &request.arguments.unwrap_or_default()));
+            }
+        }
+
+        Err(McpError::new(
+            ErrorCode::INVALID_PARAMS,
+            format!("Prompt {} not found", request.name),
+            None,
+        ))
     }
 
-    fn list_prompts(
+    async fn list_prompts(
         &self,
-        _request: Option<PaginatedRequestParam>,
+        request: Option<PaginatedRequestParam>,
         _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<ListPromptsResult, McpError>> + Send + '_ {
-        std::future::ready(Ok(ListPromptsResult::default()))
+    ) -> Result<ListPromptsResult, McpError> {
+        self.touch();
+        let protocols = self.protocols().await?;
+        let mut prompts = Vec::new();
+        for (protocol, compiled) in protocols.iter().zip(self.compile_all(&protocols).await) {
+            let Ok(compiled) = compiled else {
+                continue;
+            };
+            for tx in compiled.txs.iter() {
+                prompts.push(prompts::prompt(&tool_name("build", &protocol.name, &tx.name), &protocol.name, tx));
+            }
+        }
+        prompts.sort_by(|a, b| a.name.cmp(&b.name));
+
+        let cursor = request.and_then(|request| request.cursor);
+        let (prompts, next_cursor) = paginate(prompts, cursor, resources::RESOURCES_PAGE_SIZE, "prompts/list")?;
+        Ok(ListPromptsResult { prompts, next_cursor })
     }
 
-    fn list_resources(
+    async fn list_resources(
         &self,
-        _request: Option<PaginatedRequestParam>,
+        request: Option<PaginatedRequestParam>,
         _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<ListResourcesResult, McpError>> + Send + '_ {
-        std::future::ready(Ok(ListResourcesResult::default()))
+    ) -> Result<ListResourcesResult, McpError> {
+        self.touch();
+        let protocols = self.protocols().await?;
+        let history = RawResource {
+            uri: resources::HISTORY_URI.to_string(),
+            name: "Recent resolutions".to_string(),
+            description: Some("Transactions this client resolved recently, newest first, with parameter names but no values".to_string()),
+            mime_type: Some("application/json".to_string()),
+            size: None,
+        }.no_annotation();
+        let resources: Vec<Resource> = std::iter::once(history).chain(protocols.iter()
+            .map(|protocol| RawResource {
+                uri: resources::protocol_uri(&protocol.name),
+                name: protocol.name.clone(),
+                description: protocol.description.as_deref()
+                    .map(|description| truncate(description.trim(), self.description_max_len)),
+                mime_type: Some(resources::TX3_MIME_TYPE.to_string()),
+                size: u32::try_from(protocol.content.len()).ok(),
+            }.no_annotation()))
+            .collect();
+
+        let cursor = request.and_then(|request| request.cursor);
+        let (resources, next_cursor) = paginate(resources, cursor, resources::RESOURCES_PAGE_SIZE, "resources/list")?;
+        Ok(ListResourcesResult { resources, next_cursor })
     }
 
     fn list_resource_templates(
@@ -356,41 +2818,81 @@ impl ServerHandler for ProtocolTool {
         _request: Option<PaginatedRequestParam>,
         _context: RequestContext<RoleServer>,
     ) -> impl Future<Output = Result<ListResourceTemplatesResult, McpError>> + Send + '_ {
-        std::future::ready(Ok(ListResourceTemplatesResult::default()))
+        self.touch();
+        let template = |uri_template: &str, name: &str, description: &str| RawResourceTemplate {
+            uri_template: uri_template.to_string(),
+            name: name.to_string(),
+            description: Some(description.to_string()),
+            mime_type: Some("application/json".to_string()),
+        }.no_annotation();
+
+        std::future::ready(Ok(ListResourceTemplatesResult {
+            resource_templates: vec![
+                template(
+                    resources::TIR_URI_TEMPLATE,
+                    "Transaction TIR",
+                    "Compiled TIR of a transaction as hex, with its IR version",
+                ),
+                template(
+                    resources::SCHEMA_URI_TEMPLATE,
+                    "Transaction parameters schema",
+                    "JSON Schema of the parameters of a transaction, as used by its resolve tool",
+                ),
+            ],
+            next_cursor: None,
+        }))
     }
 
-    fn read_resource(
+    async fn read_resource(
         &self,
-        _request: ReadResourceRequestParam,
+        request: ReadResourceRequestParam,
         _context: RequestContext<RoleServer>,
-    ) -> impl Future<Output = Result<ReadResourceResult, McpError>> + Send + '_ {
-        std::future::ready(Err(
-            McpError::method_not_found::<ReadResourceRequestMethod>(),
-        ))
+    ) -> Result<ReadResourceResult, McpError> {
+        self.touch();
+        match resources::parse_uri(&request.uri)? {
+            ResourceUri::History => Ok(json_resource(request.uri, json!(self.history.entries(&self.caller())))),
+            ResourceUri::Protocol(name) => {
+                let protocol = self.find_protocol(&name).await?;
+                Ok(ReadResourceResult {
+                    contents: vec![ResourceContents::TextResourceContents {
+                        uri: request.uri,
+                        mime_type: Some(resources::TX3_MIME_TYPE.to_string()),
+                        text: protocol.content,
+                    }],
+                })
+            }
+            ResourceUri::Tir { protocol, transaction } => {
+                let (_, compiled) = self.find_transaction(&protocol, &transaction).await?;
+                Ok(json_resource(request.uri, tir_document(compiled.tx(&transaction).unwrap())))
+            }
+            ResourceUri::Schema { protocol, transaction } => {
+                let (_, compiled) = self.find_transaction(&protocol, &transaction).await?;
+                let schema = resolve_input_schema(&protocol, compiled.tx(&transaction).unwrap());
+                Ok(json_resource(request.uri, serde_json::Value::Object(schema)))
+            }
+        }
     }
 
     fn subscribe(
         &self,
-        _request: SubscribeRequestParam,
+        request: SubscribeRequestParam,
         _context: RequestContext<RoleServer>,
     ) -> impl Future<Output = Result<(), McpError>> + Send + '_ {
-        std::future::ready(Err(McpError::method_not_found::<SubscribeRequestMethod>()))
+        self.touch();
+        let result = resources::parse_uri(&request.uri).map(|_| {
+            self.subscriptions.lock().unwrap().insert(request.uri);
+        });
+        std::future::ready(result)
     }
 
     fn unsubscribe(
         &self,
-        _request: UnsubscribeRequestParam,
+        request: UnsubscribeRequestParam,
         _context: RequestContext<RoleServer>,
     ) -> impl Future<Output = Result<(), McpError>> + Send + '_ {
-        std::future::ready(Err(McpError::method_not_found::<UnsubscribeRequestMethod>()))
-    }
-
-    fn on_cancelled(
-        &self,
-        _notification: CancelledNotificationParam,
-        _context: rmcp::service::NotificationContext<RoleServer>,
-    ) -> impl Future<Output = ()> + Send + '_ {
-        std::future::ready(())
+        self.touch();
+        self.subscriptions.lock().unwrap().remove(&request.uri);
+        std::future::ready(Ok(()))
     }
 
     fn on_progress(
@@ -403,16 +2905,607 @@ impl ServerHandler for ProtocolTool {
 
     fn on_initialized(
         &self,
-        _context: rmcp::service::NotificationContext<RoleServer>,
+        context: rmcp::service::NotificationContext<RoleServer>,
     ) -> impl Future<Output = ()> + Send + '_ {
         tracing::info!("client initialized");
+        self.spawn_warmup();
+        self.spawn_root_scan(context.peer);
         std::future::ready(())
     }
 
     fn on_roots_list_changed(
         &self,
-        _context: rmcp::service::NotificationContext<RoleServer>,
+        context: rmcp::service::NotificationContext<RoleServer>,
     ) -> impl Future<Output = ()> + Send + '_ {
+        tracing::debug!("Client roots changed, rescanning");
+        self.spawn_root_scan(context.peer);
         std::future::ready(())
     }
-}
\ No newline at end of file
+
+    fn get_peer(&self) -> Option<Peer<RoleServer>> {
+        self.peer.read().unwrap().clone()
+    }
+
+    fn set_peer(&mut self, peer: Peer<RoleServer>) {
+        *self.peer.write().unwrap() = Some(peer);
+        self.sessions.lock().unwrap().push(self.session());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::SinkExt;
+    use futures::channel::mpsc;
+    use rmcp::ServiceExt;
+    use rmcp::service::{RunningService, RxJsonRpcMessage, TxJsonRpcMessage};
+    use tx3_lang::ir::Type;
+
+    use super::*;
+
+    fn test_tool() -> ProtocolTool {
+        ProtocolTool::new("file:///nonexistent/registry.json", TrpEndpoint::new("mock://")).with_warmup(Warmup::Lazy)
+    }
+
+    type ClientSide = (mpsc::Sender<RxJsonRpcMessage<RoleServer>>, mpsc::Receiver<TxJsonRpcMessage<RoleServer>>);
+
+    /// Serves `tool` over in-memory channels and completes the handshake.
+    async fn connect(tool: ProtocolTool) -> (RunningService<RoleServer, ProtocolTool>, ClientSide) {
+        let (mut to_server, server_input) = mpsc::channel(16);
+        let (server_output, from_server) = mpsc::channel(16);
+
+        let handshake = [
+            json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": "initialize",
+                "params": {
+                    "protocolVersion": "2025-03-26",
+                    "capabilities": {},
+                    "clientInfo": { "name": "test", "version": "0.0.0" },
+                },
+            }),
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        ];
+        for message in handshake {
+            to_server.send(serde_json::from_value(message).unwrap()).await.unwrap();
+        }
+
+        let service = tool.serve((server_output, server_input)).await.unwrap();
+        (service, (to_server, from_server))
+    }
+
+    /// The method of the next notification the server sends, skipping responses.
+    async fn next_notification(from_server: &mut mpsc::Receiver<TxJsonRpcMessage<RoleServer>>) -> String {
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), from_server.next())
+                .await
+                .expect("no message from the server")
+                .expect("server closed the connection");
+            let message = serde_json::to_value(&message).unwrap();
+            if let Some(method) = message.get("method").and_then(|method| method.as_str()) {
+                return method.to_string();
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn notify_reaches_a_connected_session() {
+        let tool = test_tool();
+        let (_service, (_to_server, mut from_server)) = connect(tool.for_session()).await;
+
+        tool.notify_tool_list_changed().await;
+        assert_eq!(next_notification(&mut from_server).await, "notifications/tools/list_changed");
+
+        tool.broadcast_log(LoggingLevel::Error, json!({ "message": "registry down" })).await;
+        assert_eq!(next_notification(&mut from_server).await, "notifications/message");
+    }
+
+    #[tokio::test]
+    async fn notify_skips_a_session_without_peer() {
+        let tool = test_tool();
+        assert_eq!(ProtocolTool::notify(&tool.session(), &Notification::ToolListChanged).await, Delivery::Skipped);
+    }
+
+    #[tokio::test]
+    async fn notify_drops_disconnected_sessions() {
+        let tool = test_tool();
+        let (service, _client) = connect(tool.for_session()).await;
+        assert_eq!(tool.active_sessions(), 1);
+
+        service.cancel().await.unwrap();
+        tool.notify_tool_list_changed().await;
+        assert_eq!(tool.active_sessions(), 0);
+    }
+
+    #[test]
+    fn sessions_only_want_what_they_asked_for() {
+        let session = test_tool().session();
+        let uri = resources::protocol_uri("acme_swap");
+
+        assert!(session.wants(&Notification::ToolListChanged));
+        assert!(!session.wants(&Notification::ResourceUpdated(uri.clone())));
+        session.subscriptions.lock().unwrap().insert(uri.clone());
+        assert!(session.wants(&Notification::ResourceUpdated(uri)));
+
+        assert!(!session.wants(&Notification::Log(LoggingLevel::Info, json!({}))));
+        assert!(session.wants(&Notification::Log(LoggingLevel::Error, json!({}))));
+    }
+
+    #[test]
+    fn active_call_is_released_on_drop() {
+        let active_calls = Arc::new(AtomicUsize::new(0));
+        let call = ActiveCall::start(&active_calls);
+        assert_eq!(active_calls.load(Ordering::Acquire), 1);
+        drop(call);
+        assert_eq!(active_calls.load(Ordering::Acquire), 0);
+    }
+
+    #[tokio::test]
+    async fn dropped_call_future_is_released() {
+        let active_calls = Arc::new(AtomicUsize::new(0));
+        let counter = active_calls.clone();
+        let call = async move {
+            let _active = ActiveCall::start(&counter);
+            std::future::pending::<()>().await
+        };
+
+        assert!(tokio::time::timeout(Duration::from_millis(10), call).await.is_err());
+        assert_eq!(active_calls.load(Ordering::Acquire), 0);
+    }
+
+    #[test]
+    fn negotiate_version_keeps_a_supported_request() {
+        assert_eq!(negotiate_version(&ProtocolVersion::V_2024_11_05), ProtocolVersion::V_2024_11_05);
+        assert_eq!(negotiate_version(&ProtocolVersion::V_2025_03_26), ProtocolVersion::V_2025_03_26);
+    }
+
+    #[test]
+    fn negotiate_version_falls_back_to_the_newest() {
+        let unknown: ProtocolVersion = serde_json::from_value(json!("2099-01-01")).unwrap();
+        assert_eq!(negotiate_version(&unknown), ProtocolVersion::V_2025_03_26);
+    }
+
+    #[test]
+    fn completions_are_advertised_from_2025_03_26() {
+        assert!(capabilities(&ProtocolVersion::V_2024_11_05).completions.is_none());
+        assert!(capabilities(&ProtocolVersion::V_2025_03_26).completions.is_some());
+    }
+
+    #[tokio::test]
+    async fn initialize_answers_with_the_negotiated_capabilities() {
+        let (_service, (_to_server, mut from_server)) = connect(test_tool().for_session()).await;
+
+        let response = tokio::time::timeout(Duration::from_secs(5), from_server.next()).await.unwrap().unwrap();
+        let result = &serde_json::to_value(&response).unwrap()["result"];
+        assert_eq!(result["protocolVersion"], "2025-03-26");
+        assert!(result["capabilities"]["completions"].is_object());
+        assert!(result["capabilities"]["tools"]["listChanged"].as_bool().unwrap());
+    }
+
+    #[test]
+    fn tool_name_replaces_disallowed_characters() {
+        assert_eq!(tool_name("resolve", "acme_swap", "swap-exact"), "resolve-acme_swap-swap-exact");
+        assert_eq!(tool_name("resolve", "acme.v2", "swap ada"), "resolve-acme_v2-swap_ada");
+    }
+
+    #[test]
+    fn long_tool_names_are_cut_and_stay_unique() {
+        let long = "x".repeat(80);
+        let first = tool_name("resolve", &long, "swap");
+        let second = tool_name("resolve", &long, "swap2");
+
+        assert_eq!(first.len(), MAX_TOOL_NAME_LEN);
+        assert_eq!(second.len(), MAX_TOOL_NAME_LEN);
+        assert_ne!(first, second);
+        assert!(first.starts_with("resolve-xxx"));
+        assert_eq!(first, tool_name("resolve", &long, "swap"));
+    }
+
+    #[test]
+    fn tool_name_at_the_limit_is_kept() {
+        let protocol = "p".repeat(MAX_TOOL_NAME_LEN - "resolve--swap".len());
+        assert_eq!(tool_name("resolve", &protocol, "swap"), format!("resolve-{}-swap", protocol));
+    }
+
+    #[test]
+    fn paginate_walks_every_page() {
+        let items: Vec<u32> = (0..5).collect();
+        let (page, cursor) = paginate(items.clone(), None, 2, "tools/list").unwrap();
+        assert_eq!((page, cursor.as_deref()), (vec![0, 1], Some("2")));
+        let (page, cursor) = paginate(items.clone(), cursor, 2, "tools/list").unwrap();
+        assert_eq!((page, cursor.as_deref()), (vec![2, 3], Some("4")));
+        let (page, cursor) = paginate(items, cursor, 2, "tools/list").unwrap();
+        assert_eq!((page, cursor), (vec![4], None));
+    }
+
+    #[test]
+    fn paginate_fits_on_one_page() {
+        assert_eq!(paginate(vec![1, 2], None, 2, "tools/list").unwrap(), (vec![1, 2], None));
+        assert_eq!(paginate(Vec::<u32>::new(), None, 2, "tools/list").unwrap(), (vec![], None));
+    }
+
+    #[test]
+    fn paginate_rejects_bad_cursors() {
+        for cursor in ["abc", "-1", "6"] {
+            let err = paginate(vec![1, 2, 3], Some(cursor.to_string()), 2, "resources/list").unwrap_err();
+            assert_eq!(err.code.0, ErrorCode::INVALID_PARAMS.0);
+            assert_eq!(err.message, format!("Invalid resources/list cursor {}", cursor));
+        }
+    }
+
+    fn source(name: &str, registry_url: &str, content: &str) -> ProtocolSource {
+        ProtocolSource {
+            name: name.to_string(),
+            scope: None,
+            content: content.to_string(),
+            registry_url: registry_url.to_string(),
+            description: None,
+            version: None,
+        }
+    }
+
+    fn names(protocols: &[ProtocolSource]) -> Vec<&str> {
+        protocols.iter().map(|protocol| protocol.name.as_str()).collect()
+    }
+
+    #[test]
+    fn dedupe_drops_protocols_shadowed_by_an_earlier_registry() {
+        let deduped = dedupe_protocols(vec![
+            source("acme_swap", "https://first", "party A;"),
+            source("acme_swap", "https://second", "party B;"),
+        ]);
+        assert_eq!(names(&deduped), ["acme_swap"]);
+        assert_eq!(deduped[0].registry_url, "https://first");
+    }
+
+    #[test]
+    fn dedupe_drops_identical_republished_content() {
+        let deduped = dedupe_protocols(vec![
+            source("acme_swap", "https://first", "party A;"),
+            source("acme_swap", "https://first", "party A;"),
+        ]);
+        assert_eq!(names(&deduped), ["acme_swap"]);
+    }
+
+    #[test]
+    fn dedupe_suffixes_other_collisions() {
+        let deduped = dedupe_protocols(vec![
+            source("acme_swap", "https://first", "party A;"),
+            source("acme_swap", "https://first", "party B;"),
+            source("Acme_Swap", "https://first", "party C;"),
+            source("acme_swap_2", "https://first", "party D;"),
+        ]);
+        assert_eq!(names(&deduped), ["acme_swap", "acme_swap_2", "Acme_Swap_3", "acme_swap_2_2"]);
+    }
+
+    fn compiled_tx(params: &[(&str, Type)]) -> CompiledTx {
+        CompiledTx {
+            name: "swap".to_string(),
+            params: params.iter().map(|(name, ty)| (name.to_string(), ty.clone())).collect(),
+            docs: None,
+            tir: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn schema_without_parameters_says_so() {
+        let schema = resolve_input_schema("acme_swap", &compiled_tx(&[]));
+        assert_eq!(schema["properties"], json!({}));
+        assert_eq!(schema["required"], json!([]));
+        assert_eq!(schema["description"], "This transaction takes no parameters, call it without arguments");
+    }
+
+    #[test]
+    fn schema_lists_every_parameter() {
+        let schema = resolve_input_schema("acme_swap", &compiled_tx(&[("quantity", Type::Int), ("buyer", Type::Address)]));
+        assert_eq!(schema["required"], json!(["buyer", "quantity"]));
+        assert_eq!(schema["properties"]["quantity"], params::param_schema(&Type::Int));
+        assert!(schema.get("description").is_none());
+    }
+
+    const TRANSFER: &str = "party Sender;
+
+party Receiver;
+
+tx transfer(
+    quantity: Int
+) {
+    input source {
+        from: Sender,
+        min_amount: Ada(quantity) + fees,
+    }
+
+    output {
+        to: Receiver,
+        amount: Ada(quantity),
+    }
+
+    output {
+        to: Sender,
+        amount: source - Ada(quantity) - fees,
+    }
+}
+";
+
+    /// Writes a registry fixture publishing each `(name, source)` under the `acme` scope
+    /// and returns its `file://` URL.
+    fn registry_fixture(test: &str, protocols: &[(&str, &str)]) -> String {
+        let nodes: Vec<serde_json::Value> = protocols.iter()
+            .map(|(name, source)| json!({
+                "scope": "acme",
+                "name": name,
+                "version": "0.1.0",
+                "source": source,
+                "description": null,
+                "readme": null,
+            }))
+            .collect();
+        let document = json!({ "data": { "protocols": { "pageInfo": { "hasNextPage": false }, "nodes": nodes } } });
+        let path = std::env::temp_dir().join(format!("tx3-mcp-{}-{}.json", test, std::process::id()));
+        std::fs::write(&path, document.to_string()).unwrap();
+        format!("file://{}", path.display())
+    }
+
+    fn fixture_tool(registry_url: &str) -> ProtocolTool {
+        ProtocolTool::new(registry_url, TrpEndpoint::new("mock://")).with_warmup(Warmup::Lazy)
+    }
+
+    #[tokio::test]
+    async fn protocols_that_fail_to_compile_are_skipped() {
+        let tool = fixture_tool(&registry_fixture("broken", &[("broken", "party;"), ("transfer", TRANSFER)]));
+        let snapshot = tool.build_tools().await.unwrap();
+
+        assert!(snapshot.targets.contains_key("resolve-acme_transfer-transfer"));
+        assert!(!snapshot.targets.keys().any(|name| name.contains("acme_broken")));
+        assert!(snapshot.tools.iter().any(|tool| tool.name == "registry-status"));
+    }
+
+    #[tokio::test]
+    async fn tools_are_listed_in_a_stable_order() {
+        let tool = fixture_tool(&registry_fixture("ordering", &[("zeta", TRANSFER), ("alpha", TRANSFER)]));
+        let snapshot = tool.build_tools().await.unwrap();
+        let names: Vec<&str> = snapshot.tools.iter().map(|tool| tool.name.as_ref()).collect();
+
+        let (fixed, generated): (Vec<&str>, Vec<&str>) = names.iter().partition(|name| !snapshot.targets.contains_key(**name));
+        assert_eq!(generated, [
+            "resolve-acme_alpha-transfer",
+            "describe-acme_alpha-transfer",
+            "resolve-acme_zeta-transfer",
+            "describe-acme_zeta-transfer",
+        ]);
+        assert!(fixed.is_sorted(), "{:?}", fixed);
+        assert_eq!(&names[..fixed.len()], fixed);
+    }
+
+    #[tokio::test]
+    async fn concurrent_refreshes_share_one_fetch() {
+        let tool = fixture_tool(&registry_fixture("single-flight", &[("transfer", TRANSFER)]));
+        let results = join_all((0..8).map(|_| tool.refresh())).await;
+
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.len(), 1);
+        assert!(results.iter().all(|result| Arc::ptr_eq(result.as_ref().unwrap(), first)));
+        assert!(tool.render_metrics().contains("tx3_mcp_registry_fetch_duration_seconds_count 1\n"));
+
+        // The finished flight is cleared, so the next refresh fetches again.
+        tool.refresh().await.unwrap();
+        assert!(tool.render_metrics().contains("tx3_mcp_registry_fetch_duration_seconds_count 2\n"));
+    }
+
+    #[tokio::test]
+    async fn changed_registry_content_notifies_tool_list_changed() {
+        let tool = test_tool();
+        let (_service, (_to_server, mut from_server)) = connect(tool.for_session()).await;
+
+        tool.store(vec![source("acme_swap", "https://registry", "party A;")]).await;
+        tool.store(vec![source("acme_swap", "https://registry", "party A;")]).await;
+        assert!(tokio::time::timeout(Duration::from_millis(100), from_server.next()).await.is_err());
+
+        tool.store(vec![source("acme_swap", "https://registry", "party B;")]).await;
+        assert_eq!(next_notification(&mut from_server).await, "notifications/tools/list_changed");
+    }
+
+    #[tokio::test]
+    async fn subscribed_resources_are_notified_on_change() {
+        let tool = test_tool();
+        let (_service, (mut to_server, mut from_server)) = connect(tool.for_session()).await;
+        let uri = resources::protocol_uri("acme_swap");
+
+        let subscribe = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "resources/subscribe",
+            "params": { "uri": uri },
+        });
+        to_server.send(serde_json::from_value(subscribe).unwrap()).await.unwrap();
+        loop {
+            let message = tokio::time::timeout(Duration::from_secs(5), from_server.next()).await.unwrap().unwrap();
+            if serde_json::to_value(&message).unwrap()["id"] == 2 {
+                break;
+            }
+        }
+
+        tool.store(vec![source("acme_swap", "https://registry", "party A;"), source("acme_mint", "https://registry", "party A;")]).await;
+        tool.store(vec![source("acme_swap", "https://registry", "party A;"), source("acme_mint", "https://registry", "party B;")]).await;
+        assert_eq!(next_notification(&mut from_server).await, "notifications/tools/list_changed");
+        assert!(tokio::time::timeout(Duration::from_millis(100), from_server.next()).await.is_err());
+
+        tool.store(vec![source("acme_swap", "https://registry", "party B;"), source("acme_mint", "https://registry", "party B;")]).await;
+        assert_eq!(next_notification(&mut from_server).await, "notifications/tools/list_changed");
+        assert_eq!(next_notification(&mut from_server).await, "notifications/resources/updated");
+    }
+
+    /// A TRP that never answers.
+    struct HangingResolver;
+
+    impl Resolver for HangingResolver {
+        fn resolve<'a>(
+            &'a self,
+            _protocol: &'a str,
+            _transaction: &'a str,
+            _request: ProtoTxRequest,
+        ) -> BoxFuture<'a, Result<String, tx3_sdk::trp::Error>> {
+            futures::future::pending().boxed()
+        }
+    }
+
+    fn hanging_tool(test: &str) -> ProtocolTool {
+        let tool = fixture_tool(&registry_fixture(test, &[("transfer", TRANSFER)]));
+        tool.trp_clients.lock().unwrap().insert(String::new(), (tool.trp.headers(), Arc::new(HangingResolver)));
+        tool
+    }
+
+    /// A valid value for every parameter of `acme_transfer.transfer`.
+    async fn transfer_arguments(tool: &ProtocolTool) -> Map<String, serde_json::Value> {
+        let (_, compiled) = tool.find_transaction("acme_transfer", "transfer").await.unwrap();
+        compiled.tx("transfer").unwrap().params.iter()
+            .map(|(name, ty)| {
+                let value = match ty {
+                    Type::Int => json!(5_000_000),
+                    Type::Address => json!("addr_test1qz2fxv2umyhttkxyxp8x0dlpdt3k6cwng5pxj3jhsydzer3n0d3vllmyqwsx5wktcd8cc3sq835lu7drv2xwl2wywfgs68faae"),
+                    _ => json!("00"),
+                };
+                (name.clone(), value)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn resolve_gives_up_after_the_trp_timeout() {
+        let tool = hanging_tool("timeout").with_trp_timeout(Duration::from_millis(50));
+        let arguments = transfer_arguments(&tool).await;
+
+        let err = tool.resolve_transaction("acme_transfer", "transfer", &arguments, None).await.err().unwrap();
+        assert_eq!(err.message, "TRP did not answer within 50ms");
+        assert_eq!(tool.history.entries(&tool.caller())[0].error.as_deref(), Some("TRP did not answer within 50ms"));
+    }
+
+    #[tokio::test]
+    async fn cancelled_calls_stop_waiting_for_trp() {
+        let tool = hanging_tool("cancel");
+        let arguments = transfer_arguments(&tool).await;
+        let (_service, (mut to_server, _from_server)) = connect(tool.for_session()).await;
+
+        let call = json!({
+            "jsonrpc": "2.0",
+            "id": 2,
+            "method": "tools/call",
+            "params": { "name": "resolve-acme_transfer-transfer", "arguments": arguments },
+        });
+        to_server.send(serde_json::from_value(call).unwrap()).await.unwrap();
+        wait_for_active_calls(&tool, 1).await;
+
+        let cancel = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/cancelled",
+            "params": { "requestId": 2 },
+        });
+        to_server.send(serde_json::from_value(cancel).unwrap()).await.unwrap();
+        wait_for_active_calls(&tool, 0).await;
+    }
+
+    async fn wait_for_active_calls(tool: &ProtocolTool, expected: usize) {
+        for _ in 0..100 {
+            if tool.active_calls.load(Ordering::Acquire) == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("expected {} active calls, found {}", expected, tool.active_calls.load(Ordering::Acquire));
+    }
+
+    #[tokio::test]
+    async fn failing_registry_keeps_its_last_protocols() {
+        let first = registry_fixture("partial-first", &[("transfer", TRANSFER)]);
+        let second = registry_fixture("partial-second", &[("payout", TRANSFER)]);
+        let snapshot_path = std::env::temp_dir().join(format!("tx3-mcp-partial-snapshot-{}.json", std::process::id()));
+        let tool = fixture_tool(&first)
+            .with_registries(vec![Registry::new(&first), Registry::new(&second)])
+            .with_disk_snapshot(DiskSnapshot::new(snapshot_path.clone()));
+
+        assert_eq!(names(&tool.refresh().await.unwrap()), ["acme_transfer", "acme_payout"]);
+        assert!(snapshot_path.exists());
+
+        std::fs::remove_file(snapshot_path.clone()).unwrap();
+        std::fs::remove_file(second.strip_prefix("file://").unwrap()).unwrap();
+        assert_eq!(names(&tool.refresh().await.unwrap()), ["acme_transfer", "acme_payout"]);
+        assert!(!snapshot_path.exists(), "a partial fetch must not overwrite the snapshot");
+    }
+
+    #[tokio::test]
+    async fn registry_outage_keeps_registry_protocols_under_local_ones() {
+        let registry = registry_fixture("outage", &[("transfer", TRANSFER)]);
+        let tool = fixture_tool(&registry).with_local_protocol(source("local_payout", "file:///payout.tx3", TRANSFER));
+
+        assert_eq!(names(&tool.refresh().await.unwrap()), ["local_payout", "acme_transfer"]);
+
+        std::fs::remove_file(registry.strip_prefix("file://").unwrap()).unwrap();
+        assert_eq!(names(&tool.refresh().await.unwrap()), ["local_payout", "acme_transfer"]);
+        assert!(tool.render_metrics().contains("tx3_mcp_registry_fetch_duration_seconds_count 2\n"));
+        assert_eq!(tool.breaker.status()["consecutive_failures"], 1);
+    }
+
+    #[tokio::test]
+    async fn local_protocols_are_served_when_no_registry_ever_answered() {
+        let tool = test_tool().with_local_protocol(source("local_payout", "file:///payout.tx3", TRANSFER));
+        assert_eq!(names(&tool.refresh().await.unwrap()), ["local_payout"]);
+    }
+
+    #[tokio::test]
+    async fn targeted_lookup_skips_oversized_protocols() {
+        let tool = fixture_tool(&registry_fixture("oversized", &[("transfer", TRANSFER)])).with_max_protocol_size(16);
+
+        assert!(tool.fetch_single_protocol("acme_transfer").await.unwrap().is_none());
+        let err = tool.find_protocol("acme_transfer").await.err().unwrap();
+        assert_eq!(err.code.0, ErrorCode::RESOURCE_NOT_FOUND.0);
+    }
+
+    #[tokio::test]
+    async fn targeted_lookup_skips_empty_local_sources() {
+        let tool = test_tool().with_local_protocol(source("local_payout", "file:///payout.tx3", "  \n"));
+        assert!(tool.fetch_single_protocol("local_payout").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn resolve_names_unconfigured_env_fields() {
+        let with_env = format!("env {{\n    fee_collector: Address,\n}}\n\n{}", TRANSFER);
+        let tool = fixture_tool(&registry_fixture("env", &[("transfer", &with_env)]));
+        let arguments = transfer_arguments(&tool).await;
+
+        let (_, compiled) = tool.find_transaction("acme_transfer", "transfer").await.unwrap();
+        assert_eq!(compiled.env, ["fee_collector"]);
+
+        let err = tool.resolve_transaction("acme_transfer", "transfer", &arguments, None).await.err().unwrap();
+        assert!(err.message.contains("fee_collector"), "{}", err.message);
+        assert_eq!(err.data.unwrap()["missing_env"], json!(["fee_collector"]));
+        assert!(tool.history.entries(&tool.caller()).is_empty());
+    }
+
+    #[tokio::test]
+    async fn every_request_resets_the_idle_clock() {
+        let session = test_tool().for_session();
+        let (_service, (mut to_server, mut from_server)) = connect(session.clone()).await;
+
+        let requests = [
+            ("resources/subscribe", json!({ "uri": resources::protocol_uri("acme_swap") })),
+            ("resources/unsubscribe", json!({ "uri": resources::protocol_uri("acme_swap") })),
+            ("resources/templates/list", json!({})),
+            ("logging/setLevel", json!({ "level": "debug" })),
+        ];
+        for (id, (method, params)) in requests.into_iter().enumerate() {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            assert!(session.idle_for() >= Duration::from_millis(100));
+
+            let id = id + 2;
+            let request = json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params });
+            to_server.send(serde_json::from_value(request).unwrap()).await.unwrap();
+            loop {
+                let message = tokio::time::timeout(Duration::from_secs(5), from_server.next()).await.unwrap().unwrap();
+                if serde_json::to_value(&message).unwrap()["id"] == id {
+                    break;
+                }
+            }
+            assert!(session.idle_for() < Duration::from_millis(100), "{} didn't reset the idle clock", method);
+        }
+    }
+}
@@ -0,0 +1,135 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Buckets kept before idle ones, already refilled to capacity, are dropped.
+const MAX_IDLE_BUCKETS: usize = 1024;
+
+struct TokenBucket {
+    tokens: f64,
+    updated_at: Instant,
+}
+
+/// Token buckets keyed by caller, refilled at `per_minute` up to `burst` tokens. Each
+/// call takes one token. The clock is passed in, so callers decide what "now" is.
+pub struct RateLimiter {
+    per_minute: u32,
+    burst: u32,
+    buckets: Mutex<HashMap<String, TokenBucket>>,
+}
+
+impl RateLimiter {
+    pub fn new(per_minute: u32, burst: u32) -> Self {
+        Self {
+            per_minute,
+            burst: burst.max(1),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn per_minute(&self) -> u32 {
+        self.per_minute
+    }
+
+    fn refill_rate(&self) -> f64 {
+        self.per_minute as f64 / 60.0
+    }
+
+    /// Takes a token from `key`'s bucket, or says how long until one is available.
+    pub fn acquire(&self, key: &str, now: Instant) -> Result<(), Duration> {
+        let rate = self.refill_rate();
+        let capacity = self.burst as f64;
+        let mut buckets = self.buckets.lock().unwrap();
+
+        if buckets.len() >= MAX_IDLE_BUCKETS {
+            buckets.retain(|_, bucket| bucket.tokens + now.saturating_duration_since(bucket.updated_at).as_secs_f64() * rate < capacity);
+        }
+
+        let bucket = buckets.entry(key.to_string()).or_insert(TokenBucket { tokens: capacity, updated_at: now });
+        let elapsed = now.saturating_duration_since(bucket.updated_at).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated_at = now;
+
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            return Ok(());
+        }
+
+        if rate == 0.0 {
+            return Err(Duration::MAX);
+        }
+        Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn burst_is_available_at_once() {
+        let limiter = RateLimiter::new(60, 3);
+        let now = Instant::now();
+        for _ in 0..3 {
+            assert!(limiter.acquire("client:a", now).is_ok());
+        }
+        assert_eq!(limiter.acquire("client:a", now), Err(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn tokens_refill_over_time() {
+        let limiter = RateLimiter::new(60, 1);
+        let start = Instant::now();
+        assert!(limiter.acquire("client:a", start).is_ok());
+
+        let retry_after = limiter.acquire("client:a", start + Duration::from_millis(250)).unwrap_err();
+        assert!((retry_after.as_secs_f64() - 0.75).abs() < 1e-9, "{:?}", retry_after);
+
+        assert!(limiter.acquire("client:a", start + Duration::from_secs(1)).is_ok());
+    }
+
+    #[test]
+    fn refill_stops_at_the_burst() {
+        let limiter = RateLimiter::new(60, 2);
+        let start = Instant::now();
+        assert!(limiter.acquire("client:a", start).is_ok());
+
+        let later = start + Duration::from_secs(3600);
+        assert!(limiter.acquire("client:a", later).is_ok());
+        assert!(limiter.acquire("client:a", later).is_ok());
+        assert!(limiter.acquire("client:a", later).is_err());
+    }
+
+    #[test]
+    fn callers_have_separate_buckets() {
+        let limiter = RateLimiter::new(60, 1);
+        let now = Instant::now();
+        assert!(limiter.acquire("client:a", now).is_ok());
+        assert!(limiter.acquire("client:a", now).is_err());
+        assert!(limiter.acquire("client:b", now).is_ok());
+    }
+
+    #[test]
+    fn zero_rate_never_refills() {
+        let limiter = RateLimiter::new(0, 1);
+        let now = Instant::now();
+        assert!(limiter.acquire("client:a", now).is_ok());
+        assert_eq!(limiter.acquire("client:a", now + Duration::from_secs(3600)), Err(Duration::MAX));
+    }
+
+    #[test]
+    fn idle_buckets_are_evicted() {
+        let limiter = RateLimiter::new(60, 2);
+        let start = Instant::now();
+        for index in 0..MAX_IDLE_BUCKETS {
+            assert!(limiter.acquire(&format!("session:{}", index), start).is_ok());
+        }
+        // Still draining, so kept over the limit.
+        assert!(limiter.acquire("session:busy", start).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().len(), MAX_IDLE_BUCKETS + 1);
+
+        // Every bucket has refilled by now, so only the new caller's is left.
+        assert!(limiter.acquire("session:new", start + Duration::from_secs(10)).is_ok());
+        assert_eq!(limiter.buckets.lock().unwrap().len(), 1);
+    }
+}
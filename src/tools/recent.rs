@@ -0,0 +1,55 @@
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Resolved transactions kept per session.
+pub const DEFAULT_CAPACITY: usize = 16;
+
+pub const DEFAULT_TTL: Duration = Duration::from_secs(15 * 60);
+
+/// The transactions a session resolved recently, indexed by hash, so a follow-up submit
+/// can reference one instead of passing its CBOR back.
+pub struct RecentTxs {
+    capacity: usize,
+    ttl: Duration,
+    entries: Mutex<VecDeque<(String, String, Instant)>>,
+}
+
+impl Default for RecentTxs {
+    fn default() -> Self {
+        Self::new(DEFAULT_CAPACITY, DEFAULT_TTL)
+    }
+}
+
+impl RecentTxs {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            capacity,
+            ttl,
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Remembers a transaction, evicting the oldest one past the capacity.
+    pub fn insert(&self, hash: &str, tx: &str) {
+        if self.capacity == 0 {
+            return;
+        }
+
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(existing, _, _)| existing != hash);
+        while entries.len() >= self.capacity {
+            entries.pop_front();
+        }
+        entries.push_back((hash.to_string(), tx.to_string(), Instant::now()));
+    }
+
+    /// The CBOR of a transaction resolved within the TTL.
+    pub fn get(&self, hash: &str) -> Option<String> {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|(_, _, resolved_at)| resolved_at.elapsed() < self.ttl);
+        entries.iter()
+            .find(|(existing, _, _)| existing.eq_ignore_ascii_case(hash))
+            .map(|(_, tx, _)| tx.clone())
+    }
+}
@@ -0,0 +1,513 @@
+use serde::{Deserialize, Serialize};
+use serde::de::DeserializeOwned;
+use serde_json::json;
+use rmcp::Error as McpError;
+use rmcp::model::ErrorCode;
+use cynic;
+use cynic::{GraphQlResponse, Operation, QueryBuilder};
+use cynic::http::{CynicReqwestError, ReqwestExt};
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use super::retry::RetryPolicy;
+use super::secret::Secret;
+
+#[cynic::schema("tx3")]
+mod schema {}
+
+#[derive(cynic::QueryVariables, Debug)]
+pub struct ProtocolsQueryVariables {
+    pub page_size: Option<i32>,
+    pub offset: Option<i32>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Query", variables = "ProtocolsQueryVariables")]
+pub struct ProtocolsQuery {
+    #[arguments(pageSize: $page_size, offset: $offset)]
+    pub protocols: ProtocolConnection,
+}
+
+#[derive(cynic::QueryVariables, Debug)]
+pub struct ProtocolQueryVariables {
+    pub scope: String,
+    pub name: String,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+#[cynic(graphql_type = "Query", variables = "ProtocolQueryVariables")]
+pub struct ProtocolQuery {
+    #[arguments(scope: $scope, name: $name)]
+    pub protocol: Option<Protocol>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct ProtocolConnection {
+    pub page_info: PageInfo,
+    pub nodes: Vec<Protocol>,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct PageInfo {
+    pub has_next_page: bool,
+}
+
+#[derive(cynic::QueryFragment, Debug)]
+pub struct Protocol {
+    pub scope: String,
+    pub name: String,
+    pub version: String,
+    pub source: Option<String>,
+    pub description: Option<String>,
+    pub readme: Option<String>,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ProtocolSource {
+    pub name: String,
+    /// Registry scope; local protocols have none.
+    #[serde(default)]
+    pub scope: Option<String>,
+    pub content: String,
+    pub registry_url: String,
+    pub description: Option<String>,
+    pub version: Option<String>,
+}
+
+impl ProtocolSource {
+    pub fn content_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        self.content.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Builds a protocol that isn't published in any registry, failing with the tx3
+    /// compiler diagnostics when the source doesn't load.
+    pub fn local(name: &str, content: String, origin: &str) -> anyhow::Result<Self> {
+        if let Err(err) = tx3_lang::Protocol::from_string(content.clone()).load() {
+            anyhow::bail!("Failed to load protocol {} from {}: {:?}", name, origin, err);
+        }
+
+        Ok(Self {
+            name: name.to_string(),
+            scope: None,
+            content,
+            registry_url: origin.to_string(),
+            description: None,
+            version: None,
+        })
+    }
+}
+
+/// Upper bound on the number of pages requested from a registry in a single fetch.
+pub const DEFAULT_MAX_PAGES: usize = 100;
+
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(10);
+
+pub const DEFAULT_RETRY: RetryPolicy = RetryPolicy::new(3, Duration::from_millis(200));
+
+#[derive(Clone)]
+pub struct Registry {
+    client: reqwest::Client,
+    url: String,
+    /// Header name, whether the key goes after `Bearer`, and the key itself.
+    auth: Option<(String, bool, Secret)>,
+    scopes: Vec<String>,
+    page_size: Option<i32>,
+    max_pages: usize,
+    retry: RetryPolicy,
+    timeout: Duration,
+}
+
+impl Registry {
+    pub fn new(url: &str) -> Self {
+        Self {
+            client: reqwest::Client::new(),
+            url: url.to_string(),
+            auth: None,
+            scopes: Vec::new(),
+            page_size: None,
+            max_pages: DEFAULT_MAX_PAGES,
+            retry: DEFAULT_RETRY,
+            timeout: DEFAULT_TIMEOUT,
+        }
+    }
+
+    /// Shares an HTTP client, and its connection pool, with other registries.
+    pub fn with_client(mut self, client: reqwest::Client) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Attaches an API key to every registry request. Without an explicit header name
+    /// the key is sent as `Authorization: Bearer <key>`.
+    pub fn with_key(mut self, key: Secret, header_name: Option<&str>) -> Self {
+        self.auth = Some(match header_name {
+            Some(header_name) => (header_name.to_string(), false, key),
+            None => ("Authorization".to_string(), true, key),
+        });
+        self
+    }
+
+    pub fn key(&self) -> Option<&Secret> {
+        self.auth.as_ref().map(|(_, _, key)| key)
+    }
+
+    /// Restricts the exposed protocols to the given scopes. An empty list keeps every scope.
+    pub fn with_scopes(mut self, scopes: Vec<String>) -> Self {
+        self.scopes = scopes;
+        self
+    }
+
+    /// Page size requested from the registry; `None` lets the registry pick its default.
+    pub fn with_page_size(mut self, page_size: Option<i32>) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    pub fn with_max_pages(mut self, max_pages: usize) -> Self {
+        self.max_pages = max_pages;
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    /// Upper bound for a single registry request; a timed out request counts as a
+    /// retryable failure.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    /// `file://` URLs and plain paths point at a JSON document shaped like the
+    /// protocols query response, used for offline testing and air-gapped setups.
+    fn fixture_path(&self) -> Option<PathBuf> {
+        if let Some(path) = self.url.strip_prefix("file://") {
+            return Some(PathBuf::from(path));
+        }
+        (!self.url.contains("://")).then(|| PathBuf::from(&self.url))
+    }
+
+    /// Fails when the registry is a fixture file that can't be read or parsed.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if let Some(path) = self.fixture_path() {
+            let content = std::fs::read(&path)
+                .map_err(|err| anyhow::anyhow!("Failed to read registry fixture {}: {}", path.display(), err))?;
+            parse_fixture(&content)
+                .map_err(|err| anyhow::anyhow!("Invalid registry fixture {}: {}", path.display(), err))?;
+        }
+        Ok(())
+    }
+
+    async fn fetch_fixture(&self, path: &Path) -> Result<Vec<ProtocolSource>, McpError> {
+        let fixture_error = |message: String| {
+            McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Failed to load registry fixture {}: {}", path.display(), message),
+                None,
+            )
+        };
+
+        let content = tokio::fs::read(path).await.map_err(|err| fixture_error(err.to_string()))?;
+        let connection = parse_fixture(&content).map_err(fixture_error)?;
+
+        Ok(connection.nodes.into_iter().filter_map(|protocol| self.to_source(protocol)).collect())
+    }
+
+    /// Walks every page of the registry's protocol listing and returns the
+    /// protocols that publish a tx3 source.
+    pub async fn fetch_protocols(&self) -> Result<Vec<ProtocolSource>, McpError> {
+        if let Some(path) = self.fixture_path() {
+            return self.fetch_fixture(&path).await;
+        }
+
+        let mut protocols = Vec::new();
+        let mut offset = 0;
+
+        for page in 0..self.max_pages {
+            let connection = self
+                .query(|| ProtocolsQuery::build(ProtocolsQueryVariables {
+                    page_size: self.page_size,
+                    offset: Some(offset),
+                }))
+                .await?
+                .protocols;
+            let fetched = connection.nodes.len();
+
+            protocols.extend(connection.nodes.into_iter().filter_map(|protocol| self.to_source(protocol)));
+
+            if !connection.page_info.has_next_page || fetched == 0 {
+                if page == 0 && fetched == 0 {
+                    tracing::info!("tx3 registry at {} returned no protocols", self.url);
+                }
+                return Ok(protocols);
+            }
+
+            offset += fetched as i32;
+        }
+
+        tracing::warn!(
+            "tx3 registry at {} has more than {} pages of protocols, ignoring the rest",
+            self.url,
+            self.max_pages
+        );
+        Ok(protocols)
+    }
+
+    /// Fetches a single protocol by scope and name.
+    pub async fn fetch_protocol(&self, scope: &str, name: &str) -> Result<Option<ProtocolSource>, McpError> {
+        if let Some(path) = self.fixture_path() {
+            let target = format!("{}_{}", scope, name);
+            let protocols = self.fetch_fixture(&path).await?;
+            return Ok(protocols.into_iter().find(|protocol| protocol.name == target));
+        }
+
+        let response = self
+            .query(|| ProtocolQuery::build(ProtocolQueryVariables {
+                scope: scope.to_string(),
+                name: name.to_string(),
+            }))
+            .await?;
+
+        Ok(response.protocol.and_then(|protocol| self.to_source(protocol)))
+    }
+
+    fn to_source(&self, protocol: Protocol) -> Option<ProtocolSource> {
+        if !self.scopes.is_empty() && !self.scopes.contains(&protocol.scope) {
+            return None;
+        }
+
+        let description = protocol.description
+            .filter(|description| !description.trim().is_empty())
+            .or(protocol.readme);
+
+        Some(ProtocolSource {
+            name: format!("{}_{}", protocol.scope, protocol.name),
+            scope: Some(protocol.scope),
+            content: protocol.source?,
+            registry_url: self.url.clone(),
+            description,
+            version: Some(protocol.version),
+        })
+    }
+
+    async fn query<T, V>(&self, build: impl Fn() -> Operation<T, V>) -> Result<T, McpError>
+    where
+        T: DeserializeOwned + 'static,
+        V: Serialize,
+    {
+        let response = self.retry
+            .retry(
+                &format!("Request to tx3 registry at {}", self.url),
+                || self.send(build()),
+                is_retryable,
+            )
+            .await
+            .map_err(|err| match &err {
+                CynicReqwestError::ErrorResponse(status, _)
+                    if *status == reqwest::StatusCode::UNAUTHORIZED || *status == reqwest::StatusCode::FORBIDDEN =>
+                {
+                    McpError::new(
+                        ErrorCode::INTERNAL_ERROR,
+                        format!("tx3 registry at {} rejected the request as unauthorized, check the registry key", self.url),
+                        Some(json!({ "registry_url": self.url, "status": status.as_u16() })),
+                    )
+                }
+                _ => McpError::new(
+                    ErrorCode::INTERNAL_ERROR,
+                    format!("Failed to reach tx3 registry at {}: {}", self.url, err),
+                    None,
+                ),
+            })?;
+
+        let errors: Vec<String> = response.errors
+            .unwrap_or_default()
+            .into_iter()
+            .map(|error| error.message)
+            .collect();
+
+        if !errors.is_empty() {
+            tracing::warn!("tx3 registry at {} returned errors: {}", self.url, errors.join("; "));
+        }
+
+        match response.data {
+            Some(data) => Ok(data),
+            None => Err(McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("tx3 registry at {} returned errors instead of protocols", self.url),
+                Some(json!({ "registry_url": self.url, "errors": errors })),
+            )),
+        }
+    }
+
+    async fn send<T, V>(&self, operation: Operation<T, V>) -> Result<GraphQlResponse<T>, CynicReqwestError>
+    where
+        T: DeserializeOwned + 'static,
+        V: Serialize,
+    {
+        let mut request = self.client.post(&self.url);
+        if let Some((header_name, bearer, key)) = &self.auth {
+            let key = key.get();
+            let header_value = if *bearer { format!("Bearer {}", key) } else { key };
+            request = request.header(header_name.as_str(), header_value);
+        }
+
+        match tokio::time::timeout(self.timeout, request.run_graphql(operation)).await {
+            Ok(response) => response,
+            Err(_) => Err(CynicReqwestError::ErrorResponse(
+                reqwest::StatusCode::GATEWAY_TIMEOUT,
+                format!("request timed out after {}ms", self.timeout.as_millis()),
+            )),
+        }
+    }
+}
+
+fn parse_fixture(content: &[u8]) -> Result<ProtocolConnection, String> {
+    let response: GraphQlResponse<ProtocolsQuery> = serde_json::from_slice(content).map_err(|err| err.to_string())?;
+    response
+        .data
+        .map(|data| data.protocols)
+        .ok_or_else(|| "document has no data.protocols field".to_string())
+}
+
+/// Connection failures, timeouts and 5xx responses are worth retrying; client errors are final.
+fn is_retryable(err: &CynicReqwestError) -> bool {
+    match err {
+        CynicReqwestError::ReqwestError(err) => err.is_connect() || err.is_timeout() || err.is_request(),
+        CynicReqwestError::ErrorResponse(status, _) => status.is_server_error(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use axum::{Json, Router, extract::State, routing::post};
+    use serde_json::Value;
+    use super::*;
+
+    const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/registry.json");
+
+    fn fixture_registry() -> Registry {
+        Registry::new(&format!("file://{}", FIXTURE))
+    }
+
+    #[tokio::test]
+    async fn fixture_protocols_are_listed() {
+        let protocols = fixture_registry().fetch_protocols().await.unwrap();
+        assert_eq!(protocols.len(), 1);
+
+        let protocol = &protocols[0];
+        assert_eq!(protocol.name, "acme_transfer");
+        assert_eq!(protocol.scope.as_deref(), Some("acme"));
+        assert_eq!(protocol.version.as_deref(), Some("0.1.0"));
+        assert_eq!(protocol.description.as_deref(), Some("Sends ADA from one party to another"));
+        assert!(protocol.content.contains("tx transfer("));
+        assert_eq!(protocol.registry_url, fixture_registry().url());
+    }
+
+    #[tokio::test]
+    async fn plain_paths_are_fixtures_too() {
+        assert_eq!(Registry::new(FIXTURE).fetch_protocols().await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn fixture_protocols_are_found_by_scope_and_name() {
+        let registry = fixture_registry();
+        assert!(registry.fetch_protocol("acme", "transfer").await.unwrap().is_some());
+        assert!(registry.fetch_protocol("acme", "swap").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn scopes_filter_fixture_protocols() {
+        let registry = fixture_registry().with_scopes(vec!["other".to_string()]);
+        assert!(registry.fetch_protocols().await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn broken_fixtures_are_reported() {
+        let registry = Registry::new("file:///nonexistent/registry.json");
+        assert!(registry.validate().is_err());
+        let err = registry.fetch_protocols().await.err().unwrap();
+        assert!(err.message.starts_with("Failed to load registry fixture /nonexistent/registry.json"), "{}", err.message);
+    }
+
+    #[test]
+    fn fixtures_need_a_protocols_field() {
+        assert_eq!(parse_fixture(br#"{"data": null}"#).unwrap_err(), "document has no data.protocols field");
+        assert!(parse_fixture(b"not json").is_err());
+        assert!(fixture_registry().validate().is_ok());
+    }
+
+    #[tokio::test]
+    async fn unreachable_registry_is_an_error() {
+        // Nothing listens on port 1, so the connection is refused right away.
+        let registry = Registry::new("http://127.0.0.1:1/graphql")
+            .with_retry(RetryPolicy::none())
+            .with_timeout(Duration::from_secs(5));
+        assert!(registry.fetch_protocols().await.is_err());
+        assert!(registry.fetch_protocol("acme", "transfer").await.is_err());
+    }
+
+    #[test]
+    fn only_server_errors_are_retried() {
+        assert!(is_retryable(&CynicReqwestError::ErrorResponse(reqwest::StatusCode::BAD_GATEWAY, String::new())));
+        assert!(is_retryable(&CynicReqwestError::ErrorResponse(reqwest::StatusCode::GATEWAY_TIMEOUT, String::new())));
+        assert!(!is_retryable(&CynicReqwestError::ErrorResponse(reqwest::StatusCode::BAD_REQUEST, String::new())));
+        assert!(!is_retryable(&CynicReqwestError::ErrorResponse(reqwest::StatusCode::UNAUTHORIZED, String::new())));
+    }
+
+    /// Serves `count` protocols two per page the way the registry's GraphQL endpoint
+    /// does, counting the requests it gets.
+    async fn paged_registry(count: usize) -> (String, Arc<AtomicUsize>) {
+        let requests = Arc::new(AtomicUsize::new(0));
+        let handler = move |State(requests): State<Arc<AtomicUsize>>, Json(body): Json<Value>| async move {
+            requests.fetch_add(1, Ordering::SeqCst);
+            let offset = body["variables"]["offset"].as_u64().unwrap_or(0) as usize;
+            let nodes: Vec<Value> = (offset..count.min(offset + 2))
+                .map(|index| json!({
+                    "scope": "acme",
+                    "name": format!("protocol{}", index),
+                    "version": "0.1.0",
+                    "source": "party A;",
+                    "description": null,
+                    "readme": null,
+                }))
+                .collect();
+            Json(json!({ "data": { "protocols": { "pageInfo": { "hasNextPage": offset + 2 < count }, "nodes": nodes } } }))
+        };
+        let router = Router::new().route("/graphql", post(handler)).with_state(requests.clone());
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/graphql", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+        (url, requests)
+    }
+
+    #[tokio::test]
+    async fn every_page_is_fetched() {
+        let (url, requests) = paged_registry(5).await;
+        let protocols = Registry::new(&url).with_page_size(Some(2)).fetch_protocols().await.unwrap();
+
+        let names: Vec<&str> = protocols.iter().map(|protocol| protocol.name.as_str()).collect();
+        assert_eq!(names, ["acme_protocol0", "acme_protocol1", "acme_protocol2", "acme_protocol3", "acme_protocol4"]);
+        assert_eq!(requests.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn pages_stop_at_the_limit() {
+        let (url, requests) = paged_registry(10).await;
+        let protocols = Registry::new(&url).with_max_pages(2).fetch_protocols().await.unwrap();
+
+        assert_eq!(protocols.len(), 4);
+        assert_eq!(requests.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,188 @@
+use std::collections::HashMap;
+use std::hash::{DefaultHasher, Hash, Hasher};
+use std::path::Path;
+use anyhow::{Context, Result};
+use futures::future::{BoxFuture, FutureExt};
+use serde::Deserialize;
+use serde_json::Value;
+use tx3_sdk::trp::{Client as TrpClient, Error as TrpError, ProtoTxRequest};
+
+/// URL scheme of the offline resolver, e.g. `mock://` or `mock:///path/to/fixtures.json`.
+pub const MOCK_SCHEME: &str = "mock://";
+
+/// Turns a transaction request into hex encoded CBOR. The server only talks to TRP
+/// through this trait, so a mock can stand in for it.
+pub trait Resolver: Send + Sync {
+    fn resolve<'a>(
+        &'a self,
+        protocol: &'a str,
+        transaction: &'a str,
+        request: ProtoTxRequest,
+    ) -> BoxFuture<'a, Result<String, TrpError>>;
+}
+
+impl Resolver for TrpClient {
+    fn resolve<'a>(
+        &'a self,
+        _protocol: &'a str,
+        _transaction: &'a str,
+        request: ProtoTxRequest,
+    ) -> BoxFuture<'a, Result<String, TrpError>> {
+        TrpClient::resolve(self, request).map(|result| result.map(|envelope| envelope.tx)).boxed()
+    }
+}
+
+#[derive(Default, Deserialize)]
+struct MockFixtures {
+    /// Errors returned instead of a transaction, keyed by `protocol.transaction`.
+    #[serde(default)]
+    errors: HashMap<String, MockError>,
+}
+
+#[derive(Clone, Deserialize)]
+struct MockError {
+    message: String,
+    #[serde(default)]
+    data: Value,
+}
+
+/// Resolves without contacting TRP, for demos and tests. The result is a CBOR array
+/// holding a hash of the TIR and the args, so the same request always resolves to the
+/// same payload. It is not a valid Cardano transaction.
+pub struct MockResolver {
+    errors: HashMap<String, MockError>,
+}
+
+impl MockResolver {
+    /// Builds the mock for a `mock://` URL, loading canned errors from the fixtures
+    /// file in its path, if any.
+    pub fn from_url(url: &str) -> Result<Self> {
+        let path = url.strip_prefix(MOCK_SCHEME).unwrap_or(url);
+        let fixtures = if path.is_empty() {
+            MockFixtures::default()
+        } else {
+            let content = std::fs::read_to_string(Path::new(path))
+                .with_context(|| format!("Failed to read mock TRP fixtures {}", path))?;
+            serde_json::from_str(&content).with_context(|| format!("Invalid mock TRP fixtures {}", path))?
+        };
+
+        Ok(Self { errors: fixtures.errors })
+    }
+}
+
+impl Resolver for MockResolver {
+    fn resolve<'a>(
+        &'a self,
+        protocol: &'a str,
+        transaction: &'a str,
+        request: ProtoTxRequest,
+    ) -> BoxFuture<'a, Result<String, TrpError>> {
+        let result = match self.errors.get(&format!("{}.{}", protocol, transaction)) {
+            Some(error) => Err(TrpError::JsonRpcError(error.message.clone(), error.data.to_string())),
+            None => Ok(mock_tx(&request)),
+        };
+        futures::future::ready(result).boxed()
+    }
+}
+
+fn mock_tx(request: &ProtoTxRequest) -> String {
+    let mut hasher = DefaultHasher::new();
+    request.tir.bytecode.hash(&mut hasher);
+    let tir_hash = hasher.finish().to_be_bytes();
+
+    let mut args: Vec<String> = request.args.iter().map(|(name, value)| format!("{}={:?}", name, value)).collect();
+    args.sort();
+    let args = args.join(",");
+
+    let mut cbor = vec![0x82, 0x40 | tir_hash.len() as u8];
+    cbor.extend(tir_hash);
+    cbor.extend(cbor_header(3, args.len()));
+    cbor.extend(args.as_bytes());
+    hex::encode(cbor)
+}
+
+fn cbor_header(major: u8, len: usize) -> Vec<u8> {
+    let major = major << 5;
+    match len {
+        0..=23 => vec![major | len as u8],
+        24..=0xff => vec![major | 24, len as u8],
+        0x100..=0xffff => [vec![major | 25], (len as u16).to_be_bytes().to_vec()].concat(),
+        _ => [vec![major | 26], (len as u32).to_be_bytes().to_vec()].concat(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use tx3_lang::ArgValue;
+    use tx3_sdk::trp::TirInfo;
+    use super::*;
+
+    fn request(bytecode: &str, args: &[(&str, i128)]) -> ProtoTxRequest {
+        ProtoTxRequest {
+            tir: TirInfo {
+                bytecode: bytecode.to_string(),
+                encoding: "hex".to_string(),
+                version: "v1alpha6".to_string(),
+            },
+            args: args.iter().map(|(name, value)| (name.to_string(), ArgValue::Int(*value))).collect(),
+        }
+    }
+
+    #[test]
+    fn cbor_header_switches_width_at_each_boundary() {
+        assert_eq!(cbor_header(3, 0), [0x60]);
+        assert_eq!(cbor_header(3, 23), [0x77]);
+        assert_eq!(cbor_header(3, 24), [0x78, 24]);
+        assert_eq!(cbor_header(3, 0xff), [0x78, 0xff]);
+        assert_eq!(cbor_header(3, 0x100), [0x79, 0x01, 0x00]);
+        assert_eq!(cbor_header(3, 0xffff), [0x79, 0xff, 0xff]);
+        assert_eq!(cbor_header(3, 0x10000), [0x7a, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(cbor_header(2, 8), [0x48]);
+    }
+
+    #[test]
+    fn mock_tx_is_a_cbor_array_of_hash_and_args() {
+        let tx = hex::decode(mock_tx(&request("cafe", &[("quantity", 5)]))).unwrap();
+        assert_eq!(&tx[..2], [0x82, 0x48]);
+        let args = format!("quantity={:?}", ArgValue::Int(5));
+        assert_eq!(&tx[10..10 + cbor_header(3, args.len()).len()], cbor_header(3, args.len()));
+        assert!(tx.ends_with(args.as_bytes()));
+    }
+
+    #[test]
+    fn mock_tx_depends_on_the_request_only() {
+        let tx = mock_tx(&request("cafe", &[("a", 1), ("b", 2)]));
+        assert_eq!(tx, mock_tx(&request("cafe", &[("b", 2), ("a", 1)])));
+        assert_ne!(tx, mock_tx(&request("cafd", &[("a", 1), ("b", 2)])));
+        assert_ne!(tx, mock_tx(&request("cafe", &[("a", 1), ("b", 3)])));
+    }
+
+    #[test]
+    fn long_args_use_a_wider_header() {
+        let tx = hex::decode(mock_tx(&request("cafe", &[("x".repeat(300).as_str(), 1)]))).unwrap();
+        assert_eq!(tx[10], 0x79);
+    }
+
+    #[tokio::test]
+    async fn fixtures_return_canned_errors() {
+        let path = std::env::temp_dir().join(format!("tx3-mcp-mock-{}.json", std::process::id()));
+        std::fs::write(&path, r#"{"errors": {"acme.swap": {"message": "Insufficient funds", "data": {"missing": 5}}}}"#).unwrap();
+        let resolver = MockResolver::from_url(&format!("{}{}", MOCK_SCHEME, path.display())).unwrap();
+        let _ = std::fs::remove_file(&path);
+
+        match resolver.resolve("acme", "swap", request("cafe", &[])).await {
+            Err(TrpError::JsonRpcError(message, data)) => {
+                assert_eq!(message, "Insufficient funds");
+                assert_eq!(data, r#"{"missing":5}"#);
+            }
+            _ => panic!("acme.swap should fail with the canned error"),
+        }
+        assert!(resolver.resolve("acme", "mint", request("cafe", &[])).await.is_ok());
+    }
+
+    #[test]
+    fn missing_fixtures_fail_to_load() {
+        assert!(MockResolver::from_url("mock://").is_ok());
+        assert!(MockResolver::from_url("mock:///nonexistent/fixtures.json").is_err());
+    }
+}
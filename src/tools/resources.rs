@@ -0,0 +1,117 @@
+use rmcp::Error as McpError;
+use rmcp::model::ErrorCode;
+
+/// Prefix of the resource URI of a protocol, followed by its `scope_name` identifier.
+pub const PROTOCOL_URI_PREFIX: &str = "tx3://protocol/";
+
+/// URI of the recent resolutions, readable as a JSON array.
+pub const HISTORY_URI: &str = "tx3://history/resolutions";
+
+/// Mime type of tx3 source documents.
+pub const TX3_MIME_TYPE: &str = "text/x-tx3";
+
+/// Resources listed per `resources/list` page.
+pub const RESOURCES_PAGE_SIZE: usize = 50;
+
+/// Templates of the per-transaction documents, listed by `resources/templates/list`.
+pub const TIR_URI_TEMPLATE: &str = "tx3://protocol/{protocol}/tx/{transaction}/tir";
+pub const SCHEMA_URI_TEMPLATE: &str = "tx3://protocol/{protocol}/tx/{transaction}/schema";
+
+pub fn protocol_uri(protocol: &str) -> String {
+    format!("{}{}", PROTOCOL_URI_PREFIX, protocol)
+}
+
+/// A parsed `tx3://` resource URI.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ResourceUri {
+    History,
+    Protocol(String),
+    Tir { protocol: String, transaction: String },
+    Schema { protocol: String, transaction: String },
+}
+
+pub fn parse_uri(uri: &str) -> Result<ResourceUri, McpError> {
+    let invalid = |problem: String| McpError::new(
+        ErrorCode::RESOURCE_NOT_FOUND,
+        format!("Unknown resource {}: {}", uri, problem),
+        None,
+    );
+
+    if uri == HISTORY_URI {
+        return Ok(ResourceUri::History);
+    }
+
+    let path = uri.strip_prefix(PROTOCOL_URI_PREFIX)
+        .ok_or_else(|| invalid(format!("expected a URI starting with {}", PROTOCOL_URI_PREFIX)))?;
+    let segments: Vec<&str> = path.split('/').collect();
+
+    let protocol = segments[0];
+    if protocol.is_empty() {
+        return Err(invalid("the {protocol} segment is empty".to_string()));
+    }
+    if segments.len() == 1 {
+        return Ok(ResourceUri::Protocol(protocol.to_string()));
+    }
+
+    if segments[1] != "tx" {
+        return Err(invalid(format!("expected tx after the protocol, found {}", segments[1])));
+    }
+    let transaction = match segments.get(2) {
+        Some(transaction) if !transaction.is_empty() => transaction.to_string(),
+        _ => return Err(invalid("the {transaction} segment is missing".to_string())),
+    };
+    let protocol = protocol.to_string();
+
+    match &segments[3..] {
+        ["tir"] => Ok(ResourceUri::Tir { protocol, transaction }),
+        ["schema"] => Ok(ResourceUri::Schema { protocol, transaction }),
+        [] => Err(invalid("expected tir or schema after the transaction".to_string())),
+        [document] => Err(invalid(format!("expected tir or schema after the transaction, found {}", document))),
+        _ => Err(invalid("unexpected segments after the document name".to_string())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parsed(uri: &str) -> ResourceUri {
+        parse_uri(uri).unwrap()
+    }
+
+    #[test]
+    fn parses_history_and_protocol_uris() {
+        assert_eq!(parsed(HISTORY_URI), ResourceUri::History);
+        assert_eq!(parsed(&protocol_uri("acme_swap")), ResourceUri::Protocol("acme_swap".to_string()));
+    }
+
+    #[test]
+    fn rejects_unknown_uris() {
+        for uri in ["https://acme.example", "tx3://protocol/", "tx3://history/other"] {
+            let err = parse_uri(uri).unwrap_err();
+            assert_eq!(err.code.0, ErrorCode::RESOURCE_NOT_FOUND.0);
+            assert!(err.message.starts_with(&format!("Unknown resource {}", uri)), "{}", err.message);
+        }
+    }
+
+    #[test]
+    fn parses_transaction_documents() {
+        let tx = |document: &str| format!("tx3://protocol/acme_swap/tx/swap/{}", document);
+        assert_eq!(parsed(&tx("tir")), ResourceUri::Tir { protocol: "acme_swap".to_string(), transaction: "swap".to_string() });
+        assert_eq!(parsed(&tx("schema")), ResourceUri::Schema { protocol: "acme_swap".to_string(), transaction: "swap".to_string() });
+    }
+
+    #[test]
+    fn rejects_malformed_transaction_documents() {
+        for uri in [
+            "tx3://protocol/acme_swap/txs/swap/tir",
+            "tx3://protocol/acme_swap/tx",
+            "tx3://protocol/acme_swap/tx/",
+            "tx3://protocol/acme_swap/tx/swap",
+            "tx3://protocol/acme_swap/tx/swap/cbor",
+            "tx3://protocol/acme_swap/tx/swap/tir/extra",
+        ] {
+            assert!(parse_uri(uri).is_err(), "{}", uri);
+        }
+    }
+}
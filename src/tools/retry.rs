@@ -0,0 +1,116 @@
+use std::fmt::Display;
+use std::future::Future;
+use std::time::Duration;
+use rand::Rng;
+
+/// Exponential backoff with jitter for calls to upstream services.
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    pub base_delay: Duration,
+}
+
+impl RetryPolicy {
+    pub const fn new(attempts: u32, base_delay: Duration) -> Self {
+        Self { attempts, base_delay }
+    }
+
+    /// A policy that makes a single attempt.
+    pub const fn none() -> Self {
+        Self::new(1, Duration::ZERO)
+    }
+
+    fn delay(&self, attempt: u32) -> Duration {
+        let backoff = self.base_delay.saturating_mul(1 << attempt.min(16));
+        let jitter = rand::thread_rng().gen_range(0..=backoff.as_millis() as u64 / 2);
+        backoff + Duration::from_millis(jitter)
+    }
+
+    /// Runs `operation` until it succeeds, the error is not retryable, or the attempts
+    /// are exhausted, in which case the last error is returned.
+    pub async fn retry<T, E, F, Fut>(
+        &self,
+        what: &str,
+        mut operation: F,
+        is_retryable: impl Fn(&E) -> bool,
+    ) -> Result<T, E>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, E>>,
+        E: Display,
+    {
+        let mut attempt = 0;
+        loop {
+            match operation().await {
+                Ok(value) => return Ok(value),
+                Err(err) if attempt + 1 < self.attempts && is_retryable(&err) => {
+                    let delay = self.delay(attempt);
+                    attempt += 1;
+                    tracing::warn!(
+                        "{} failed (attempt {}/{}), retrying in {:?}: {}",
+                        what,
+                        attempt,
+                        self.attempts,
+                        delay,
+                        err
+                    );
+                    tokio::time::sleep(delay).await;
+                }
+                Err(err) => return Err(err),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::cell::Cell;
+    use super::*;
+
+    /// No backoff, so the tests don't sleep.
+    const IMMEDIATE: RetryPolicy = RetryPolicy::new(3, Duration::ZERO);
+
+    async fn run(policy: RetryPolicy, failures: u32, retryable: bool) -> (Result<u32, String>, u32) {
+        let calls = Cell::new(0);
+        let result = policy.retry(
+            "test call",
+            || {
+                calls.set(calls.get() + 1);
+                let call = calls.get();
+                async move { if call <= failures { Err(format!("failure {}", call)) } else { Ok(call) } }
+            },
+            |_| retryable,
+        ).await;
+        (result, calls.get())
+    }
+
+    #[tokio::test]
+    async fn retries_until_success() {
+        assert_eq!(run(IMMEDIATE, 2, true).await, (Ok(3), 3));
+    }
+
+    #[tokio::test]
+    async fn gives_up_with_the_last_error() {
+        assert_eq!(run(IMMEDIATE, 5, true).await, (Err("failure 3".to_string()), 3));
+    }
+
+    #[tokio::test]
+    async fn final_errors_are_not_retried() {
+        assert_eq!(run(IMMEDIATE, 5, false).await, (Err("failure 1".to_string()), 1));
+    }
+
+    #[tokio::test]
+    async fn none_makes_a_single_attempt() {
+        assert_eq!(run(RetryPolicy::none(), 5, true).await, (Err("failure 1".to_string()), 1));
+    }
+
+    #[test]
+    fn delay_doubles_with_up_to_half_of_jitter() {
+        let policy = RetryPolicy::new(5, Duration::from_millis(100));
+        for attempt in 0..4 {
+            let backoff = Duration::from_millis(100 << attempt);
+            let delay = policy.delay(attempt);
+            assert!(delay >= backoff && delay <= backoff + backoff / 2, "attempt {}: {:?}", attempt, delay);
+        }
+    }
+}
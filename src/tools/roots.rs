@@ -0,0 +1,86 @@
+use std::path::{Path, PathBuf};
+
+use super::registry::ProtocolSource;
+
+/// Directories below a root that are searched for `.tx3` files.
+pub const DEFAULT_MAX_DEPTH: usize = 4;
+
+/// Directory names never descended into.
+pub const DEFAULT_IGNORE: [&str; 5] = [".git", "node_modules", "target", "dist", "build"];
+
+/// How the filesystem roots advertised by the client are scanned for protocols.
+#[derive(Clone)]
+pub struct RootScan {
+    pub max_depth: usize,
+    pub ignore: Vec<String>,
+}
+
+impl Default for RootScan {
+    fn default() -> Self {
+        Self {
+            max_depth: DEFAULT_MAX_DEPTH,
+            ignore: DEFAULT_IGNORE.iter().map(|name| name.to_string()).collect(),
+        }
+    }
+}
+
+impl RootScan {
+    /// Loads every `.tx3` file under the `file://` roots as a local protocol named after
+    /// its file stem. Files that don't compile are logged and skipped, and when two files
+    /// share a stem the first one found wins.
+    pub fn scan(&self, roots: &[String]) -> Vec<ProtocolSource> {
+        let mut files = Vec::new();
+        for root in roots {
+            match root.strip_prefix("file://") {
+                Some(path) => self.walk(Path::new(path), 0, &mut files),
+                None => tracing::debug!("Skipping root {}, only file:// roots are scanned", root),
+            }
+        }
+
+        let mut protocols: Vec<ProtocolSource> = Vec::new();
+        for path in files {
+            let Some(name) = path.file_stem().map(|stem| stem.to_string_lossy().to_string()) else {
+                continue;
+            };
+            if protocols.iter().any(|protocol| protocol.name == name) {
+                tracing::warn!("Skipping {}, a protocol named {} was already found in the roots", path.display(), name);
+                continue;
+            }
+
+            let origin = format!("file://{}", path.display());
+            let loaded = std::fs::read_to_string(&path)
+                .map_err(anyhow::Error::from)
+                .and_then(|content| ProtocolSource::local(&name, content, &origin));
+            match loaded {
+                Ok(protocol) => protocols.push(protocol),
+                Err(err) => tracing::warn!("Skipping {}: {}", path.display(), err),
+            }
+        }
+
+        protocols
+    }
+
+    fn walk(&self, dir: &Path, depth: usize, files: &mut Vec<PathBuf>) {
+        let entries = match std::fs::read_dir(dir) {
+            Ok(entries) => entries,
+            Err(err) => {
+                tracing::debug!("Failed to read {}: {}", dir.display(), err);
+                return;
+            }
+        };
+
+        let mut paths: Vec<PathBuf> = entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect();
+        paths.sort();
+
+        for path in paths {
+            let name = path.file_name().map(|name| name.to_string_lossy().to_string()).unwrap_or_default();
+            if path.is_dir() {
+                if depth < self.max_depth && !self.ignore.contains(&name) {
+                    self.walk(&path, depth + 1, files);
+                }
+            } else if path.extension().is_some_and(|extension| extension == "tx3") {
+                files.push(path);
+            }
+        }
+    }
+}
@@ -0,0 +1,177 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use anyhow::{Context, Result};
+
+/// Stands in for credentials in logs and status output.
+pub const REDACTED: &str = "***";
+
+/// Whether a header, env var or arg name may hold a credential.
+pub fn is_sensitive(name: &str) -> bool {
+    let name = name.to_ascii_lowercase();
+    ["key", "token", "auth", "secret", "password", "cookie"].iter().any(|word| name.contains(word))
+}
+
+/// Name-value pairs safe to log: values of sensitive names are replaced with `***`.
+/// Headers, env and `ClientOptions` are only logged through this.
+pub fn redact<'a, V: fmt::Display + 'a>(entries: impl IntoIterator<Item = (&'a String, &'a V)>) -> BTreeMap<&'a str, String> {
+    entries.into_iter()
+        .map(|(name, value)| {
+            let value = if is_sensitive(name) { REDACTED.to_string() } else { value.to_string() };
+            (name.as_str(), value)
+        })
+        .collect()
+}
+
+/// How often key files are checked for rotation.
+pub const RELOAD_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct SecretState {
+    value: String,
+    modified: Option<SystemTime>,
+}
+
+/// An API key, either given inline or read from a file that may be rotated. Clones
+/// share the value, so a reload is seen by every holder; requests already sent keep
+/// the key they were built with. The value is never printed by `Debug`.
+#[derive(Clone)]
+pub struct Secret {
+    path: Option<PathBuf>,
+    state: Arc<RwLock<SecretState>>,
+}
+
+impl Secret {
+    pub fn new(value: &str) -> Self {
+        Self {
+            path: None,
+            state: Arc::new(RwLock::new(SecretState { value: value.to_string(), modified: None })),
+        }
+    }
+
+    /// Reads the secret from `path`, trimming the trailing newline most tools add.
+    pub fn from_file(path: &Path) -> Result<Self> {
+        let secret = Self {
+            path: Some(path.to_path_buf()),
+            state: Arc::default(),
+        };
+        secret.reload()?;
+        Ok(secret)
+    }
+
+    pub fn get(&self) -> String {
+        self.state.read().unwrap().value.clone()
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// Re-reads a file backed secret. Returns whether the value changed.
+    pub fn reload(&self) -> Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        let value = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read secret file {}", path.display()))?
+            .trim()
+            .to_string();
+
+        let mut state = self.state.write().unwrap();
+        state.modified = modified;
+        if state.value == value {
+            return Ok(false);
+        }
+        state.value = value;
+        Ok(true)
+    }
+
+    /// Reloads the secret when its file changed since the last read.
+    pub fn reload_if_modified(&self) -> Result<bool> {
+        let Some(path) = &self.path else {
+            return Ok(false);
+        };
+
+        let modified = std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+        if modified.is_some() && modified == self.state.read().unwrap().modified {
+            return Ok(false);
+        }
+        self.reload()
+    }
+}
+
+impl fmt::Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.path {
+            Some(path) => write!(f, "Secret({}, <redacted>)", path.display()),
+            None => f.write_str("Secret(<redacted>)"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn credential_names_are_sensitive() {
+        for name in ["dmtr-api-key", "Authorization", "X-Auth-Token", "client_secret", "PASSWORD", "Cookie"] {
+            assert!(is_sensitive(name), "{}", name);
+        }
+        for name in ["Content-Type", "network", "X-Request-Source"] {
+            assert!(!is_sensitive(name), "{}", name);
+        }
+    }
+
+    #[test]
+    fn redact_replaces_sensitive_values_only() {
+        let entries = BTreeMap::from([
+            ("api_key".to_string(), "abc".to_string()),
+            ("network".to_string(), "preprod".to_string()),
+        ]);
+        let redacted = redact(entries.iter());
+        assert_eq!(redacted["api_key"], REDACTED);
+        assert_eq!(redacted["network"], "preprod");
+    }
+
+    #[test]
+    fn debug_never_prints_the_value() {
+        let secret = Secret::new("dmtr_secret");
+        assert_eq!(format!("{:?}", secret), "Secret(<redacted>)");
+        assert_eq!(secret.get(), "dmtr_secret");
+    }
+
+    #[test]
+    fn file_secrets_are_trimmed_and_reloaded() {
+        let path = std::env::temp_dir().join(format!("tx3-mcp-secret-{}", std::process::id()));
+        std::fs::write(&path, "first\n").unwrap();
+        let secret = Secret::from_file(&path).unwrap();
+        let shared = secret.clone();
+        assert_eq!(secret.get(), "first");
+        assert!(!secret.reload().unwrap());
+
+        std::fs::write(&path, "second\n").unwrap();
+        assert!(secret.reload().unwrap());
+        let _ = std::fs::remove_file(&path);
+
+        assert_eq!(shared.get(), "second");
+        assert!(format!("{:?}", secret).ends_with(", <redacted>)"));
+    }
+
+    #[test]
+    fn missing_files_fail_to_load() {
+        assert!(Secret::from_file(Path::new("/nonexistent/trp.key")).is_err());
+    }
+
+    #[test]
+    fn inline_secrets_never_reload() {
+        let secret = Secret::new("inline");
+        assert!(!secret.reload().unwrap());
+        assert!(!secret.reload_if_modified().unwrap());
+        assert_eq!(secret.path(), None);
+    }
+}
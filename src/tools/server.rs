@@ -0,0 +1,182 @@
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::time::Duration;
+use anyhow::Result;
+use axum::{Json, Router};
+use axum::http::{StatusCode, header};
+use axum::routing::get;
+use axum::serve::Listener;
+use serde_json::json;
+use tokio::task::JoinHandle;
+use tokio_util::sync::CancellationToken;
+use tracing_subscriber::EnvFilter;
+
+use super::check::ReportFormat;
+use super::config;
+use super::protocol::ProtocolTool;
+use super::secret;
+
+/// How log lines are written.
+#[derive(Clone, Copy, Default)]
+pub enum LogFormat {
+    #[default]
+    Text,
+    /// One JSON object per line, span fields included.
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            _ => Err(format!("unknown log format {}, expected text or json", value)),
+        }
+    }
+}
+
+/// Installs the log subscriber in the `LOG_FORMAT` format, filtered by `RUST_LOG`. When
+/// stdout is taken, by the stdio transport or a `--check` report, `stdio` logs to stderr
+/// without colors.
+pub fn init_logging(stdio: bool) -> Result<()> {
+    let format = config::optional::<LogFormat>("LOG_FORMAT")?.unwrap_or_default();
+    let directives = config::var("RUST_LOG");
+    let filter = if stdio {
+        EnvFilter::new(directives.unwrap_or_default()).add_directive(tracing::Level::DEBUG.into())
+    } else {
+        directives
+            .and_then(|directives| EnvFilter::try_new(directives).ok())
+            .unwrap_or_else(|| "debug".into())
+    };
+
+    let builder = tracing_subscriber::fmt().with_env_filter(filter);
+    match (format, stdio) {
+        (LogFormat::Json, true) => builder.json().with_writer(std::io::stderr).init(),
+        (LogFormat::Json, false) => builder.json().init(),
+        (LogFormat::Text, true) => builder.with_writer(std::io::stderr).with_ansi(false).init(),
+        (LogFormat::Text, false) => builder.init(),
+    }
+    Ok(())
+}
+
+/// Probes TRP before serving. Fails when `TRP_REQUIRE_HEALTHY` is set, otherwise the
+/// server starts anyway and calls fail until TRP recovers.
+pub async fn check_trp(tool: &ProtocolTool) -> Result<()> {
+    if let Err(err) = tool.probe_trp().await {
+        if config::optional::<bool>("TRP_REQUIRE_HEALTHY")?.unwrap_or(false) {
+            anyhow::bail!("TRP health check failed: {}", err);
+        }
+        tracing::warn!("Starting anyway, TRP calls are likely to fail until it recovers");
+    }
+    Ok(())
+}
+
+/// Prints the `--check` report of `tool` to stdout, failing when a check did.
+pub async fn check(tool: &ProtocolTool, format: ReportFormat) -> Result<()> {
+    let report = tool.check().await;
+    println!("{}", report.render(format));
+    if !report.passed {
+        anyhow::bail!("Configuration check failed");
+    }
+    Ok(())
+}
+
+/// How long in-flight tool calls may run once a shutdown signal arrives.
+pub const DEFAULT_DRAIN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Resolves on SIGINT or, on unix, SIGTERM.
+async fn shutdown_signal() -> Result<()> {
+    #[cfg(unix)]
+    {
+        let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+        tokio::select! {
+            result = tokio::signal::ctrl_c() => result?,
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    tokio::signal::ctrl_c().await?;
+    Ok(())
+}
+
+/// Waits for SIGINT or SIGTERM, then cancels `stop_accepting` so listeners take no new
+/// connections, refuses new tool calls and lets the running ones finish for up to
+/// `DRAIN_TIMEOUT_SECS` before they are cancelled.
+pub async fn wait_for_shutdown(tool: &ProtocolTool, stop_accepting: Option<&CancellationToken>) -> Result<()> {
+    let timeout = config::optional::<u64>("DRAIN_TIMEOUT_SECS")?
+        .map(Duration::from_secs)
+        .unwrap_or(DEFAULT_DRAIN_TIMEOUT);
+    shutdown_signal().await?;
+    if let Some(stop_accepting) = stop_accepting {
+        stop_accepting.cancel();
+    }
+    tracing::info!("Shutting down, letting tool calls finish for up to {:?}", timeout);
+    tool.drain(timeout).await;
+    Ok(())
+}
+
+/// Starts the registry refresh and key reload tasks, and the metrics listener when
+/// `METRICS_ADDRESS` is set. Everything stops once `ct` is cancelled.
+pub async fn spawn_background_tasks(tool: &ProtocolTool, ct: &CancellationToken) -> Result<()> {
+    if let Some(secs) = config::optional::<u64>("REGISTRY_REFRESH_INTERVAL_SECS")? {
+        tool.spawn_refresh_task(Duration::from_secs(secs), ct.child_token());
+    }
+    tool.spawn_secret_reload_task(secret::RELOAD_INTERVAL, ct.child_token());
+
+    if let Some(metrics_address) = config::optional::<SocketAddr>("METRICS_ADDRESS")? {
+        serve_metrics(tool.clone(), metrics_address, ct.child_token()).await?;
+    }
+    Ok(())
+}
+
+/// `/healthz` answers 200 while the process runs, `/readyz` answers 200 once the server
+/// can list tools and 503 with a JSON explanation before. Neither opens an MCP session.
+#[allow(dead_code)]
+pub fn health_router(tool: ProtocolTool) -> Router {
+    Router::new()
+        .route("/healthz", get(|| async { StatusCode::OK }))
+        .route(
+            "/readyz",
+            get(move || {
+                let tool = tool.clone();
+                async move {
+                    match tool.readiness().await {
+                        Ok(()) => (StatusCode::OK, Json(json!({ "ready": true }))),
+                        Err(reason) => (StatusCode::SERVICE_UNAVAILABLE, Json(reason)),
+                    }
+                }
+            }),
+        )
+}
+
+/// Serves `/metrics` on its own listener, so scrapers need no client token.
+async fn serve_metrics(tool: ProtocolTool, bind: SocketAddr, ct: CancellationToken) -> Result<()> {
+    let router = Router::new().route(
+        "/metrics",
+        get(move || {
+            let tool = tool.clone();
+            async move { ([(header::CONTENT_TYPE, "text/plain; version=0.0.4")], tool.render_metrics()) }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind(bind).await?;
+    tracing::info!("Serving metrics on http://{}/metrics", bind);
+    serve(listener, router, ct, "Metrics");
+    Ok(())
+}
+
+/// Serves `router` until `ct` is cancelled, letting in-flight requests finish. The
+/// returned handle completes once they have.
+pub fn serve<L>(listener: L, router: Router, ct: CancellationToken, what: &'static str) -> JoinHandle<()>
+where
+    L: Listener,
+    L::Addr: std::fmt::Debug,
+{
+    tokio::spawn(async move {
+        let server = axum::serve(listener, router).with_graceful_shutdown(async move { ct.cancelled().await });
+        if let Err(err) = server.await {
+            tracing::error!("{} server error: {}", what, err);
+        }
+    })
+}
@@ -0,0 +1,68 @@
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use serde::{Deserialize, Serialize};
+
+use super::registry::ProtocolSource;
+
+#[derive(Serialize, Deserialize)]
+struct Snapshot {
+    saved_at: u64,
+    protocols: Vec<ProtocolSource>,
+}
+
+/// Last successfully fetched protocol list persisted on disk, so a cold start without
+/// registry access still exposes tools.
+pub struct DiskSnapshot {
+    path: PathBuf,
+}
+
+impl DiskSnapshot {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    /// `$XDG_CACHE_HOME/tx3-mcp/registry.json`, falling back to `~/.cache`.
+    pub fn default_path() -> Option<PathBuf> {
+        let cache_home = std::env::var_os("XDG_CACHE_HOME")
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".cache")))?;
+        Some(cache_home.join("tx3-mcp").join("registry.json"))
+    }
+
+    pub async fn save(&self, protocols: &[ProtocolSource]) {
+        let snapshot = Snapshot {
+            saved_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default().as_secs(),
+            protocols: protocols.to_vec(),
+        };
+
+        let result = async {
+            if let Some(parent) = self.path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let content = serde_json::to_vec(&snapshot)?;
+            tokio::fs::write(&self.path, content).await?;
+            anyhow::Ok(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            tracing::warn!("Failed to write registry snapshot to {}: {}", self.path.display(), err);
+        }
+    }
+
+    /// Returns the persisted protocols together with the age of the snapshot.
+    pub async fn load(&self) -> Option<(Vec<ProtocolSource>, Duration)> {
+        let content = tokio::fs::read(&self.path).await.ok()?;
+        let snapshot: Snapshot = match serde_json::from_slice(&content) {
+            Ok(snapshot) => snapshot,
+            Err(err) => {
+                tracing::warn!("Ignoring unreadable registry snapshot at {}: {}", self.path.display(), err);
+                return None;
+            }
+        };
+
+        let saved_at = UNIX_EPOCH + Duration::from_secs(snapshot.saved_at);
+        let age = SystemTime::now().duration_since(saved_at).unwrap_or_default();
+        Some((snapshot.protocols, age))
+    }
+}
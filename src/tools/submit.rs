@@ -0,0 +1,110 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::sync::Arc;
+use serde_json::{Value, json};
+use rmcp::Error as McpError;
+use rmcp::model::{ErrorCode, Tool, ToolAnnotations};
+
+pub const SUBMIT_TRANSACTION: &str = "submit-transaction";
+
+/// Submitting is the only tool with side effects on chain, annotated as destructive so
+/// clients ask the user for confirmation.
+pub fn tool() -> Tool {
+    let schema = json!({
+        "type": "object",
+        "properties": {
+            "tx": {
+                "type": "string",
+                "description": "Signed transaction as hex encoded CBOR",
+                "pattern": "^([0-9a-fA-F]{2})+$",
+            },
+            "tx_hash": {
+                "type": "string",
+                "description": "Hash of a transaction resolved earlier in this session, submitted as resolved instead of `tx`",
+                "pattern": "^[0-9a-fA-F]{64}$",
+            },
+        },
+    });
+
+    Tool {
+        name: Cow::Borrowed(SUBMIT_TRANSACTION),
+        description: Some(Cow::Borrowed(
+            "Submits a signed transaction to the chain through TRP. Resolve the transaction first, \
+             have it signed by the user's wallet, then pass the signed CBOR hex as `tx`. A transaction \
+             that needs no further signatures can instead be referenced by the `hash` its resolve \
+             returned, passed as `tx_hash`; the server remembers the recent resolves of this session.",
+        )),
+        annotations: Some(ToolAnnotations {
+            title: Some("Submit transaction".to_string()),
+            read_only_hint: Some(false),
+            destructive_hint: Some(true),
+            idempotent_hint: Some(false),
+            open_world_hint: Some(true),
+        }),
+        input_schema: Arc::new(schema.as_object().cloned().unwrap_or_default()),
+    }
+}
+
+/// Sends `trp.submit` to the TRP endpoint. JSON-RPC errors, such as a failing script,
+/// come back with the node message untouched and the error data attached.
+pub async fn submit(
+    client: &reqwest::Client,
+    endpoint: &str,
+    headers: &HashMap<String, String>,
+    tx: &str,
+) -> Result<Value, McpError> {
+    if hex::decode(tx).is_err() {
+        return Err(McpError::new(
+            ErrorCode::INVALID_PARAMS,
+            "Signed transaction must be hex encoded CBOR".to_string(),
+            None,
+        ));
+    }
+
+    let body = json!({
+        "jsonrpc": "2.0",
+        "method": "trp.submit",
+        "params": {
+            "tx": { "content": tx, "encoding": "hex" },
+            "witnesses": [],
+        },
+        "id": 1,
+    });
+
+    let mut request = client.post(endpoint).json(&body);
+    for (name, value) in headers.iter() {
+        request = request.header(name.as_str(), value.as_str());
+    }
+
+    let response: Value = request
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(|err| {
+            McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Error submitting transaction: {}", err),
+                None,
+            )
+        })?
+        .json()
+        .await
+        .map_err(|err| {
+            McpError::new(
+                ErrorCode::INTERNAL_ERROR,
+                format!("Invalid response from TRP submit: {}", err),
+                None,
+            )
+        })?;
+
+    if let Some(error) = response.get("error") {
+        let message = error.get("message").and_then(Value::as_str).unwrap_or("unknown error");
+        return Err(McpError::new(
+            ErrorCode::INTERNAL_ERROR,
+            format!("Transaction rejected: {}", message),
+            Some(error.clone()),
+        ));
+    }
+
+    Ok(response.get("result").cloned().unwrap_or(Value::Null))
+}
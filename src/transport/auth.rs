@@ -0,0 +1,117 @@
+use std::sync::Arc;
+use axum::Router;
+use axum::extract::{Request, State};
+use axum::http::{StatusCode, header};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Response};
+use subtle::ConstantTimeEq;
+
+use crate::tools::config;
+
+/// Reads the bearer tokens accepted by the server from `AUTH_TOKENS_FILE`, one per line,
+/// or from the comma-separated `AUTH_TOKENS`. Tokens are never logged.
+pub fn tokens_from_env() -> anyhow::Result<Vec<String>> {
+    let Some(tokens) = config::secret("AUTH_TOKENS")? else {
+        return Ok(Vec::new());
+    };
+    Ok(tokens.get()
+        .split([',', '\n'])
+        .map(str::trim)
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect())
+}
+
+pub fn bearer_token(request: &Request) -> Option<&str> {
+    request.headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "))
+}
+
+/// Index of the token matching `token`. Every token is compared, in constant time, so
+/// response times don't reveal how much of a guess was right.
+pub fn find_token<'a>(tokens: impl Iterator<Item = &'a str>, token: &str) -> Option<usize> {
+    let mut found = None;
+    for (index, valid) in tokens.enumerate() {
+        if bool::from(valid.as_bytes().ct_eq(token.as_bytes())) {
+            found = Some(index);
+        }
+    }
+    found
+}
+
+pub fn unauthorized() -> Response {
+    (StatusCode::UNAUTHORIZED, "Missing or unknown bearer token").into_response()
+}
+
+/// Rejects requests without one of the accepted bearer tokens before they reach the
+/// MCP transport, so no session is opened for them.
+async fn require_token(State(tokens): State<Arc<Vec<String>>>, request: Request, next: Next) -> Response {
+    let authorized = bearer_token(&request)
+        .and_then(|token| find_token(tokens.iter().map(String::as_str), token))
+        .is_some();
+    if !authorized {
+        return unauthorized();
+    }
+    next.run(request).await
+}
+
+/// Wraps `router` in the bearer token check. Without tokens it's returned as is.
+pub fn require_tokens(router: Router, tokens: Vec<String>) -> Router {
+    if tokens.is_empty() {
+        return router;
+    }
+    tracing::info!("Requiring one of {} bearer tokens", tokens.len());
+    router.layer(middleware::from_fn_with_state(Arc::new(tokens), require_token))
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::routing::get;
+    use tower::ServiceExt;
+    use super::*;
+
+    fn protected() -> Router {
+        let router = Router::new().route("/mcp", get(|| async { "ok" }));
+        require_tokens(router, vec!["first".to_string(), "second".to_string()])
+    }
+
+    async fn status(router: Router, authorization: Option<&str>) -> StatusCode {
+        let mut request = Request::builder().uri("/mcp");
+        if let Some(authorization) = authorization {
+            request = request.header(header::AUTHORIZATION, authorization);
+        }
+        router.oneshot(request.body(Body::empty()).unwrap()).await.unwrap().status()
+    }
+
+    #[test]
+    fn find_token_returns_the_matching_index() {
+        let tokens = ["first", "second", "third"];
+        assert_eq!(find_token(tokens.into_iter(), "second"), Some(1));
+        assert_eq!(find_token(tokens.into_iter(), "secon"), None);
+        assert_eq!(find_token(tokens.into_iter(), "second!"), None);
+        assert_eq!(find_token(std::iter::empty(), "second"), None);
+    }
+
+    #[tokio::test]
+    async fn known_token_is_let_through() {
+        assert_eq!(status(protected(), Some("Bearer first")).await, StatusCode::OK);
+        assert_eq!(status(protected(), Some("Bearer second")).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn missing_or_unknown_token_is_rejected() {
+        assert_eq!(status(protected(), None).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(protected(), Some("Bearer third")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(protected(), Some("first")).await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(protected(), Some("Basic first")).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn no_tokens_leaves_the_router_open() {
+        let router = require_tokens(Router::new().route("/mcp", get(|| async { "ok" })), Vec::new());
+        assert_eq!(status(router, None).await, StatusCode::OK);
+    }
+}
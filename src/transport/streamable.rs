@@ -0,0 +1,437 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::Duration;
+use axum::Router;
+use axum::body::Bytes;
+use axum::extract::State;
+use axum::http::{HeaderMap, HeaderValue, StatusCode};
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::response::{IntoResponse, Response};
+use axum::routing::post;
+use futures::channel::{mpsc, oneshot};
+use futures::{SinkExt, StreamExt};
+use rmcp::{RoleServer, ServiceExt};
+use rmcp::service::{RxJsonRpcMessage, TxJsonRpcMessage};
+use serde_json::Value;
+use tokio_util::sync::CancellationToken;
+
+use crate::tools::protocol::ProtocolTool;
+
+/// Header carrying the session id assigned on initialize.
+pub const SESSION_HEADER: &str = "mcp-session-id";
+
+/// Messages buffered per channel between the HTTP handlers and a session's service.
+const CHANNEL_BUFFER: usize = 32;
+
+/// How often sessions are checked for idleness.
+const IDLE_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A session of the streamable HTTP transport: a `ProtocolTool` service fed through
+/// channels. Responses are handed to the POST waiting for them, everything else the
+/// server sends goes to the session's GET stream, if one is open.
+struct Session {
+    to_service: mpsc::Sender<RxJsonRpcMessage<RoleServer>>,
+    pending: Mutex<HashMap<String, oneshot::Sender<Value>>>,
+    stream: Mutex<Option<mpsc::Sender<Value>>>,
+    /// The session's handler, whose last request decides when the session is idle.
+    tool: ProtocolTool,
+    /// Stops the session's service.
+    ct: CancellationToken,
+}
+
+impl Session {
+    /// Registers the request ids of a POST so their responses are handed back to it.
+    /// Fails with the first id the session is already waiting on, or that the POST
+    /// repeats, as the responses couldn't be told apart.
+    fn expect(&self, ids: &[String]) -> Result<Vec<oneshot::Receiver<Value>>, String> {
+        let mut pending = self.pending.lock().unwrap();
+        let mut seen = HashSet::new();
+        if let Some(id) = ids.iter().find(|id| pending.contains_key(*id) || !seen.insert(*id)) {
+            return Err(id.clone());
+        }
+
+        Ok(ids.iter()
+            .map(|id| {
+                let (sender, receiver) = oneshot::channel();
+                pending.insert(id.clone(), sender);
+                receiver
+            })
+            .collect())
+    }
+
+    fn forget(&self, ids: &[String]) {
+        let mut pending = self.pending.lock().unwrap();
+        for id in ids {
+            pending.remove(id);
+        }
+    }
+
+    fn deliver(&self, message: TxJsonRpcMessage<RoleServer>) {
+        let message = match serde_json::to_value(&message) {
+            Ok(message) => message,
+            Err(err) => {
+                tracing::warn!("Dropping a message that failed to serialize: {}", err);
+                return;
+            }
+        };
+
+        if message.get("method").is_none() {
+            if let Some(id) = message.get("id") {
+                if let Some(waiting) = self.pending.lock().unwrap().remove(&id.to_string()) {
+                    let _ = waiting.send(message);
+                    return;
+                }
+            }
+        }
+
+        let mut stream = self.stream.lock().unwrap();
+        if let Some(sender) = stream.as_mut() {
+            if let Err(err) = sender.try_send(message) {
+                if err.is_disconnected() {
+                    *stream = None;
+                } else {
+                    tracing::debug!("Dropping a notification, the client isn't reading its stream");
+                }
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Sessions {
+    tool: ProtocolTool,
+    sessions: Arc<Mutex<HashMap<String, Arc<Session>>>>,
+    ct: CancellationToken,
+}
+
+impl Sessions {
+    /// Starts the service of a new session. It runs until the session is deleted, goes
+    /// idle or the client never completes the handshake, and the handler's session is
+    /// closed once it stops.
+    fn open(&self) -> (String, Arc<Session>) {
+        let id = format!("{:032x}", rand::random::<u128>());
+        let (to_service, service_input) = mpsc::channel(CHANNEL_BUFFER);
+        let (service_output, mut from_service) = mpsc::channel::<TxJsonRpcMessage<RoleServer>>(CHANNEL_BUFFER);
+
+        let tool = self.tool.for_session();
+        let ct = self.ct.child_token();
+        let session = Arc::new(Session {
+            to_service,
+            pending: Mutex::default(),
+            stream: Mutex::default(),
+            tool: tool.clone(),
+            ct: ct.clone(),
+        });
+        self.sessions.lock().unwrap().insert(id.clone(), session.clone());
+
+        // Weak, so a deleted session's input closes and its service stops.
+        let dispatch: Weak<Session> = Arc::downgrade(&session);
+        tokio::spawn(async move {
+            while let Some(message) = from_service.next().await {
+                let Some(session) = dispatch.upgrade() else {
+                    break;
+                };
+                session.deliver(message);
+            }
+        });
+
+        let sessions = self.sessions.clone();
+        let session_id = id.clone();
+        tokio::spawn(async move {
+            match tool.clone().serve_with_ct((service_output, service_input), ct).await {
+                Ok(service) => {
+                    if let Err(err) = service.waiting().await {
+                        tracing::debug!("Session {} ended with an error: {}", session_id, err);
+                    }
+                }
+                Err(err) => tracing::warn!("Session {} failed to initialize: {}", session_id, err),
+            }
+            tool.close_session();
+            sessions.lock().unwrap().remove(&session_id);
+        });
+
+        tracing::info!("Opened streamable HTTP session {}", id);
+        (id, session)
+    }
+
+    fn get(&self, headers: &HeaderMap) -> Result<Arc<Session>, Response> {
+        let Some(id) = headers.get(SESSION_HEADER).and_then(|value| value.to_str().ok()) else {
+            return Err((StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header").into_response());
+        };
+        self.sessions.lock().unwrap().get(id).cloned()
+            .ok_or_else(|| (StatusCode::NOT_FOUND, "Unknown or expired session").into_response())
+    }
+
+    /// Forgets the session and stops its service.
+    fn close(&self, id: &str) -> bool {
+        let Some(session) = self.sessions.lock().unwrap().remove(id) else {
+            return false;
+        };
+        session.ct.cancel();
+        true
+    }
+
+    /// Closes sessions without a request for `timeout`, as clients that go away without
+    /// a DELETE would otherwise keep them forever.
+    fn spawn_idle_sweep(&self, timeout: Duration) {
+        let state = self.clone();
+        tokio::spawn(async move {
+            loop {
+                tokio::select! {
+                    _ = state.ct.cancelled() => break,
+                    _ = tokio::time::sleep(IDLE_CHECK_INTERVAL.min(timeout)) => {}
+                }
+
+                let idle: Vec<String> = state.sessions.lock().unwrap().iter()
+                    .filter(|(_, session)| session.tool.idle_for() >= timeout)
+                    .map(|(id, _)| id.clone())
+                    .collect();
+                for id in idle {
+                    tracing::info!("Closing streamable HTTP session {} idle for over {:?}", id, timeout);
+                    state.close(&id);
+                }
+            }
+        });
+    }
+}
+
+/// Serves MCP over the streamable HTTP transport on `path`: POST carries client messages
+/// and returns the responses as JSON, GET opens a stream of server notifications and
+/// DELETE ends the session. Sessions without a request for `idle_timeout` are closed,
+/// and all of them once `ct` is cancelled.
+pub fn router(tool: ProtocolTool, path: &str, idle_timeout: Option<Duration>, ct: CancellationToken) -> Router {
+    let state = Sessions { tool, sessions: Arc::default(), ct: ct.clone() };
+    if let Some(timeout) = idle_timeout {
+        state.spawn_idle_sweep(timeout);
+    }
+
+    let sessions = state.sessions.clone();
+    tokio::spawn(async move {
+        ct.cancelled().await;
+        sessions.lock().unwrap().clear();
+    });
+
+    Router::new()
+        .route(path, post(handle_post).get(handle_get).delete(handle_delete))
+        .with_state(state)
+}
+
+async fn handle_post(State(state): State<Sessions>, headers: HeaderMap, body: Bytes) -> Response {
+    let body: Value = match serde_json::from_slice(&body) {
+        Ok(body) => body,
+        Err(err) => return (StatusCode::BAD_REQUEST, format!("Invalid JSON-RPC message: {}", err)).into_response(),
+    };
+    let (messages, batch) = match body {
+        Value::Array(messages) => (messages, true),
+        message => (vec![message], false),
+    };
+
+    let initialize = messages.iter().any(|message| message.get("method").and_then(Value::as_str) == Some("initialize"));
+
+    // Requests carry a method and an id; their responses are awaited below.
+    let mut parsed = Vec::with_capacity(messages.len());
+    for message in messages {
+        let id = message.get("method").and(message.get("id")).map(Value::to_string);
+        match serde_json::from_value::<RxJsonRpcMessage<RoleServer>>(message) {
+            Ok(message) => parsed.push((id, message)),
+            Err(err) => return (StatusCode::BAD_REQUEST, format!("Invalid JSON-RPC message: {}", err)).into_response(),
+        }
+    }
+
+    let (session_id, session) = if initialize {
+        let (id, session) = state.open();
+        (Some(id), session)
+    } else {
+        match state.get(&headers) {
+            Ok(session) => (None, session),
+            Err(response) => return response,
+        }
+    };
+
+    let ids: Vec<String> = parsed.iter().filter_map(|(id, _)| id.clone()).collect();
+    let waiting = match session.expect(&ids) {
+        Ok(waiting) => waiting,
+        Err(id) => {
+            if let Some(session_id) = &session_id {
+                state.close(session_id);
+            }
+            return (StatusCode::CONFLICT, format!("Request id {} is already in flight in this session", id)).into_response();
+        }
+    };
+
+    let mut to_service = session.to_service.clone();
+    for (_, message) in parsed {
+        if to_service.send(message).await.is_err() {
+            session.forget(&ids);
+            return (StatusCode::NOT_FOUND, "Session is closed").into_response();
+        }
+    }
+
+    if waiting.is_empty() {
+        return StatusCode::ACCEPTED.into_response();
+    }
+
+    let mut responses = Vec::with_capacity(waiting.len());
+    for receiver in waiting {
+        match receiver.await {
+            Ok(response) => responses.push(response),
+            Err(_) => return (StatusCode::INTERNAL_SERVER_ERROR, "Session closed before answering").into_response(),
+        }
+    }
+
+    let body = if batch { Value::Array(responses) } else { responses.remove(0) };
+    let mut response = axum::Json(body).into_response();
+    if let Some(id) = session_id.and_then(|id| HeaderValue::from_str(&id).ok()) {
+        response.headers_mut().insert(SESSION_HEADER, id);
+    }
+    response
+}
+
+async fn handle_get(State(state): State<Sessions>, headers: HeaderMap) -> Response {
+    let session = match state.get(&headers) {
+        Ok(session) => session,
+        Err(response) => return response,
+    };
+
+    // A new stream replaces the previous one, which stops receiving messages.
+    let (sender, receiver) = mpsc::channel(CHANNEL_BUFFER);
+    *session.stream.lock().unwrap() = Some(sender);
+
+    let events = receiver.map(|message| Event::default().event("message").json_data(message));
+    Sse::new(events).keep_alive(KeepAlive::default()).into_response()
+}
+
+async fn handle_delete(State(state): State<Sessions>, headers: HeaderMap) -> Response {
+    let Some(id) = headers.get(SESSION_HEADER).and_then(|value| value.to_str().ok()) else {
+        return (StatusCode::BAD_REQUEST, "Missing Mcp-Session-Id header").into_response();
+    };
+    if !state.close(id) {
+        return (StatusCode::NOT_FOUND, "Unknown or expired session").into_response();
+    }
+    tracing::info!("Closed streamable HTTP session {}", id);
+    StatusCode::NO_CONTENT.into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::http::Method;
+    use serde_json::json;
+    use tower::ServiceExt;
+    use crate::tools::protocol::Warmup;
+    use crate::tools::trp::TrpEndpoint;
+    use super::*;
+
+    fn test_tool() -> ProtocolTool {
+        ProtocolTool::new("file:///nonexistent/registry.json", TrpEndpoint::new("mock://")).with_warmup(Warmup::Lazy)
+    }
+
+    async fn send(router: &Router, method: Method, session: Option<&str>, body: Option<Value>) -> Response {
+        let mut request = Request::builder()
+            .method(method)
+            .uri("/mcp")
+            .header("content-type", "application/json")
+            .header("accept", "application/json, text/event-stream");
+        if let Some(session) = session {
+            request = request.header(SESSION_HEADER, session);
+        }
+        let body = body.map_or_else(Body::empty, |body| Body::from(body.to_string()));
+        router.clone().oneshot(request.body(body).unwrap()).await.unwrap()
+    }
+
+    async fn json_body(response: Response) -> Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    /// Opens a session and completes the handshake, returning the session id.
+    async fn initialize(router: &Router) -> String {
+        let initialize = json!({
+            "jsonrpc": "2.0",
+            "id": 1,
+            "method": "initialize",
+            "params": {
+                "protocolVersion": "2025-03-26",
+                "capabilities": {},
+                "clientInfo": { "name": "test", "version": "0.0.0" },
+            },
+        });
+        let response = send(router, Method::POST, None, Some(initialize)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let session = response.headers()[SESSION_HEADER].to_str().unwrap().to_string();
+        assert_eq!(json_body(response).await["result"]["protocolVersion"], "2025-03-26");
+
+        let initialized = json!({ "jsonrpc": "2.0", "method": "notifications/initialized" });
+        let response = send(router, Method::POST, Some(&session), Some(initialized)).await;
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        session
+    }
+
+    fn list_tools(id: u64) -> Value {
+        json!({ "jsonrpc": "2.0", "id": id, "method": "tools/list", "params": {} })
+    }
+
+    async fn wait_for_sessions(tool: &ProtocolTool, expected: usize) {
+        for _ in 0..100 {
+            if tool.active_sessions() == expected {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        }
+        panic!("expected {} active sessions, found {}", expected, tool.active_sessions());
+    }
+
+    #[tokio::test]
+    async fn initialize_list_tools_and_delete() {
+        let tool = test_tool();
+        let router = router(tool.clone(), "/mcp", None, CancellationToken::new());
+        let session = initialize(&router).await;
+
+        let response = send(&router, Method::POST, Some(&session), Some(list_tools(2))).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let listed = json_body(response).await;
+        assert_eq!(listed["id"], 2);
+        assert!(!listed["result"]["tools"].as_array().unwrap().is_empty());
+        wait_for_sessions(&tool, 1).await;
+
+        let response = send(&router, Method::DELETE, Some(&session), None).await;
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+        wait_for_sessions(&tool, 0).await;
+
+        let response = send(&router, Method::POST, Some(&session), Some(list_tools(3))).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn idle_sessions_are_closed() {
+        let tool = test_tool();
+        let router = router(tool.clone(), "/mcp", Some(Duration::from_millis(100)), CancellationToken::new());
+        let session = initialize(&router).await;
+        wait_for_sessions(&tool, 1).await;
+
+        wait_for_sessions(&tool, 0).await;
+        let response = send(&router, Method::POST, Some(&session), Some(list_tools(2))).await;
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn request_ids_in_flight_are_not_reused() {
+        let session = Session {
+            to_service: mpsc::channel(1).0,
+            pending: Mutex::default(),
+            stream: Mutex::default(),
+            tool: test_tool(),
+            ct: CancellationToken::new(),
+        };
+        let ids = |ids: &[&str]| ids.iter().map(|id| id.to_string()).collect::<Vec<_>>();
+
+        let _waiting = session.expect(&ids(&["1", "2"])).unwrap();
+        assert_eq!(session.expect(&ids(&["3", "1"])).err(), Some("1".to_string()));
+        assert_eq!(session.expect(&ids(&["4", "4"])).err(), Some("4".to_string()));
+        assert!(session.pending.lock().unwrap().get("3").is_none(), "a rejected POST registers nothing");
+
+        session.forget(&ids(&["1"]));
+        assert!(session.expect(&ids(&["1"])).is_ok());
+    }
+}
@@ -0,0 +1,143 @@
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
+use anyhow::{Context, Result};
+use axum::Router;
+use hyper_util::rt::{TokioExecutor, TokioIo};
+use hyper_util::server::conn::auto;
+use hyper_util::service::TowerToHyperService;
+use rustls::ServerConfig;
+use rustls_pki_types::{CertificateDer, PrivateKeyDer};
+use rustls_pki_types::pem::PemObject;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use tokio_rustls::TlsAcceptor;
+use tokio_util::sync::CancellationToken;
+
+/// A TLS acceptor reloaded when its certificate or key file changes. A pair that fails to
+/// load keeps the previous one in use.
+#[derive(Clone)]
+pub struct ReloadingAcceptor {
+    cert_path: PathBuf,
+    key_path: PathBuf,
+    acceptor: Arc<RwLock<TlsAcceptor>>,
+    modified: Arc<Mutex<(Option<SystemTime>, Option<SystemTime>)>>,
+}
+
+impl ReloadingAcceptor {
+    /// Loads the PEM certificate chain and private key, failing when either doesn't parse
+    /// or the key doesn't belong to the certificate.
+    pub fn new(cert_path: &Path, key_path: &Path) -> Result<Self> {
+        let acceptor = load(cert_path, key_path)?;
+        Ok(Self {
+            cert_path: cert_path.to_path_buf(),
+            key_path: key_path.to_path_buf(),
+            acceptor: Arc::new(RwLock::new(acceptor)),
+            modified: Arc::new(Mutex::new(modified(cert_path, key_path))),
+        })
+    }
+
+    fn current(&self) -> TlsAcceptor {
+        self.acceptor.read().unwrap().clone()
+    }
+
+    fn reload_if_modified(&self) {
+        let latest = modified(&self.cert_path, &self.key_path);
+        {
+            let mut seen = self.modified.lock().unwrap();
+            if *seen == latest {
+                return;
+            }
+            *seen = latest;
+        }
+
+        match load(&self.cert_path, &self.key_path) {
+            Ok(acceptor) => {
+                *self.acceptor.write().unwrap() = acceptor;
+                tracing::info!("Reloaded TLS certificate from {}", self.cert_path.display());
+            }
+            Err(err) => tracing::warn!("Keeping the previous TLS certificate: {:#}", err),
+        }
+    }
+
+    /// Checks the certificate and key files for changes every `interval` until `ct` is
+    /// cancelled. New connections use the reloaded pair, open ones keep theirs.
+    pub fn spawn_reload_task(&self, interval: Duration, ct: CancellationToken) -> JoinHandle<()> {
+        let acceptor = self.clone();
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.tick().await;
+            loop {
+                tokio::select! {
+                    _ = ct.cancelled() => break,
+                    _ = ticker.tick() => acceptor.reload_if_modified(),
+                }
+            }
+        })
+    }
+}
+
+fn modified(cert_path: &Path, key_path: &Path) -> (Option<SystemTime>, Option<SystemTime>) {
+    let modified = |path: &Path| std::fs::metadata(path).and_then(|metadata| metadata.modified()).ok();
+    (modified(cert_path), modified(key_path))
+}
+
+fn load(cert_path: &Path, key_path: &Path) -> Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(cert_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .with_context(|| format!("Failed to read TLS certificate {}", cert_path.display()))?;
+    if certs.is_empty() {
+        anyhow::bail!("TLS certificate {} holds no PEM certificate", cert_path.display());
+    }
+    let key = PrivateKeyDer::from_pem_file(key_path)
+        .with_context(|| format!("Failed to read TLS private key {}", key_path.display()))?;
+
+    let config = ServerConfig::builder_with_provider(Arc::new(rustls::crypto::ring::default_provider()))
+        .with_safe_default_protocol_versions()?
+        .with_no_client_auth()
+        .with_single_cert(certs, key)
+        .with_context(|| format!(
+            "TLS private key {} doesn't match certificate {}",
+            key_path.display(),
+            cert_path.display(),
+        ))?;
+
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// Serves `router` over TLS until `ct` is cancelled. Handshake failures only drop the
+/// connection they happened on.
+pub fn serve(listener: TcpListener, router: Router, acceptor: ReloadingAcceptor, ct: CancellationToken) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        loop {
+            let (stream, peer) = tokio::select! {
+                _ = ct.cancelled() => break,
+                accepted = listener.accept() => match accepted {
+                    Ok(accepted) => accepted,
+                    Err(err) => {
+                        tracing::warn!("Failed to accept connection: {}", err);
+                        continue;
+                    }
+                },
+            };
+
+            let tls = acceptor.current();
+            let service = TowerToHyperService::new(router.clone());
+            tokio::spawn(async move {
+                let stream = match tls.accept(stream).await {
+                    Ok(stream) => stream,
+                    Err(err) => {
+                        tracing::debug!("TLS handshake with {} failed: {}", peer, err);
+                        return;
+                    }
+                };
+                if let Err(err) = auto::Builder::new(TokioExecutor::new())
+                    .serve_connection_with_upgrades(TokioIo::new(stream), service)
+                    .await
+                {
+                    tracing::debug!("Connection from {} ended with an error: {}", peer, err);
+                }
+            });
+        }
+    })
+}
@@ -0,0 +1,104 @@
+//! Spawns the stdio binary against a registry fixture and the mock TRP, and talks to it
+//! over its pipes the way an MCP client would.
+
+use std::io::{BufRead, BufReader, Write};
+use std::process::{Child, ChildStdin, Command, Stdio};
+use std::sync::mpsc::{self, Receiver};
+use std::time::Duration;
+use serde_json::{Value, json};
+
+/// How long to wait for each response before giving up on the server.
+const RESPONSE_TIMEOUT: Duration = Duration::from_secs(30);
+
+struct Server {
+    child: Child,
+    stdin: ChildStdin,
+    lines: Receiver<String>,
+}
+
+impl Server {
+    fn spawn() -> Self {
+        let registry = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/registry.json");
+        let mut child = Command::new(env!("CARGO_BIN_EXE_stdio"))
+            // Away from the checkout, so a developer's .env isn't picked up.
+            .current_dir(std::env::temp_dir())
+            .env("TX3_REGISTRY_URL", format!("file://{}", registry))
+            .env("TRP_URL", "mock://")
+            .env("DISCOVER_ROOTS", "false")
+            .env_remove("TRP_KEY")
+            .env_remove("TX3_PROTOCOL_FILE")
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .expect("stdio binary should start");
+
+        let stdin = child.stdin.take().unwrap();
+        let stdout = child.stdout.take().unwrap();
+        let (sender, lines) = mpsc::channel();
+        std::thread::spawn(move || {
+            for line in BufReader::new(stdout).lines() {
+                let Ok(line) = line else { break };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        Self { child, stdin, lines }
+    }
+
+    fn send(&mut self, message: Value) {
+        writeln!(self.stdin, "{}", message).unwrap();
+        self.stdin.flush().unwrap();
+    }
+
+    /// The response to request `id`, skipping notifications sent meanwhile.
+    fn response(&self, id: u64) -> Value {
+        loop {
+            let line = self.lines.recv_timeout(RESPONSE_TIMEOUT).expect("server should answer");
+            let message: Value = serde_json::from_str(&line).expect("server should only write JSON-RPC");
+            if message["id"] == json!(id) {
+                return message;
+            }
+        }
+    }
+}
+
+impl Drop for Server {
+    fn drop(&mut self) {
+        let _ = self.child.kill();
+        let _ = self.child.wait();
+    }
+}
+
+#[test]
+fn initialize_and_list_tools() {
+    let mut server = Server::spawn();
+
+    server.send(json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "initialize",
+        "params": {
+            "protocolVersion": "2025-03-26",
+            "capabilities": {},
+            "clientInfo": { "name": "stdio-test", "version": "0.0.0" },
+        },
+    }));
+    let initialized = server.response(1);
+    assert_eq!(initialized["result"]["protocolVersion"], "2025-03-26");
+    assert!(initialized["result"]["capabilities"]["tools"].is_object());
+
+    server.send(json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }));
+    server.send(json!({ "jsonrpc": "2.0", "id": 2, "method": "tools/list", "params": {} }));
+
+    let listed = server.response(2);
+    let tools = listed["result"]["tools"].as_array().expect("tools/list should return tools");
+    let names: Vec<&str> = tools.iter().filter_map(|tool| tool["name"].as_str()).collect();
+    assert!(names.contains(&"resolve-acme_transfer-transfer"), "missing resolve tool in {:?}", names);
+    assert!(names.contains(&"describe-acme_transfer-transfer"), "missing describe tool in {:?}", names);
+
+    let resolve = tools.iter().find(|tool| tool["name"] == "resolve-acme_transfer-transfer").unwrap();
+    assert!(resolve["inputSchema"]["properties"]["quantity"].is_object());
+}